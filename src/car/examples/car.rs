@@ -3,17 +3,27 @@ use bevy::{pbr::ExtendedMaterial, prelude::*};
 // Use the main menu and preferences plugin
 use car::preferences::{CarPreferences, PreferencesPlugin};
 
-use bevy_integrator::{GameState, SimTime, Solver};
+use bevy_integrator::{
+    realtime::{OverrunPolicy, PhysicsDiagnosticsPlugin},
+    sim_control::SimControlPlugin,
+    snapshot::SnapshotPlugin,
+    GameState, SimTime, Solver,
+};
 
 use car::{
     build::{build_car, car_startup_system, update_engine_audio, update_engine_speed, CarList},
     control::ControlType,
     egui_main_menu::EguiMainMenuPlugin,
     environment::build_environment,
+    linearize::LinearizationPlugin,
     setup::{camera_setup, simulation_setup},
 };
 use grid_terrain::{examples::TerrainPreferences, MyExtension};
-use rigid_body::plugin::{CarState, RigidBodyPlugin};
+use rigid_body::{
+    health::{HealthMonitor, HealthMonitorPlugin},
+    joint::Joint,
+    plugin::{CarState, RigidBodyPlugin},
+};
 
 // Main function
 fn main() {
@@ -39,6 +49,20 @@ fn main() {
             },
             MaterialPlugin::<ExtendedMaterial<StandardMaterial, MyExtension>>::default(),
         ))
+        // keep the last 10 seconds, R rewinds 2 seconds
+        .add_plugins(SnapshotPlugin::<Joint>::new(10., 10, KeyCode::R, 2.))
+        // P pauses, N single steps, [ and ] change the time scale
+        .add_plugins(SimControlPlugin::default())
+        // on a slow machine, slow down the simulation instead of spiralling with catch-up steps
+        .add_plugins(PhysicsDiagnosticsPlugin::new(OverrunPolicy::DropTime {
+            max_frame_cost: 0.02,
+        }))
+        // roll back (or respawn) a car when its physics blows up
+        .add_plugins(HealthMonitorPlugin {
+            monitor: HealthMonitor::default(),
+        })
+        // L writes the A and B matrices about the current state to linearization.json
+        .add_plugins(LinearizationPlugin::default())
         .add_plugins(EguiMainMenuPlugin)
         .insert_resource(Msaa::Off)
         .add_plugins(GameSetupPlugin)
@@ -0,0 +1,87 @@
+use std::time::Instant;
+
+use bevy::prelude::*;
+
+use bevy_integrator::{implicit::ImplicitStep, step, SimTime, Solver};
+use car::{
+    batch::{chassis_pose, headless_car_setup},
+    build::build_car,
+    control::ControlType,
+    preferences::CarPreferences,
+};
+use grid_terrain::examples::TerrainPreferences;
+use rigid_body::joint::Joint;
+
+// Drive a car with unfiltered tires (filter_time 0) of realistic stiffness at a 2 ms time step, with
+// the implicit solvers and, for comparison, RK4.
+fn main() {
+    let duration = 10.;
+    let dt = 0.002;
+    for (name, solver) in [
+        ("backward Euler", Solver::BackwardEuler),
+        ("Rosenbrock2", Solver::Rosenbrock2),
+        ("RK4", Solver::RK4),
+    ] {
+        let mut app = App::new();
+        let car_preferences = CarPreferences::default();
+        let mut car = build_car(
+            [0., 0., 0.],
+            ControlType::WASD,
+            0,
+            car_preferences.max_speed,
+            car_preferences.mass,
+            car_preferences.max_torque,
+            car_preferences.friction_coefficient,
+        )
+        .with_implicit_tires();
+        car.carcontrol.throttle = 0.5;
+        car.carcontrol.steering = 0.2;
+
+        headless_car_setup(
+            &mut app,
+            SimTime::new(dt, 0., Some(duration)),
+            solver,
+            vec![car],
+            car_preferences,
+            TerrainPreferences {
+                grid_size: 400.,
+                subdivisions: 128.,
+                seed: 1,
+            },
+        );
+        app.insert_resource(ImplicitStep::default());
+        app.update();
+
+        let start = Instant::now();
+        let mut iterations = 0;
+        let steps = (duration / dt).round() as usize;
+        for _ in 0..steps {
+            step::<Joint>(&mut app.world, 1);
+            iterations += app.world.resource::<ImplicitStep>().iterations;
+        }
+        let wall_time = start.elapsed().as_secs_f64();
+
+        let (position, angles) = chassis_pose(&mut app.world);
+        let implicit = app.world.resource::<ImplicitStep>();
+        println!(
+            "{}: {:.2} s wall time, {:.1}x real time, final position [{:.2}, {:.2}, {:.2}], roll {:.3}, pitch {:.3}",
+            name,
+            wall_time,
+            duration / wall_time,
+            position[0],
+            position[1],
+            position[2],
+            angles[0],
+            angles[1],
+        );
+        if matches!(solver, Solver::BackwardEuler | Solver::Rosenbrock2) {
+            println!(
+                "  {} Jacobians for {} steps, {:.2} Newton iterations per step, {} singular solves",
+                implicit.jacobian_updates,
+                steps,
+                iterations as f64 / steps as f64,
+                implicit.singular_solves,
+            );
+        }
+    }
+}
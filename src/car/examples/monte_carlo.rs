@@ -0,0 +1,102 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use bevy_integrator::{
+    batch::{BatchRunner, OutputKind},
+    SimTime, Solver,
+};
+use car::{
+    batch::{chassis_pose, chassis_up, headless_car_setup},
+    build::build_car,
+    control::ControlType,
+    preferences::CarPreferences,
+};
+use grid_terrain::examples::TerrainPreferences;
+use rigid_body::joint::Joint;
+
+// Drive a car with randomized friction, mass, terrain and start position over a perlin terrain,
+// and collect where it ended up and how far it rolled.
+fn main() {
+    let parameters = [
+        "friction_coefficient",
+        "mass",
+        "terrain_seed",
+        "start_x",
+        "start_y",
+        "steering",
+    ];
+
+    let runner = BatchRunner::new(
+        100,
+        1234,
+        &parameters,
+        |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            vec![
+                rng.gen_range(0.6..1.0),
+                rng.gen_range(800.0..1400.0),
+                rng.gen::<u32>() as f64,
+                rng.gen_range(-2.0..2.0),
+                rng.gen_range(-2.0..2.0),
+                rng.gen_range(-0.5..0.5),
+            ]
+        },
+        |app, parameters| {
+            let car_preferences = CarPreferences {
+                friction_coefficient: parameters[0],
+                mass: parameters[1],
+                ..Default::default()
+            };
+            let mut car = build_car(
+                [parameters[3], parameters[4], 0.],
+                ControlType::WASD,
+                0,
+                car_preferences.max_speed,
+                car_preferences.mass,
+                car_preferences.max_torque,
+                car_preferences.friction_coefficient,
+            );
+            car.carcontrol.throttle = 0.5;
+            car.carcontrol.steering = parameters[5] as f32;
+
+            headless_car_setup(
+                app,
+                SimTime::new(0.002, 0., Some(10.)),
+                Solver::RK4,
+                vec![car],
+                car_preferences,
+                TerrainPreferences {
+                    grid_size: 400.,
+                    subdivisions: 128.,
+                    seed: parameters[2] as u32,
+                },
+            );
+        },
+    )
+    .with_output("final_x", OutputKind::Final, |world| {
+        chassis_pose(world).0[0]
+    })
+    .with_output("final_y", OutputKind::Final, |world| {
+        chassis_pose(world).0[1]
+    })
+    .with_output("max_roll", OutputKind::Max, |world| {
+        chassis_pose(world).1[0].abs()
+    })
+    // upside down, rolled over or flipped nose over tail
+    .with_output("flipped", OutputKind::Max, |world| {
+        (chassis_up(world)[2] < 0.) as u8 as f64
+    });
+
+    let results = runner.run::<Joint>();
+    if let Err(error) = results.write_csv("monte_carlo.csv") {
+        println!("Failed to write monte_carlo.csv: {}", error);
+    }
+
+    for output in ["final_x", "final_y", "max_roll", "flipped"] {
+        if let Some(statistics) = results.statistics(output) {
+            println!(
+                "{}: mean {:.4}, std {:.4}, min {:.4}, max {:.4}",
+                output, statistics.mean, statistics.std, statistics.min, statistics.max
+            );
+        }
+    }
+}
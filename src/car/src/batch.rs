@@ -0,0 +1,81 @@
+use bevy::prelude::*;
+use bevy_integrator::{SimTime, Solver};
+use grid_terrain::{
+    examples::{perlin_plane, TerrainPreferences},
+    GridTerrain,
+};
+use rigid_body::{
+    joint::{Floating, Joint},
+    plugin::{CarState, HeadlessRigidBodyPlugin},
+    sva::Vector,
+};
+
+use crate::{
+    build::{car_startup_system, CarDefinition, CarList},
+    preferences::CarPreferences,
+    setup::simulation_setup,
+};
+
+// Set up a headless car simulation, e.g. for a BatchRunner sample: the cars on a perlin terrain
+// without rendering, audio or input. The physics is advanced with bevy_integrator::step.
+pub fn headless_car_setup(
+    app: &mut App,
+    time: SimTime,
+    solver: Solver,
+    cars: Vec<CarDefinition>,
+    car_preferences: CarPreferences,
+    terrain_preferences: TerrainPreferences,
+) {
+    app.add_plugins(HeadlessRigidBodyPlugin {
+        time,
+        solver,
+        simulation_setup: vec![simulation_setup],
+    })
+    .add_state::<CarState>()
+    .insert_resource(CarList { cars })
+    .insert_resource(car_preferences)
+    .insert_resource(terrain_preferences)
+    .add_systems(Startup, (car_startup_system, headless_terrain_system));
+}
+
+// the terrain for the tire contacts, without meshes
+fn headless_terrain_system(
+    mut commands: Commands,
+    terrain_preferences: ResMut<TerrainPreferences>,
+) {
+    let size = terrain_preferences.grid_size;
+    let elements = perlin_plane(terrain_preferences);
+    commands.insert_resource(GridTerrain::new(elements, [size, size]));
+}
+
+// current joint coordinate of the first joint with this name, e.g. a suspension travel
+pub fn joint_position(world: &mut World, name: &str) -> f64 {
+    let mut query = world.query::<&Joint>();
+    query
+        .iter(world)
+        .find(|joint| joint.name == name)
+        .map_or(f64::NAN, |joint| joint.q)
+}
+
+// position and roll, pitch, yaw of the first chassis, with a floating base or the joint chain
+pub fn chassis_pose(world: &mut World) -> ([f64; 3], [f64; 3]) {
+    let mut query = world.query::<&Joint>();
+    if let Some(chassis) = query.iter(world).find(|joint| joint.name == "chassis") {
+        return (
+            chassis.floating.position.into(),
+            chassis.floating.euler_angles(),
+        );
+    }
+    let position =
+        ["chassis_px", "chassis_py", "chassis_pz"].map(|name| joint_position(world, name));
+    let angles = ["chassis_rx", "chassis_ry", "chassis_rz"].map(|name| joint_position(world, name));
+    (position, angles)
+}
+
+// world direction of the z axis of the first chassis, pointing down when the car is upside down
+pub fn chassis_up(world: &mut World) -> [f64; 3] {
+    let (_, euler_angles) = chassis_pose(world);
+    let mut pose = Floating::default();
+    pose.set_pose([0.; 3], euler_angles);
+    (pose.orientation * Vector::z()).into()
+}
@@ -9,14 +9,16 @@ use flo_curves::*;
 
 use cameras::control::CameraParentList;
 use rigid_body::{
+    collision::{Collider, CollisionShape},
     definitions::{MeshDef, MeshTypeDef, TransformDef}, 
     joint::{Base, Joint}, 
+    limits::JointLimit,
     sva::{Inertia, Matrix, Motion, Vector, Xform},
     plugin::CarState, 
 };
 
 use crate::{
-    control::{CarControl, ControlType}, physics::{
+    contact::TerrainContact, control::{CarControl, ControlType}, physics::{
         BrakeWheel, DriveType, DrivenWheelLookup, SteeringCurvature, SteeringType,
         SuspensionComponent,
     }, preferences::CarPreferences, tire::PointTire
@@ -150,9 +152,10 @@ pub fn build_car(
         cg_position: [0., 0., 0.],
         moi,
         dimensions,
-        position: startposition, // position: [0., 0., 0.],
+        position: [0., 0., 0.], // chassis mesh and collider relative to the body
         initial_position: [-5. + xpos, 20. + ypos, 0.3 + 0.25 + zpos], // initial_position: [-5., 20., 0.3 + 0.25],
         initial_orientation: [0., 0., 1.57],
+        floating_base: true,
         mesh_file: Some("models/vehicle/chassis/car_chassisV2.glb#Scene0".to_string()),
         index: id,
     };
@@ -164,6 +167,13 @@ pub fn build_car(
     let suspension_damping = 0.25 * 2. * (suspension_stiffness * (1000. / 4.) as f64).sqrt();
     let suspension_preload = mass * (GRAVITY / 4.);
     let suspension_moi = (2. / 3.) * suspension_mass * suspension_size.powi(2);
+    let suspension_limit = JointLimit::new(
+        -0.15,
+        0.15,
+        10. * suspension_stiffness,
+        suspension_damping,
+        0.3,
+    );
 
     let suspension_names = ["fl", "fr", "rl", "rr"].map(|name| name.to_string());
     let suspension_locations = [
@@ -199,6 +209,7 @@ pub fn build_car(
                 preload: suspension_preload,
                 moi: suspension_moi,
                 location: *location,
+                limit: Some(suspension_limit),
             }
         })
         .collect();
@@ -259,6 +270,19 @@ pub fn build_car(
     }
 }
 
+impl CarDefinition {
+    // Tires for the implicit solvers (Solver::BackwardEuler or Solver::Rosenbrock2): the vertical
+    // stiffness of a passenger car tire, and no Y moment filter, as they stay stable without it.
+    pub fn with_implicit_tires(mut self) -> Self {
+        self.wheel.stiffness = [IMPLICIT_TIRE_STIFFNESS, 0.];
+        self.wheel.damping = 0.01 * 2. * (IMPLICIT_TIRE_STIFFNESS * self.wheel.mass).sqrt();
+        self.wheel.filter_time = 0.;
+        self
+    }
+}
+
+const IMPLICIT_TIRE_STIFFNESS: f64 = 250e3; // N/m
+
 pub fn build_wheel(chassis_mass: f64, fricion_coefficient: f64) -> Wheel {
     let wheel_mass = 20.;
     let wheel_radius = 0.325_f64;
@@ -285,7 +309,7 @@ pub fn build_wheel(chassis_mass: f64, fricion_coefficient: f64) -> Wheel {
 
 pub fn car_startup_system(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
+    asset_server: Option<Res<AssetServer>>, // None without rendering, e.g. in batch runs
     mut players: ResMut<CarList>,
     mut car_state: ResMut<NextState<CarState>>,
     car_preferences: Res<CarPreferences>,
@@ -306,15 +330,15 @@ pub fn car_startup_system(
         let mut rng = rand::thread_rng();
 
         // Chassis
-        let chassis_ids = car.chassis.build(
+        let (chassis_id, chase_target_id) = car.chassis.build(
             &mut commands,
             Color::rgb(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>()),
             base_id,
-            &asset_server,
+            asset_server.as_deref(),
         );
-        let chassis_id = chassis_ids[3]; // ids are not ordered by parent child order!!! "3" is rx, the last joint in the chain
 
-        camera_parent_list.push(chassis_ids[5]);
+        // follow x, y, z and yaw of the chassis
+        camera_parent_list.push(chase_target_id);
 
         let mut brake_wheel_ids = Vec::new(); // fill this with ids and set car.carcontrol.brake_wheels
         let mut steer_wheel_ids = Vec::new(); // fill this with ids and set car.carcontrol.steer_wheels
@@ -339,7 +363,7 @@ pub fn car_startup_system(
                 car.drives[ind].clone(),
                 braked_wheel.clone(),
                 0.,
-                &asset_server,
+                asset_server.as_deref(),
                 ind,
             );
 
@@ -356,7 +380,7 @@ pub fn car_startup_system(
 
     commands.insert_resource(CameraParentList {
         list: camera_parent_list,
-        active: 1, // start with following x, y, z and yaw of chassis
+        active: 1, // start with following the chassis
     });
 
     //Change the car state to Rendered
@@ -373,18 +397,131 @@ pub struct Chassis {
     pub position: [f64; 3],
     pub initial_position: [f64; 3],
     pub initial_orientation: [f64; 3],
+    pub floating_base: bool, // one floating joint instead of the px, py, pz, rz, ry, rx chain
     pub mesh_file: Option<String>,
     pub index: i32,
 }
 
 impl Chassis {
+    // Returns the body of the car, the parent of the suspension, and the chase camera target that
+    // follows its position and yaw: a ChaseTarget with a floating joint, or the rz joint of the
+    // px, py, pz, rz, ry, rx chain.
     pub fn build(
         &self,
         commands: &mut Commands,
         color: Color,
         parent_id: Entity,
-        asset_server: &Res<AssetServer>,
-    ) -> Vec<Entity> {
+        asset_server: Option<&AssetServer>,
+    ) -> (Entity, Entity) {
+        // this is the body of the car!
+        let mass = self.mass;
+        let cg_position = self.cg_position;
+        let moi = self.moi;
+        let position = self.position;
+        let dimensions = self.dimensions;
+        let inertia = Inertia::new(
+            mass,
+            Vector::new(cg_position[0], cg_position[1], cg_position[2]),
+            Matrix::from_diagonal(&Vector::new(moi[0], moi[1], moi[2])),
+        );
+
+        let (body_id, chase_target_id) = if self.floating_base {
+            // all six degrees of freedom in one joint, with a quaternion orientation
+            let mut chassis = Joint::floating("chassis".to_string(), inertia, Xform::identity());
+            chassis
+                .floating
+                .set_pose(self.initial_position, self.initial_orientation);
+            let mut chassis_e = commands.spawn((chassis, SpatialBundle::default()));
+            chassis_e.set_parent(parent_id);
+            let chassis_id = chassis_e.id();
+            let chase_target = ChaseTarget {
+                chassis: chassis_id,
+            };
+            let chase_target_id = commands
+                .spawn((chase_target, SpatialBundle::default()))
+                .id();
+            (chassis_id, chase_target_id)
+        } else {
+            self.build_joint_chain(commands, parent_id, inertia)
+        };
+        let mut body_e = commands.entity(body_id);
+
+        //Create a bezier curve for curving playback audio (to simulate changing of gears)
+        let sound_curve = bezier::Curve::from_points(
+            Coord2(0.0, 0.6),
+            (Coord2(1.0, 1.4), Coord2(0.84, 0.55)),
+            Coord2(1.0, 1.0),
+        );
+
+        //Insert the car chassis into the body joint entity.
+        if let Some(_chassis_file) = &self.mesh_file {
+            // the model and sound need an asset server
+            if let Some(asset_server) = asset_server {
+                body_e.insert(SceneBundle {
+                    transform: (&TransformDef::from_position(position)).into(),
+                    scene: asset_server.load("models/vehicle/chassis/car_chassis.glb#Scene0"),
+                    ..default()
+                });
+
+                 //Setup audio emitter for our engine audio and parent it to our chassis
+                 body_e.insert((
+                    AudioBundle {
+                        source: asset_server.load("sounds/engine_hum.ogg"),
+                        settings: PlaybackSettings::LOOP.with_spatial(true),
+                        ..default()
+                    },
+                    Engine {
+                        speed: 0.0,
+                        curve: sound_curve,
+                    },
+                ));
+            }
+        } else {
+            body_e.insert(MeshDef {
+                mesh_type: MeshTypeDef::Box {
+                    dimensions: [
+                        dimensions[0] as f32,
+                        dimensions[1] as f32,
+                        dimensions[2] as f32,
+                    ],
+                },
+                transform: TransformDef::from_position(position),
+                color,
+            });
+        }
+
+        // contact with the other cars and obstacles, the box of the chassis dimensions
+        body_e.insert(
+            Collider::new(
+                CollisionShape::Box {
+                    half_extents: Vector::new(dimensions[0], dimensions[1], dimensions[2]) / 2.,
+                },
+                Xform::pos(position[0], position[1], position[2]),
+            )
+            .with_contact(1e6, 2e4, 0.5)
+            .with_group(self.index as u32),
+        );
+
+        // contact with the terrain when the car rolls over or bottoms out
+        body_e.insert(TerrainContact::box_edges(
+            dimensions,
+            Vector::new(position[0], position[1], position[2]),
+            5,
+            1e5,
+            2e3,
+            0.6,
+        ));
+
+        (body_id, chase_target_id)
+    }
+
+    // Euler angle chain of 1-DoF joints. It has a gimbal lock when the car pitches to +-90 deg.
+    fn build_joint_chain(
+        &self,
+        commands: &mut Commands,
+        parent_id: Entity,
+        inertia: Inertia,
+    ) -> (Entity, Entity) {
         // x degree of freedom (absolute coordinate system, not relative to car)
         let mut px = Joint::px("chassis_px".to_string(), Inertia::zero(), Xform::identity());
         px.q = self.initial_position[0];
@@ -420,69 +557,36 @@ impl Chassis {
         ry_e.set_parent(rz_id);
         let ry_id = ry_e.id();
 
-        // roll degree of freedom (rotation around x axis)
-        // this is the body of the car!
-        let mass = self.mass;
-        let cg_position = self.cg_position;
-        let moi = self.moi;
-        let position = self.position;
-        let dimensions = self.dimensions;
-        let inertia = Inertia::new(
-            mass,
-            Vector::new(cg_position[0], cg_position[1], cg_position[2]),
-            Matrix::from_diagonal(&Vector::new(moi[0], moi[1], moi[2])),
-        );
-
+        // roll degree of freedom (rotation around x axis), the body of the car
         let mut rx = Joint::rx("chassis_rx".to_string(), inertia, Xform::identity());
         rx.q = self.initial_orientation[0];
         let mut rx_e = commands.spawn((rx, SpatialBundle::default()));
         rx_e.set_parent(ry_id);
         let rx_id = rx_e.id();
 
-        //Create a bezier curve for curving playback audio (to simulate changing of gears)
-        let sound_curve = bezier::Curve::from_points(
-            Coord2(0.0, 0.6),
-            (Coord2(1.0, 1.4), Coord2(0.84, 0.55)),
-            Coord2(1.0, 1.0),
-        );
-
-        //Insert the car chassis into the rx roll degree of freedom joint entity.
-        if let Some(_chassis_file) = &self.mesh_file {
-            rx_e.insert(SceneBundle {
-                transform: (&TransformDef::from_position(position)).into(),
-                scene: asset_server.load("models/vehicle/chassis/car_chassis.glb#Scene0"),
-                ..default()
-            });
+        (rx_id, rz_id)
+    }
+}
 
-             //Setup audio emitter for our engine audio and parent it to our chassis
-             rx_e.insert((
-                AudioBundle {
-                    source: asset_server.load("sounds/engine_hum.ogg"),
-                    settings: PlaybackSettings::LOOP.with_spatial(true),
-                    ..default()
-                },
-                Engine {
-                    speed: 0.0,
-                    curve: sound_curve,
-                },
-            ));
-        } else {
-            rx_e.insert(MeshDef {
-                mesh_type: MeshTypeDef::Box {
-                    dimensions: [
-                        dimensions[0] as f32,
-                        dimensions[1] as f32,
-                        dimensions[2] as f32,
-                    ],
-                },
-                transform: TransformDef::from_position(position),
-                color,
-            });
-        }
+// Chase camera target of a floating chassis, not parented so it can follow the position and yaw
+// of the chassis without its pitch and roll
+#[derive(Component)]
+pub struct ChaseTarget {
+    pub chassis: Entity,
+}
 
-        let chassis_ids = vec![px_id, py_id, pz_id, rx_id, ry_id, rz_id];
-        // return id the last joint in the chain. It will be the parent of the suspension / wheels
-        chassis_ids
+pub fn chase_target_system(
+    chassis_query: Query<&Joint>,
+    mut target_query: Query<(&ChaseTarget, &mut Transform)>,
+) {
+    for (target, mut transform) in target_query.iter_mut() {
+        let Ok(chassis) = chassis_query.get(target.chassis) else {
+            continue;
+        };
+        let position = chassis.floating.position;
+        let yaw = chassis.floating.euler_angles()[2];
+        transform.translation = Vec3::new(position.x as f32, position.y as f32, position.z as f32);
+        transform.rotation = Quat::from_rotation_z(yaw as f32);
     }
 }
 
@@ -496,6 +600,7 @@ pub struct Suspension {
     pub preload: f64,
     pub moi: f64,
     pub location: [f64; 3],
+    pub limit: Option<JointLimit>, // bump and droop stops of the suspension travel
 }
 
 impl Suspension {
@@ -555,6 +660,9 @@ impl Suspension {
             SuspensionComponent::new(self.stiffness, self.damping, self.preload),
         ));
         susp_e.set_parent(parent_id);
+        if let Some(limit) = self.limit {
+            susp_e.insert(limit);
+        }
 
         (susp_e.id(), steer_id)
     }
@@ -585,7 +693,7 @@ impl Wheel {
         driven_wheel: DriveType,
         braked_wheel: Option<BrakeWheel>,
         initial_speed: f64,
-        asset_server: &Res<AssetServer>,
+        asset_server: Option<&AssetServer>,
         index: usize,
     ) -> Entity {
         // wheel inertia
@@ -602,26 +710,23 @@ impl Wheel {
 
         let mut wheel_e;
         // Check which side this wheel model should be displayed as depending on index number at setup (Left or Right)
-        if index == 1 || index == 3 {
+        if let Some(asset_server) = asset_server {
+            let scene = if index == 1 || index == 3 {
+                asset_server.load("models/vehicle/wheel/wheelR.glb#Scene0")
+            } else {
+                asset_server.load("models/vehicle/wheel/wheelL.glb#Scene0")
+            };
             wheel_e = commands.spawn((
                 ry,
                 // Assign the mesh of the wheel model
                 SceneBundle {
                     transform: (&TransformDef::Identity).into(),
-                    scene: asset_server.load("models/vehicle/wheel/wheelR.glb#Scene0"),
+                    scene,
                     ..default()
                 },
             ));
         } else {
-            wheel_e = commands.spawn((
-                ry,
-                // Assign the mesh of the wheel model
-                SceneBundle {
-                    transform: (&TransformDef::Identity).into(),
-                    scene: asset_server.load("models/vehicle/wheel/wheelL.glb#Scene0"),
-                    ..default()
-                },
-            ));
+            wheel_e = commands.spawn((ry, SpatialBundle::default()));
         }
 
         // add driven and braked components
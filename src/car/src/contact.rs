@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+use grid_terrain::GridTerrain;
+use rigid_body::{
+    forces::regularized_friction,
+    joint::Joint,
+    sva::{Force, Vector},
+};
+
+// Contact of a set of body-fixed points with the terrain, on any joint entity, e.g. the corners
+// and edges of the chassis so it rests on the ground when the car rolls over or high-centers.
+// Each point in the ground gets a spring-damper normal force and Coulomb friction.
+#[derive(Component, Clone, Debug)]
+pub struct TerrainContact {
+    points: Vec<Vector>, // in body coordinates
+    pub stiffness: f64,  // per point
+    pub damping: f64,    // per point
+    pub friction: f64,
+    pub slip_speed: f64, // sliding speed of full friction, it is linear below
+}
+
+impl TerrainContact {
+    pub fn new(points: Vec<Vector>, stiffness: f64, damping: f64, friction: f64) -> Self {
+        Self {
+            points,
+            stiffness,
+            damping,
+            friction,
+            slip_speed: 0.1,
+        }
+    }
+
+    // the corners of a box and points_per_edge points along each edge, corners included
+    pub fn box_edges(
+        dimensions: [f64; 3],
+        center: Vector,
+        points_per_edge: usize,
+        stiffness: f64,
+        damping: f64,
+        friction: f64,
+    ) -> Self {
+        let half = Vector::from(dimensions) / 2.;
+        let mut points = Vec::new();
+        for sx in [-1., 1.] {
+            for sy in [-1., 1.] {
+                for sz in [-1., 1.] {
+                    points.push(center + Vector::new(sx, sy, sz).component_mul(&half));
+                }
+            }
+        }
+        // interior points of the edges along each axis
+        let divisions = points_per_edge.max(2) - 1;
+        for axis in 0..3 {
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            for su in [-1., 1.] {
+                for sv in [-1., 1.] {
+                    for index in 1..divisions {
+                        let mut point = Vector::zeros();
+                        point[axis] =
+                            -half[axis] + dimensions[axis] * index as f64 / divisions as f64;
+                        point[u] = su * half[u];
+                        point[v] = sv * half[v];
+                        points.push(center + point);
+                    }
+                }
+            }
+        }
+        Self::new(points, stiffness, damping, friction)
+    }
+
+    pub fn points(&self) -> &Vec<Vector> {
+        &self.points
+    }
+}
+
+pub fn terrain_contact_system(
+    mut contact_query: Query<(&mut Joint, &TerrainContact)>,
+    grid_terrain: Res<GridTerrain>,
+) {
+    let terrain = grid_terrain.as_ref();
+    for (mut joint, contact) in contact_query.iter_mut() {
+        let x0i = joint.x.inverse(); // spatial transform from the joint to absolute coordinates
+        let v0 = x0i * joint.v; // spatial velocity of the joint in absolute coordinates
+
+        let mut f_ext = Force::zero();
+        for point in contact.points.iter() {
+            let point_abs = x0i.transform_point(*point); // point in absolute coordinates
+            let Some(interference) = terrain.interference(point_abs) else {
+                continue;
+            };
+            let normal = interference.normal;
+            let velocity = v0.velocity_point(point_abs).vel;
+            let normal_velocity = velocity.dot(&normal);
+            let plane_velocity = velocity - normal_velocity * normal;
+
+            // the ground can only push
+            let normal_force = (contact.stiffness * interference.magnitude
+                - contact.damping * normal_velocity)
+                .max(0.);
+
+            let friction_force = regularized_friction(
+                normal_force,
+                plane_velocity,
+                contact.friction,
+                contact.slip_speed,
+            );
+
+            let force = normal_force * normal + friction_force;
+            f_ext += Force::force_point(force, interference.position);
+        }
+        joint.f_ext += f_ext;
+    }
+}
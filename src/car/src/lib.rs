@@ -1,7 +1,10 @@
+pub mod batch;
 pub mod build;
+pub mod contact;
 pub mod control;
 pub mod environment;
 pub mod interpolate;
+pub mod linearize;
 pub mod mesh;
 pub mod physics;
 pub mod setup;
@@ -0,0 +1,109 @@
+use bevy::prelude::*;
+use bevy_integrator::linearize::{linearize, LinearInputs, LinearizationSettings};
+use rigid_body::{joint::Joint, plugin::CarState};
+
+use crate::control::CarControl;
+
+// Throttle, steering and brake of every car, ordered by the CarControl entity
+pub struct CarControlInputs;
+
+const INPUTS: [&str; 3] = ["throttle", "steering", "brake"];
+
+fn sorted_controls(world: &mut World) -> Vec<Entity> {
+    let mut query = world.query_filtered::<Entity, With<CarControl>>();
+    let mut entities: Vec<Entity> = query.iter(world).collect();
+    entities.sort();
+    entities
+}
+
+impl LinearInputs for CarControlInputs {
+    fn names(&self, world: &mut World) -> Vec<String> {
+        let mut names = Vec::new();
+        for car in 0..sorted_controls(world).len() {
+            for input in INPUTS.iter() {
+                names.push(format!("car_{}.{}", car, input));
+            }
+        }
+        names
+    }
+
+    fn get(&self, world: &mut World) -> Vec<f64> {
+        let mut inputs = Vec::new();
+        for entity in sorted_controls(world) {
+            let control = world.get::<CarControl>(entity).unwrap();
+            inputs.extend([control.throttle, control.steering, control.brake].map(f64::from));
+        }
+        inputs
+    }
+
+    fn set(&self, world: &mut World, inputs: &[f64]) {
+        for (entity, values) in sorted_controls(world)
+            .iter()
+            .zip(inputs.chunks(INPUTS.len()))
+        {
+            let mut control = world.get_mut::<CarControl>(*entity).unwrap();
+            control.throttle = values[0] as f32;
+            control.steering = values[1] as f32;
+            control.brake = values[2] as f32;
+        }
+    }
+}
+
+// Linearizes the cars about the current state and controls when the key is pressed, and writes
+// A and B to <path>_a.csv, <path>_b.csv and <path>.json
+#[derive(Resource, Clone)]
+pub struct LinearizationPlugin {
+    pub key: KeyCode,
+    pub path: String,
+    pub settings: LinearizationSettings,
+}
+
+impl Default for LinearizationPlugin {
+    fn default() -> Self {
+        Self {
+            key: KeyCode::L,
+            path: "linearization".to_string(),
+            settings: LinearizationSettings::default(),
+        }
+    }
+}
+
+impl Plugin for LinearizationPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.clone()).add_systems(
+            Update,
+            linearization_key_system.run_if(in_state(CarState::Finished)),
+        );
+    }
+}
+
+fn linearization_key_system(world: &mut World) {
+    let config = world.resource::<LinearizationPlugin>().clone();
+    if !world.resource::<Input<KeyCode>>().just_pressed(config.key) {
+        return;
+    }
+
+    let Some(linearization) = linearize::<Joint>(world, &CarControlInputs, config.settings) else {
+        warn!("Can't linearize before the physics is initialized");
+        return;
+    };
+    let a_path = format!("{}_a.csv", config.path);
+    let b_path = format!("{}_b.csv", config.path);
+    let json_path = format!("{}.json", config.path);
+    let result = linearization
+        .write_csv(&a_path, &b_path)
+        .and_then(|_| linearization.write_json(&json_path));
+    match result {
+        Ok(_) => info!(
+            "Linearized {} states and {} inputs at t = {:.3} to {}",
+            linearization.state_names.len(),
+            linearization.input_names.len(),
+            linearization.time,
+            json_path
+        ),
+        Err(error) => warn!(
+            "Failed to write linearization to {}: {}",
+            config.path, error
+        ),
+    }
+}
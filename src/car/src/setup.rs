@@ -1,14 +1,22 @@
 use bevy::prelude::*;
-use bevy_integrator::{GameState, PhysicsSchedule, PhysicsSet};
-use rigid_body::plugin::CarState;
+use bevy_integrator::{
+    snapshot::SnapshotAppExt, GameState, PhysicsSchedule, PhysicsSet, StatefulAppExt,
+};
+use rigid_body::{
+    collision::CollisionPlugin,
+    limits::{JointLimit, JointLimitPlugin},
+    plugin::CarState,
+};
 
 use crate::{
-    control::user_control_system,
+    build::chase_target_system,
+    contact::terrain_contact_system,
+    control::{user_control_system, CarControl},
     physics::{
         brake_wheel_system, driven_wheel_lookup_system, steering_curvature_system, steering_system,
         suspension_system,
     },
-    tire::point_tire_system,
+    tire::{point_tire_system, PointTire},
 };
 
 use cameras::{
@@ -17,6 +25,11 @@ use cameras::{
 };
 
 pub fn simulation_setup(app: &mut App) {
+    // suspension bump stops
+    app.add_plugins(JointLimitPlugin);
+    // contact between the cars
+    app.add_plugins(CollisionPlugin);
+
     app.add_systems(
         PhysicsSchedule,
         (steering_system, steering_curvature_system).in_set(PhysicsSet::Pre),
@@ -26,6 +39,7 @@ pub fn simulation_setup(app: &mut App) {
         (
             suspension_system,
             point_tire_system,
+            terrain_contact_system,
             driven_wheel_lookup_system,
             brake_wheel_system,
         )
@@ -34,7 +48,14 @@ pub fn simulation_setup(app: &mut App) {
     .add_systems(
         Update,
         (user_control_system,).run_if(in_state(CarState::Finished)),
-    );
+    )
+    // the tire moment filter is integrated together with the joints
+    .add_stateful::<PointTire>()
+    // state outside of the joints that is needed to rewind the simulation
+    .register_snapshot_component::<PointTire>()
+    .register_snapshot_component::<CarControl>()
+    // which bump stops are engaged, so a rewind doesn't resend or miss limit events
+    .register_snapshot_component::<JointLimit>();
 }
 
 pub fn camera_setup(app: &mut App) {
@@ -55,6 +76,11 @@ pub fn camera_setup(app: &mut App) {
     )
     .add_systems(
         Update,
-        (camera_az_el::az_el_camera, camera_parent_system).run_if(in_state(CarState::Finished)),
+        (
+            camera_az_el::az_el_camera,
+            camera_parent_system,
+            chase_target_system,
+        )
+            .run_if(in_state(CarState::Finished)),
     ); // setup the camera
 }
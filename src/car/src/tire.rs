@@ -1,11 +1,12 @@
 use bevy::prelude::*;
+use bevy_integrator::Stateful;
 use grid_terrain::GridTerrain;
 use rigid_body::{
     joint::Joint,
     sva::{Force, Vector},
 };
 
-#[derive(Component)]
+#[derive(Component, Clone, Debug)]
 pub struct PointTire {
     joint_entity: Entity,
     joint_parent: Entity,
@@ -18,6 +19,7 @@ pub struct PointTire {
     low_speed: f64,
     filter_time: f64,
     my_filtered: f64,
+    my_filtered_rate: f64,
     activation_length: f64,
 }
 
@@ -79,6 +81,7 @@ impl PointTire {
             low_speed,
             filter_time,
             my_filtered: 0.,
+            my_filtered_rate: 0.,
             activation_length,
         }
     }
@@ -92,6 +95,35 @@ impl PointTire {
     }
 }
 
+// The filtered Y moment is integrated with the joints, so it is consistent across the solver stages
+impl Stateful for PointTire {
+    type State = f64;
+
+    fn get_state(&self) -> Self::State {
+        self.my_filtered
+    }
+
+    fn set_state(&mut self, state: &Self::State) {
+        self.my_filtered = *state;
+    }
+
+    fn get_dstate(&self) -> Self::State {
+        self.my_filtered_rate
+    }
+
+    fn set_dstate(&mut self, dstate: Self::State) {
+        self.my_filtered_rate = dstate;
+    }
+
+    fn reset(&mut self) {
+        self.my_filtered_rate = 0.;
+    }
+
+    fn get_name(&self) -> String {
+        format!("tire_{:?}", self.joint_entity)
+    }
+}
+
 pub fn point_tire_system(
     mut tire_query: Query<&mut PointTire>,
     mut query_joints: Query<&mut Joint>,
@@ -193,12 +225,15 @@ pub fn point_tire_system(
                 f_ext += Force::force_point(force, contact.position);
             }
 
-            // Y Moment Filter (otherwise the wheel oscillates, it is too stiff for the solver)
-            let mut f_ext_parent = parent.x * f_ext; // resolve the force about the axle
-            let weight = 0.5_f64.powf(1. / (tire.filter_time / (0.002 / 4.))); // hard coded time step
-            tire.my_filtered = tire.my_filtered * weight + f_ext_parent.m.y * (1. - weight);
-            f_ext_parent.m.y = tire.my_filtered;
-            f_ext = parent.x.inverse() * f_ext_parent;
+            // Y Moment Filter (otherwise the wheel oscillates, it is too stiff for the explicit solvers)
+            // A filter_time of zero disables the filter, e.g. when using an implicit solver
+            if tire.filter_time > 0. {
+                let mut f_ext_parent = parent.x * f_ext; // resolve the force about the axle
+                let time_constant = tire.filter_time / std::f64::consts::LN_2; // filter_time is the half-life
+                tire.my_filtered_rate = (f_ext_parent.m.y - tire.my_filtered) / time_constant;
+                f_ext_parent.m.y = tire.my_filtered;
+                f_ext = parent.x.inverse() * f_ext_parent;
+            }
 
             // apply the force to the joint
             joint.f_ext += f_ext;
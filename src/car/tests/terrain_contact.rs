@@ -0,0 +1,81 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{step, PhysicsSchedule, PhysicsSet, SimTime, Solver};
+use car::contact::{terrain_contact_system, TerrainContact};
+use grid_terrain::GridTerrain;
+use rigid_body::{
+    joint::{Base, Joint},
+    plugin::HeadlessRigidBodyPlugin,
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+const MASS: f64 = 100.;
+const DIMENSIONS: [f64; 3] = [2., 1., 0.5];
+const STIFFNESS: f64 = 1e5;
+
+fn contact_setup(app: &mut App) {
+    app.add_systems(
+        PhysicsSchedule,
+        terrain_contact_system.in_set(PhysicsSet::Evaluate),
+    );
+}
+
+// a box dropped flat onto the terrain, with a contact point at each corner
+fn box_startup_system(mut commands: Commands) {
+    // no grid elements, the terrain is the flat ground at z = 0 everywhere
+    commands.insert_resource(GridTerrain::new(vec![], [10., 10.]));
+
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    let inertia = Inertia::new(
+        MASS,
+        Vector::zeros(),
+        Matrix::from_diagonal(&Vector::new(10., 35., 40.)),
+    );
+    let mut body = Joint::floating("box".to_string(), inertia, Xform::identity());
+    body.floating
+        .set_pose([3., 4., DIMENSIONS[2] / 2. + 0.05], [0., 0., 0.3]);
+    commands
+        .spawn((
+            body,
+            TerrainContact::box_edges(DIMENSIONS, Vector::zeros(), 2, STIFFNESS, 2e3, 0.8),
+        ))
+        .set_parent(base_id);
+}
+
+// At rest the four bottom corners carry the weight, each sunk in by a quarter of it over the
+// stiffness, and the contact force has no moment about the center of the box.
+#[test]
+fn box_rests_on_its_bottom_corners() {
+    let mut app = App::new();
+    app.add_plugins(HeadlessRigidBodyPlugin {
+        time: SimTime::new(0.001, 0., None),
+        solver: Solver::RK4,
+        simulation_setup: vec![contact_setup],
+    })
+    .add_systems(Startup, box_startup_system);
+    app.update();
+
+    step::<Joint>(&mut app.world, 2000);
+    app.world.run_schedule(PhysicsSchedule);
+
+    let mut query = app.world.query::<(&Joint, &TerrainContact)>();
+    let (joint, _) = query.single(&app.world);
+    let center = joint.x.inverse().transform_point(Vector::zeros());
+    let weight = MASS * 9.81;
+
+    let sink = weight / (4. * STIFFNESS);
+    assert!(
+        (center.z - (DIMENSIONS[2] / 2. - sink)).abs() < 1e-6,
+        "{}",
+        center.z
+    );
+
+    // f_ext is in absolute coordinates, take its moment about the center
+    let f_ext = joint.f_ext;
+    let moment = f_ext.m - center.cross(&f_ext.f);
+    assert!((f_ext.f.z - weight).abs() < 1e-3, "{:?}", f_ext.f);
+    assert!(f_ext.f.xy().norm() < 1e-3, "{:?}", f_ext.f);
+    assert!(moment.norm() < 1e-3, "{:?}", moment);
+}
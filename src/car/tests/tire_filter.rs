@@ -0,0 +1,53 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{step, PhysicsState, SimTime, Solver, Stateful};
+use car::{
+    batch::headless_car_setup, build::build_car, control::ControlType, preferences::CarPreferences,
+    tire::PointTire,
+};
+use grid_terrain::examples::TerrainPreferences;
+use rigid_body::joint::Joint;
+
+// The Y moment filter of the tires is registered with add_stateful, so the solver integrates it
+// together with the joints. In the air the moment is zero, and the filtered moment halves every
+// filter_time (5 ms).
+#[test]
+fn tire_filter_is_integrated_with_the_joints() {
+    let mut app = App::new();
+    let car_preferences = CarPreferences::default();
+    let car = build_car(
+        [0., 0., 20.],
+        ControlType::WASD,
+        0,
+        car_preferences.max_speed,
+        car_preferences.mass,
+        car_preferences.max_torque,
+        car_preferences.friction_coefficient,
+    );
+    headless_car_setup(
+        &mut app,
+        SimTime::new(0.0005, 0., None),
+        Solver::RK4,
+        vec![car],
+        car_preferences,
+        TerrainPreferences {
+            grid_size: 100.,
+            subdivisions: 16.,
+            seed: 1,
+        },
+    );
+    app.update();
+
+    for mut tire in app.world.query::<&mut PointTire>().iter_mut(&mut app.world) {
+        tire.set_state(&100.);
+    }
+
+    for expected in [50., 25.] {
+        step::<Joint>(&mut app.world, 10);
+        let physics_state = app.world.resource::<PhysicsState<PointTire>>();
+        assert_eq!(physics_state.states.len(), 4);
+        for (_, filtered) in physics_state.states.iter() {
+            assert!((filtered - expected).abs() < 1e-4, "{}", filtered);
+        }
+    }
+}
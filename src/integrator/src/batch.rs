@@ -0,0 +1,228 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    thread,
+};
+
+use bevy::prelude::*;
+
+use crate::{step, SimTime, Stateful};
+
+type Sampler = Box<dyn Fn(u64) -> Vec<f64> + Send + Sync>;
+type Builder = Box<dyn Fn(&mut App, &[f64]) + Send + Sync>;
+type OutputFunction = Box<dyn Fn(&mut World) -> f64 + Send + Sync>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputKind {
+    Final, // value at the end of the run
+    Max,   // largest value after any step
+    Min,   // smallest value after any step
+}
+
+struct BatchOutput {
+    name: String,
+    kind: OutputKind,
+    function: OutputFunction,
+}
+
+// Runs a Monte Carlo batch of headless simulations. For every sample the sampler draws the
+// parameters from the seed of the sample, the builder sets up a new App with them, and the
+// simulation runs until SimTime::end_time. The outputs are collected into one BatchResults table.
+// The seeds only depend on the batch seed and the sample index, so a batch is reproducible.
+pub struct BatchRunner {
+    pub samples: usize,
+    pub seed: u64,
+    pub threads: usize,
+    parameter_names: Vec<String>,
+    sampler: Sampler,
+    builder: Builder,
+    outputs: Vec<BatchOutput>,
+}
+
+impl BatchRunner {
+    pub fn new(
+        samples: usize,
+        seed: u64,
+        parameter_names: &[&str],
+        sampler: impl Fn(u64) -> Vec<f64> + Send + Sync + 'static,
+        builder: impl Fn(&mut App, &[f64]) + Send + Sync + 'static,
+    ) -> Self {
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        Self {
+            samples,
+            seed,
+            threads,
+            parameter_names: parameter_names
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            sampler: Box::new(sampler),
+            builder: Box::new(builder),
+            outputs: Vec::new(),
+        }
+    }
+
+    // add a scalar output, e.g. the final position or whether the car flipped (as 0 or 1)
+    pub fn with_output(
+        mut self,
+        name: &str,
+        kind: OutputKind,
+        function: impl Fn(&mut World) -> f64 + Send + Sync + 'static,
+    ) -> Self {
+        self.outputs.push(BatchOutput {
+            name: name.to_string(),
+            kind,
+            function: Box::new(function),
+        });
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    // seed of a sample (splitmix64), 53 bits so it is exact in the f64 results table
+    pub fn sample_seed(&self, sample: usize) -> u64 {
+        let mut z = self
+            .seed
+            .wrapping_add((sample as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15));
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        (z ^ (z >> 31)) >> 11
+    }
+
+    // Run every sample, spread over the threads. T is the Stateful type of the integrator,
+    // e.g. Joint. The rows of the results are in sample order whatever the number of threads.
+    pub fn run<T: Component + Stateful>(&self) -> BatchResults {
+        let mut columns = vec!["sample".to_string(), "seed".to_string()];
+        columns.extend(self.parameter_names.iter().cloned());
+        columns.extend(self.outputs.iter().map(|output| output.name.clone()));
+
+        let mut rows = vec![Vec::new(); self.samples];
+        let chunk_size = self.samples.div_ceil(self.threads.max(1)).max(1);
+        thread::scope(|scope| {
+            for (chunk, chunk_rows) in rows.chunks_mut(chunk_size).enumerate() {
+                scope.spawn(move || {
+                    for (i, row) in chunk_rows.iter_mut().enumerate() {
+                        *row = self.run_sample::<T>(chunk * chunk_size + i);
+                    }
+                });
+            }
+        });
+
+        BatchResults { columns, rows }
+    }
+
+    fn run_sample<T: Component + Stateful>(&self, sample: usize) -> Vec<f64> {
+        let seed = self.sample_seed(sample);
+        let parameters = (self.sampler)(seed);
+
+        let mut app = App::new();
+        (self.builder)(&mut app, &parameters);
+        // run the startup systems
+        app.update();
+
+        let mut values: Vec<f64> = self
+            .outputs
+            .iter()
+            .map(|output| match output.kind {
+                OutputKind::Final => f64::NAN,
+                OutputKind::Max => f64::NEG_INFINITY,
+                OutputKind::Min => f64::INFINITY,
+            })
+            .collect();
+
+        let has_end_time = app
+            .world
+            .get_resource::<SimTime>()
+            .is_some_and(|time| time.end_time.is_some());
+        if !has_end_time {
+            warn!(
+                "Batch sample {} has no SimTime::end_time, not stepping",
+                sample
+            );
+        }
+        let complete = |world: &World| !has_end_time || world.resource::<SimTime>().is_complete();
+
+        while !complete(&app.world) {
+            step::<T>(&mut app.world, 1);
+            for (value, output) in values.iter_mut().zip(self.outputs.iter()) {
+                match output.kind {
+                    OutputKind::Final => (),
+                    OutputKind::Max => *value = value.max((output.function)(&mut app.world)),
+                    OutputKind::Min => *value = value.min((output.function)(&mut app.world)),
+                }
+            }
+        }
+        for (value, output) in values.iter_mut().zip(self.outputs.iter()) {
+            if output.kind == OutputKind::Final {
+                *value = (output.function)(&mut app.world);
+            }
+        }
+
+        let mut row = vec![sample as f64, seed as f64];
+        row.extend(parameters);
+        row.extend(values);
+        row
+    }
+}
+
+// One row per sample: sample index, seed, parameters and outputs
+#[derive(Clone, Debug, Default)]
+pub struct BatchResults {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<f64>>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ColumnStatistics {
+    pub mean: f64,
+    pub std: f64,
+    pub min: f64,
+    pub max: f64,
+    pub count: usize, // finite values
+}
+
+impl BatchResults {
+    pub fn column(&self, name: &str) -> Option<Vec<f64>> {
+        let index = self.columns.iter().position(|column| column == name)?;
+        Some(self.rows.iter().map(|row| row[index]).collect())
+    }
+
+    // statistics of the finite values of a column
+    pub fn statistics(&self, name: &str) -> Option<ColumnStatistics> {
+        let values: Vec<f64> = self
+            .column(name)?
+            .into_iter()
+            .filter(|value| value.is_finite())
+            .collect();
+        if values.is_empty() {
+            return Some(ColumnStatistics::default());
+        }
+        let count = values.len();
+        let mean = values.iter().sum::<f64>() / count as f64;
+        let variance = values
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / (count.max(2) - 1) as f64;
+        Some(ColumnStatistics {
+            mean,
+            std: variance.sqrt(),
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            count,
+        })
+    }
+
+    pub fn write_csv(&self, path: &str) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", self.columns.join(","))?;
+        for row in self.rows.iter() {
+            let values: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+            writeln!(writer, "{}", values.join(","))?;
+        }
+        writer.flush()
+    }
+}
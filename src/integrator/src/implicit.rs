@@ -0,0 +1,280 @@
+use bevy::prelude::*;
+use nalgebra::{DMatrix, DVector};
+
+use crate::{evaluate_state, SolverState};
+
+// Settings for the implicit solvers. The Jacobian of the state derivative is built by finite
+// differences (one physics evaluation per tangent coordinate of the state), so it is reused for
+// up to jacobian_update_interval steps, and rebuilt early when the Newton iteration of backward
+// Euler doesn't converge with it.
+#[derive(Resource, Clone, Debug)]
+pub struct ImplicitStep {
+    pub perturbation: f64,
+    pub tolerance: f64,
+    pub max_iterations: usize,
+    pub jacobian_update_interval: usize,
+    pub iterations: usize,       // newton iterations taken in the last step
+    pub jacobian_updates: usize, // Jacobians built, since reset_counts
+    pub singular_solves: usize,  // solves that fell back to the explicit update, since reset_counts
+    jacobian: Option<Jacobian>,
+}
+
+#[derive(Clone, Debug)]
+struct Jacobian {
+    order: Vec<Entity>,
+    matrix: DMatrix<f64>,
+    age: usize,
+}
+
+impl ImplicitStep {
+    pub fn new(
+        perturbation: f64,
+        tolerance: f64,
+        max_iterations: usize,
+        jacobian_update_interval: usize,
+    ) -> Self {
+        Self {
+            perturbation,
+            tolerance,
+            max_iterations,
+            jacobian_update_interval,
+            iterations: 0,
+            jacobian_updates: 0,
+            singular_solves: 0,
+            jacobian: None,
+        }
+    }
+
+    // force the Jacobian to be rebuilt on the next step
+    pub fn reset_jacobian(&mut self) {
+        self.jacobian = None;
+    }
+
+    pub fn reset_counts(&mut self) {
+        self.jacobian_updates = 0;
+        self.singular_solves = 0;
+    }
+
+    fn jacobian_reused(&self) -> bool {
+        matches!(&self.jacobian, Some(jacobian) if jacobian.age > 1)
+    }
+
+    fn jacobian<S: SolverState>(
+        &mut self,
+        world: &mut World,
+        state: &S,
+        order: &[Entity],
+        derivative: &DVector<f64>,
+        t: f64,
+    ) -> DMatrix<f64> {
+        if let Some(jacobian) = self.jacobian.as_mut() {
+            if jacobian.order == order && jacobian.age < self.jacobian_update_interval {
+                jacobian.age += 1;
+                return jacobian.matrix.clone();
+            }
+        }
+
+        let matrix = finite_difference_jacobian(world, state, derivative, self.perturbation, t);
+        self.jacobian_updates += 1;
+        self.jacobian = Some(Jacobian {
+            order: order.to_vec(),
+            matrix: matrix.clone(),
+            age: 1,
+        });
+        matrix
+    }
+
+    // Solve (I - scale * J) x = rhs. If the matrix is singular with a reused Jacobian, the
+    // Jacobian is rebuilt at the state of the step (with its derivative) and solved again. If it is
+    // still singular, this falls back to the explicit update x = rhs and counts it.
+    #[allow(clippy::too_many_arguments)]
+    fn solve<S: SolverState>(
+        &mut self,
+        world: &mut World,
+        state: &S,
+        derivative: &DVector<f64>,
+        t: f64,
+        jacobian: &mut DMatrix<f64>,
+        scale: f64,
+        rhs: &DVector<f64>,
+    ) -> DVector<f64> {
+        if let Some(x) = solve(jacobian, scale, rhs) {
+            return x;
+        }
+        if self.jacobian_reused() {
+            self.reset_jacobian();
+            *jacobian = self.jacobian(world, state, &state.layout(), derivative, t);
+            if let Some(x) = solve(jacobian, scale, rhs) {
+                return x;
+            }
+        }
+        self.singular_solves += 1;
+        rhs.clone()
+    }
+}
+
+impl Default for ImplicitStep {
+    fn default() -> Self {
+        Self::new(1e-7, 1e-6, 4, 50)
+    }
+}
+
+// the state moved by delta in tangent coordinates (see StateVector::tangent)
+fn retract<S: SolverState>(state: &S, delta: &DVector<f64>) -> S {
+    let mut retracted = state.clone();
+    retracted.set_components(&state.retract(delta.as_slice()));
+    retracted
+}
+
+// The derivative of the tangent coordinates about the state, evaluated with the state moved by
+// delta. The implicit solvers work in these coordinates, so the Newton updates and the Jacobian
+// move orientations along the rotation group instead of off the unit quaternions.
+fn tangent_derivative<S: SolverState>(
+    world: &mut World,
+    state: &S,
+    delta: &DVector<f64>,
+    t: f64,
+) -> DVector<f64> {
+    let components = state.retract(delta.as_slice());
+    let mut moved = state.clone();
+    moved.set_components(&components);
+    let derivative = evaluate_state(world, &moved, t).to_components();
+    DVector::from_vec(state.tangent_derivative(&components, &derivative))
+}
+
+fn finite_difference_jacobian<S: SolverState>(
+    world: &mut World,
+    state: &S,
+    derivative: &DVector<f64>,
+    perturbation: f64,
+    t: f64,
+) -> DMatrix<f64> {
+    let tangent = state.to_tangent();
+    let n = tangent.len();
+    let mut jacobian = DMatrix::zeros(n, n);
+    for (j, x) in tangent.iter().enumerate() {
+        let delta = perturbation * x.abs().max(1.);
+        let mut dx = DVector::zeros(n);
+        dx[j] = delta;
+        let column = (tangent_derivative(world, state, &dx, t) - derivative) / delta;
+        jacobian.set_column(j, &column);
+    }
+    jacobian
+}
+
+// solve (I - scale * J) x = rhs, None if the matrix is singular
+fn solve(jacobian: &DMatrix<f64>, scale: f64, rhs: &DVector<f64>) -> Option<DVector<f64>> {
+    let n = rhs.len();
+    let matrix = DMatrix::identity(n, n) - jacobian * scale;
+    matrix.lu().solve(rhs)
+}
+
+// backward Euler, solved with a simplified Newton iteration
+pub(crate) fn backward_euler<S: SolverState>(world: &mut World, state: &S, t: f64, dt: f64) -> S {
+    let mut settings = world.remove_resource::<ImplicitStep>().unwrap_or_default();
+
+    let order = state.layout();
+    let zero = DVector::zeros(state.to_tangent().len());
+    let derivative_0 = tangent_derivative(world, state, &zero, t);
+    let mut jacobian = settings.jacobian(world, state, &order, &derivative_0, t);
+
+    settings.iterations = 0;
+    let (mut delta_1, converged) = newton(
+        &mut settings,
+        world,
+        state,
+        &derivative_0,
+        &mut jacobian,
+        t,
+        dt,
+    );
+    if !converged && settings.jacobian_reused() {
+        // the reused Jacobian is too far off, rebuild it at this state and solve again
+        settings.reset_jacobian();
+        jacobian = settings.jacobian(world, state, &order, &derivative_0, t);
+        (delta_1, _) = newton(
+            &mut settings,
+            world,
+            state,
+            &derivative_0,
+            &mut jacobian,
+            t,
+            dt,
+        );
+    }
+
+    world.insert_resource(settings);
+    retract(state, &delta_1)
+}
+
+// Newton iteration for the backward Euler step in tangent coordinates about the state, and
+// whether it converged
+fn newton<S: SolverState>(
+    settings: &mut ImplicitStep,
+    world: &mut World,
+    state: &S,
+    derivative_0: &DVector<f64>,
+    jacobian: &mut DMatrix<f64>,
+    t: f64,
+    dt: f64,
+) -> (DVector<f64>, bool) {
+    let tangent = DVector::from_vec(state.to_tangent());
+    let mut delta_1 = DVector::zeros(tangent.len());
+    let mut derivative = derivative_0.clone();
+    for iteration in 1..=settings.max_iterations.max(1) {
+        let residual = &derivative * dt - &delta_1;
+        let delta = settings.solve(world, state, derivative_0, t, jacobian, dt, &residual);
+        delta_1 += &delta;
+        settings.iterations += 1;
+
+        let converged = delta
+            .iter()
+            .zip((&tangent + &delta_1).iter())
+            .all(|(d, y)| d.abs() <= settings.tolerance * (1. + y.abs()));
+        if converged {
+            return (delta_1, true);
+        }
+        if iteration < settings.max_iterations {
+            derivative = tangent_derivative(world, state, &delta_1, t + dt);
+        }
+    }
+    (delta_1, false)
+}
+
+// Two stage, second order L-stable Rosenbrock method (ROS2). With a reused Jacobian it is only
+// first order accurate, but stays stable for the stiff modes the Jacobian captured.
+pub(crate) fn rosenbrock2<S: SolverState>(world: &mut World, state: &S, t: f64, dt: f64) -> S {
+    let mut settings = world.remove_resource::<ImplicitStep>().unwrap_or_default();
+    let gamma = 1. + 1. / 2_f64.sqrt();
+
+    let order = state.layout();
+    let zero = DVector::zeros(state.to_tangent().len());
+    let derivative = tangent_derivative(world, state, &zero, t);
+    let mut jacobian = settings.jacobian(world, state, &order, &derivative, t);
+
+    let k1 = settings.solve(
+        world,
+        state,
+        &derivative,
+        t,
+        &mut jacobian,
+        gamma * dt,
+        &derivative,
+    );
+    let derivative_k1 = tangent_derivative(world, state, &(&k1 * dt), t + dt);
+    let rhs = derivative_k1 - &k1 * 2.;
+    let k2 = settings.solve(
+        world,
+        state,
+        &derivative,
+        t,
+        &mut jacobian,
+        gamma * dt,
+        &rhs,
+    );
+    let delta_1 = (&k1 * 1.5 + &k2 * 0.5) * dt;
+
+    settings.iterations = 0;
+    world.insert_resource(settings);
+    retract(state, &delta_1)
+}
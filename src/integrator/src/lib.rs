@@ -1,11 +1,28 @@
 // pub mod integrator;
-// pub mod recorder;
-
-use bevy::{ecs::schedule::ScheduleLabel, prelude::*};
-use std::{
-    collections::HashMap,
-    ops::{Add, Mul},
+pub mod batch;
+pub mod implicit;
+pub mod linearize;
+pub mod realtime;
+pub mod recorder;
+pub mod sim_control;
+pub mod snapshot;
+pub mod state_map;
+pub mod state_set;
+pub mod zero_crossing;
+
+use bevy::{
+    ecs::{schedule::ScheduleLabel, system::RunSystemOnce},
+    prelude::*,
 };
+use implicit::{backward_euler, rosenbrock2};
+use realtime::{allow_step, timed_step, PhysicsTiming};
+use sim_control::SimControl;
+use snapshot::{snapshot_buffer_system, SnapshotBuffer};
+use state_set::{post_step, registered_post_steps};
+use std::ops::{Add, Mul};
+
+pub use state_map::StateMap;
+pub use state_set::{IntegratedState, SolverState, StatefulAppExt};
 
 // STATE
 // Enum that will be used as a global state for the game
@@ -33,14 +50,18 @@ pub enum PhysicsSet {
     Post,
 }
 
+// Run after every integrator step, in the fixed update and in step(), on the accepted state and
+// before it is recorded or snapshotted, e.g. to project the state onto constraints or check it.
+// Systems are added with app.add_systems(PostStepSchedule, ...).
+#[derive(ScheduleLabel, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct PostStepSchedule;
+
 #[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
 enum SolverSet {
     Pre,
     Post,
 }
 
-pub struct StateMap<T: Stateful>(pub HashMap<Entity, T::State>);
-
 #[derive(Resource, Clone)]
 pub struct SimTime {
     pub dt: f64,
@@ -79,98 +100,197 @@ impl SimTime {
     }
 }
 
-// wrapper over HashMap<Entity, T::State> to implement Add and Mul
-impl<T: Stateful> StateMap<T> {
-    pub fn new() -> Self {
-        StateMap(HashMap::new())
-    }
-    pub fn get(&self, entity: &Entity) -> Option<&T::State> {
-        self.0.get(entity)
-    }
+// Run the physics for the given state and write the state derivative into dstates
+fn evaluate_state_into<S: SolverState>(world: &mut World, state: &S, _t: f64, dstates: &mut S) {
+    // assign the state
+    state.write_states(world);
 
-    pub fn insert(&mut self, entity: Entity, state: T::State) {
-        self.0.insert(entity, state);
-    }
+    // run the physics
+    world.run_schedule(PhysicsSchedule);
+
+    // copy out the state derivative
+    dstates.read_derivatives(world);
 }
 
-impl<T: Stateful> Clone for StateMap<T> {
-    fn clone(&self) -> Self {
-        StateMap(self.0.clone())
-    }
+fn evaluate_state<S: SolverState>(world: &mut World, state: &S, t: f64) -> S {
+    let mut dstates = state.clone();
+    evaluate_state_into(world, state, t, &mut dstates);
+    dstates
 }
 
-impl<T: Stateful> Mul<f64> for &StateMap<T> {
-    type Output = StateMap<T>;
+pub fn integrator_schedule<T: Component + Stateful>(world: &mut World) {
+    // get step size
+    let time_step = world
+        .get_resource::<Time<Fixed>>()
+        .unwrap()
+        .delta()
+        .as_secs_f64();
+
+    // pause, single steps and time scale
+    let n_steps = match world.get_resource_mut::<SimControl>() {
+        Some(mut control) => control.steps_due(),
+        None => 1,
+    };
+
+    for i in 0..n_steps {
+        // overrun policy
+        if !allow_step(world) {
+            if let Some(mut timing) = world.get_resource_mut::<PhysicsTiming>() {
+                timing.record_dropped(n_steps - i);
+            }
+            break;
+        }
 
-    fn mul(self, rhs: f64) -> Self::Output {
-        let mut result = HashMap::new();
-        for (entity, state) in self.0.iter() {
-            result.insert(*entity, state.clone() * rhs);
+        timed_step(world, |world| integrate_step::<T>(world, time_step));
+        // the fixed update systems after this one only see the last step
+        if i + 1 < n_steps {
+            post_step_systems::<T>(world);
+        } else {
+            run_post_step_schedule(world);
         }
-        StateMap(result)
     }
 }
 
-impl<T: Stateful> Add for &StateMap<T> {
-    type Output = StateMap<T>;
+// Advance the physics state, and the states of the types registered with add_stateful,
+// by a single step of size time_step
+pub fn integrate_step<T: Stateful>(world: &mut World, time_step: f64) {
+    if let Some(state_0) = IntegratedState::<T>::read(world) {
 
-    fn add(self, rhs: Self) -> Self::Output {
-        let mut result = HashMap::new();
-        for (entity, state) in self.0.iter() {
-            result.insert(*entity, state.clone() + rhs.0.get(entity).unwrap().clone());
-        }
-        StateMap(result)
+        // get time and increment
+        let mut time_resource = world.get_resource_mut::<SimTime>().unwrap();
+        let t_0 = time_resource.time();
+        time_resource.increment();
+        let time = time_resource.time();
+
+        // get Solver resource from world
+        let solver = *world.get_resource::<Solver>().unwrap();
+        let state = solve_step(world, solver, &state_0, time, time_step);
+
+        zero_crossing::detect_zero_crossings(world, &state_0, &state, t_0, time_step);
+        state.write_states(world);
     }
 }
 
-fn evaluate_state<T: Stateful>(world: &mut World, state: &StateMap<T>, _t: f64) -> StateMap<T> {
-    // assign the state
-    world.resource_scope(
-        |_world: &mut World, mut physics_state: Mut<PhysicsState<T>>| {
-            physics_state.states = state.clone();
-        },
-    );
+// one step of the solver from state_0, without touching SimTime or the PhysicsState resources
+pub(crate) fn solve_step<S: SolverState>(
+    world: &mut World,
+    solver: Solver,
+    state_0: &S,
+    time: f64,
+    time_step: f64,
+) -> S {
+    match solver {
+        Solver::Euler => euler(world, state_0, time, time_step),
+        Solver::Heun => heun(world, state_0, time, time_step),
+        Solver::Midpoint => midpoint(world, state_0, time, time_step),
+        Solver::RK4 => rk4(world, state_0, time, time_step),
+        Solver::DormandPrince45 => dormand_prince45(world, state_0, time, time_step),
+        Solver::BackwardEuler => backward_euler(world, state_0, time, time_step),
+        Solver::Rosenbrock2 => rosenbrock2(world, state_0, time, time_step),
+    }
+}
 
-    // run the physics
-    world.run_schedule(PhysicsSchedule);
+// Advance the simulation by n_steps steps of SimTime::dt, independent of wall-clock time.
+// This doesn't need a window or the fixed timestep loop, so it can be used headless.
+// The physics state is initialized from the components on the first call.
+pub fn step<T: Component + Stateful>(world: &mut World, n_steps: usize) {
+    if !world.contains_resource::<PhysicsState<T>>() {
+        world.run_system_once(initialize_state::<T>);
+    }
 
-    // return the state derivative
-    let mut dstates = StateMap(HashMap::new());
-    world.resource_scope(|_world: &mut World, physics_state: Mut<PhysicsState<T>>| {
-        dstates = physics_state.dstates.clone();
-    });
-    dstates
+    let time_step = world.get_resource::<SimTime>().unwrap().dt;
+    for _ in 0..n_steps {
+        integrate_step::<T>(world, time_step);
+        post_step_systems::<T>(world);
+    }
 }
 
-pub fn integrator_schedule<T: Stateful>(world: &mut World) {
-    if let Some(state_0) = world
-    .get_resource::<PhysicsState<T>>() {
-        let state_0 = state_0.states.clone();
+// the post step schedule, then recording and snapshots, which otherwise run in the fixed update
+// after integrator_schedule
+fn post_step_systems<T: Component + Stateful>(world: &mut World) {
+    run_post_step_schedule(world);
+    post_step::<T>(world);
+    registered_post_steps(world);
+    if world.contains_resource::<SnapshotBuffer<T>>() {
+        snapshot_buffer_system::<T>(world);
+    }
+}
 
-        // get step size
-        let time_step = world
-            .get_resource::<Time<Fixed>>()
-            .unwrap()
-            .delta()
-            .as_secs_f64();
+fn run_post_step_schedule(world: &mut World) {
+    // there is no schedule if no systems were added to it
+    let _ = world.try_run_schedule(PostStepSchedule);
+}
 
-        // get time and increment
-        let mut time_resource = world.get_resource_mut::<SimTime>().unwrap();
-        time_resource.increment();
-        let time = time_resource.time();
+// Scalar size of a state, used by the adaptive solvers to measure the local error
+pub trait StateNorm {
+    fn norm(&self) -> f64;
+}
 
-        // get Solver resource from world
-        let solver = world.get_resource::<Solver>().unwrap();
+// Flat access to the scalar components of a state, used to build Jacobians for the implicit solvers
+pub trait StateVector {
+    fn to_vec(&self) -> Vec<f64>;
+    fn from_slice(components: &[f64]) -> Self;
 
-        let state = match solver {
-            Solver::Euler => euler::<T>(world, &state_0, time, time_step),
-            Solver::Heun => heun::<T>(world, &state_0, time, time_step),
-            Solver::Midpoint => midpoint::<T>(world, &state_0, time, time_step),
-            Solver::RK4 => rk4::<T>(world, &state_0, time, time_step),
-        };
+    // names of the components, appended to the name of the Stateful when exporting models
+    fn component_names(&self) -> Vec<String> {
+        (0..self.to_vec().len()).map(|i| i.to_string()).collect()
+    }
+
+    // project the state back onto its constraints after it's written, e.g. a unit quaternion
+    fn normalize(&mut self) {}
+
+    // self + a * x, used by the solver stages. States that own heap data can override it to
+    // update in place, without the clones of the Add and Mul operators.
+    fn axpy(&mut self, a: f64, x: &Self)
+    where
+        Self: Clone + Add<Output = Self> + Mul<f64, Output = Self>,
+    {
+        *self = self.clone() + x.clone() * a;
+    }
+
+    // Minimal coordinates for linearizing about the state, e.g. a rotation vector instead of a
+    // unit quaternion, so no perturbation leaves the constraint. The defaults are the components.
+    fn tangent(&self) -> Vec<f64> {
+        self.to_vec()
+    }
+
+    fn tangent_names(&self) -> Vec<String> {
+        self.component_names()
+    }
+
+    // components of the state moved by a perturbation in tangent coordinates
+    fn retract(&self, delta: &[f64]) -> Vec<f64> {
+        self.to_vec()
+            .iter()
+            .zip(delta.iter())
+            .map(|(x, dx)| x + dx)
+            .collect()
+    }
+
+    // derivative of the tangent coordinates about this state, at the state with these components
+    fn tangent_derivative(&self, _components: &[f64], derivative: &[f64]) -> Vec<f64> {
+        derivative.to_vec()
+    }
+}
+
+// scalar states, e.g. filters and engine speed
+impl StateNorm for f64 {
+    fn norm(&self) -> f64 {
+        self.abs()
+    }
+}
+
+impl StateVector for f64 {
+    fn to_vec(&self) -> Vec<f64> {
+        vec![*self]
+    }
 
-        let mut physics_state = world.get_resource_mut::<PhysicsState<T>>().unwrap();
-        physics_state.states = state;
+    fn from_slice(components: &[f64]) -> Self {
+        components[0]
+    }
+
+    fn component_names(&self) -> Vec<String> {
+        vec![String::new()]
     }
 }
 
@@ -180,7 +300,9 @@ pub trait Stateful: std::fmt::Debug + 'static {
         + Clone
         + Sync
         + Send
-        + Into<f64>;
+        + Into<f64>
+        + StateNorm
+        + StateVector;
 
     fn get_state(&self) -> Self::State;
     fn set_state(&mut self, state: &Self::State);
@@ -204,6 +326,9 @@ pub trait PhysicsScheduleExt {
     ) -> &mut Self
     where
         T: Component + Stateful;
+
+    // copy the states of T into the components before the physics, and the derivatives back after
+    fn add_stateful_systems<T: Component + Stateful>(&mut self) -> &mut Self;
 }
 
 impl PhysicsScheduleExt for Schedule {
@@ -228,13 +353,17 @@ impl PhysicsScheduleExt for Schedule {
             )
                 .chain().run_if(in_state(GameState::InGame)), // This defines the ordering of the system sets
         )
-        .add_systems(distribute_state::<T>.in_set(SolverSet::Pre))
+        .add_stateful_systems::<T>()
         .add_systems(systems_init.in_set(PhysicsSet::Initialize))
-        .add_systems(systems_final.in_set(PhysicsSet::Finalize))
-        .add_systems(collect_state_derivatives::<T>.in_set(SolverSet::Post));
+        .add_systems(systems_final.in_set(PhysicsSet::Finalize));
 
         self
     }
+
+    fn add_stateful_systems<T: Component + Stateful>(&mut self) -> &mut Self {
+        self.add_systems(distribute_state::<T>.in_set(SolverSet::Pre))
+            .add_systems(collect_state_derivatives::<T>.in_set(SolverSet::Post))
+    }
 }
 
 pub fn initialize_state<T: Component + Stateful>(
@@ -242,15 +371,18 @@ pub fn initialize_state<T: Component + Stateful>(
     joint_query: Query<(Entity, &T)>,
 ) {
     let mut states = StateMap::<T>::new();
-    let mut dstates = StateMap::<T>::new();
     for (entity, joint) in joint_query.iter() {
         states.insert(entity, joint.get_state());
+    }
+    // share the entity index between the states and derivatives
+    let mut dstates = states.clone();
+    for (entity, joint) in joint_query.iter() {
         dstates.insert(entity, joint.get_dstate());
     }
     commands.insert_resource(PhysicsState::<T> { states, dstates });
 }
 
-fn distribute_state<T: Component + Stateful>(
+pub(crate) fn distribute_state<T: Component + Stateful>(
     mut joint_query: Query<(Entity, &mut T)>,
     physics_state: Res<PhysicsState<T>>,
 ) {
@@ -262,7 +394,7 @@ fn distribute_state<T: Component + Stateful>(
     }
 }
 
-fn collect_state_derivatives<T: Component + Stateful>(
+pub(crate) fn collect_state_derivatives<T: Component + Stateful>(
     mut joint_query: Query<(Entity, &mut T)>,
     mut physics_state: ResMut<PhysicsState<T>>,
 ) {
@@ -278,46 +410,248 @@ pub enum Solver {
     Heun,
     Midpoint,
     RK4,
+    DormandPrince45,
+    BackwardEuler,
+    Rosenbrock2,
+}
+
+// Settings and statistics for the adaptive solvers. The fixed time step is split into
+// internal substeps whose size is chosen to keep the local error within the tolerances.
+#[derive(Resource, Clone, Debug)]
+pub struct AdaptiveStep {
+    pub relative_tolerance: f64,
+    pub absolute_tolerance: f64,
+    pub min_step: f64,
+    pub max_step: f64,
+    pub step_size: f64, // current internal step size, carried over between fixed steps
+    pub accepted: usize,
+    pub rejected: usize,
+}
+
+impl AdaptiveStep {
+    pub fn new(relative_tolerance: f64, absolute_tolerance: f64, min_step: f64, max_step: f64) -> Self {
+        Self {
+            relative_tolerance,
+            absolute_tolerance,
+            min_step,
+            max_step,
+            step_size: max_step,
+            accepted: 0,
+            rejected: 0,
+        }
+    }
+
+    pub fn reset_counts(&mut self) {
+        self.accepted = 0;
+        self.rejected = 0;
+    }
+}
+
+impl Default for AdaptiveStep {
+    fn default() -> Self {
+        Self::new(1e-6, 1e-6, 1e-6, 0.01)
+    }
+}
+
+// Buffers for the stage states and derivatives, kept between steps so the explicit
+// solvers don't allocate for every stage
+#[derive(Resource)]
+struct SolverWorkspace<S: SolverState> {
+    stage: S,
+    derivatives: Vec<S>,
 }
 
-fn euler<T: Stateful>(world: &mut World, state: &StateMap<T>, t: f64, dt: f64) -> StateMap<T> {
-    let state_derivative = evaluate_state(world, &mut state.clone(), t);
-    let updated_state = state + &(&state_derivative * dt);
+fn take_workspace<S: SolverState>(
+    world: &mut World,
+    state: &S,
+    n_derivatives: usize,
+) -> SolverWorkspace<S> {
+    let mut workspace = world
+        .remove_resource::<SolverWorkspace<S>>()
+        .unwrap_or_else(|| SolverWorkspace {
+            stage: state.clone(),
+            derivatives: Vec::new(),
+        });
+    if workspace.derivatives.len() < n_derivatives {
+        workspace
+            .derivatives
+            .resize_with(n_derivatives, || state.clone());
+    }
+    workspace
+}
+
+fn euler<S: SolverState>(world: &mut World, state: &S, t: f64, dt: f64) -> S {
+    let mut workspace = take_workspace(world, state, 1);
+    let k = &mut workspace.derivatives;
+    evaluate_state_into(world, state, t, &mut k[0]);
+
+    let mut updated_state = state.clone();
+    updated_state.axpy(dt, &k[0]);
+    world.insert_resource(workspace);
     updated_state
 }
 
-fn heun<T: Stateful>(world: &mut World, state: &StateMap<T>, t: f64, dt: f64) -> StateMap<T> {
-    let state_derivative = evaluate_state(world, &mut state.clone(), t);
-    let state_derivative2 = evaluate_state(world, &mut (state + &(&state_derivative * dt)), t + dt);
-    state + &(&(&state_derivative + &state_derivative2) * (dt * 0.5))
+fn heun<S: SolverState>(world: &mut World, state: &S, t: f64, dt: f64) -> S {
+    let mut workspace = take_workspace(world, state, 2);
+    let (stage, k) = (&mut workspace.stage, &mut workspace.derivatives);
+    evaluate_state_into(world, state, t, &mut k[0]);
+
+    stage.clone_from(state);
+    stage.axpy(dt, &k[0]);
+    evaluate_state_into(world, stage, t + dt, &mut k[1]);
+
+    let mut updated_state = state.clone();
+    updated_state.axpy(dt * 0.5, &k[0]);
+    updated_state.axpy(dt * 0.5, &k[1]);
+    world.insert_resource(workspace);
+    updated_state
 }
 
-fn midpoint<T: Stateful>(world: &mut World, state: &StateMap<T>, t: f64, dt: f64) -> StateMap<T> {
-    let state_derivative = evaluate_state(world, &mut state.clone(), t);
-    let state_derivative2 = evaluate_state(
-        world,
-        &mut (state + &(&state_derivative * (dt * 0.5))),
-        t + dt * 0.5,
-    );
-    state + &(&state_derivative2 * dt)
+fn midpoint<S: SolverState>(world: &mut World, state: &S, t: f64, dt: f64) -> S {
+    let mut workspace = take_workspace(world, state, 2);
+    let (stage, k) = (&mut workspace.stage, &mut workspace.derivatives);
+    evaluate_state_into(world, state, t, &mut k[0]);
+
+    stage.clone_from(state);
+    stage.axpy(dt * 0.5, &k[0]);
+    evaluate_state_into(world, stage, t + dt * 0.5, &mut k[1]);
+
+    let mut updated_state = state.clone();
+    updated_state.axpy(dt, &k[1]);
+    world.insert_resource(workspace);
+    updated_state
 }
 
-fn rk4<T: Stateful>(world: &mut World, state: &StateMap<T>, t: f64, dt: f64) -> StateMap<T> {
-    let state_derivative = evaluate_state(world, &mut state.clone(), t);
-    let state_derivative2 = evaluate_state(
-        world,
-        &mut (state + &(&state_derivative * (dt * 0.5))),
-        t + dt * 0.5,
-    );
-    let state_derivative3 = evaluate_state(
-        world,
-        &mut (state + &(&state_derivative2 * (dt * 0.5))),
-        t + dt * 0.5,
+fn rk4<S: SolverState>(world: &mut World, state: &S, t: f64, dt: f64) -> S {
+    let mut workspace = take_workspace(world, state, 4);
+    let (stage, k) = (&mut workspace.stage, &mut workspace.derivatives);
+    evaluate_state_into(world, state, t, &mut k[0]);
+
+    stage.clone_from(state);
+    stage.axpy(dt * 0.5, &k[0]);
+    evaluate_state_into(world, stage, t + dt * 0.5, &mut k[1]);
+
+    stage.clone_from(state);
+    stage.axpy(dt * 0.5, &k[1]);
+    evaluate_state_into(world, stage, t + dt * 0.5, &mut k[2]);
+
+    stage.clone_from(state);
+    stage.axpy(dt, &k[2]);
+    evaluate_state_into(world, stage, t + dt, &mut k[3]);
+
+    let mut updated_state = state.clone();
+    updated_state.axpy(dt / 6., &k[0]);
+    updated_state.axpy(dt / 3., &k[1]);
+    updated_state.axpy(dt / 3., &k[2]);
+    updated_state.axpy(dt / 6., &k[3]);
+    world.insert_resource(workspace);
+    updated_state
+}
+
+// Dormand-Prince 5(4) coefficients
+const DP_C: [f64; 7] = [0., 1. / 5., 3. / 10., 4. / 5., 8. / 9., 1., 1.];
+const DP_A: [[f64; 6]; 6] = [
+    [1. / 5., 0., 0., 0., 0., 0.],
+    [3. / 40., 9. / 40., 0., 0., 0., 0.],
+    [44. / 45., -56. / 15., 32. / 9., 0., 0., 0.],
+    [19372. / 6561., -25360. / 2187., 64448. / 6561., -212. / 729., 0., 0.],
+    [9017. / 3168., -355. / 33., 46732. / 5247., 49. / 176., -5103. / 18656., 0.],
+    [35. / 384., 0., 500. / 1113., 125. / 192., -2187. / 6784., 11. / 84.],
+];
+// difference between the 5th and 4th order solutions
+const DP_E: [f64; 7] = [
+    71. / 57600.,
+    0.,
+    -71. / 16695.,
+    71. / 1920.,
+    -17253. / 339200.,
+    22. / 525.,
+    -1. / 40.,
+];
+
+// step size controller parameters
+const SAFETY_FACTOR: f64 = 0.9;
+const MIN_FACTOR: f64 = 0.2;
+const MAX_FACTOR: f64 = 5.;
+
+// root mean square of the error, scaled by the tolerances
+fn error_norm<S: SolverState>(error: &S, state_0: &S, state_1: &S, control: &AdaptiveStep) -> f64 {
+    let (sum, count) = error.error_sum(
+        state_0,
+        state_1,
+        control.absolute_tolerance,
+        control.relative_tolerance,
     );
-    let state_derivative4 =
-        evaluate_state(world, &mut (state + &(&state_derivative3 * dt)), t + dt);
-    let state_change = &(&(&state_derivative + &(&state_derivative2 * 2.))
-        + &(&state_derivative3 * 2.))
-        + &state_derivative4;
-    state + &(&state_change * (dt / 6.))
+    if count == 0 {
+        return 0.;
+    }
+    (sum / count as f64).sqrt()
+}
+
+fn dormand_prince45<S: SolverState>(world: &mut World, state: &S, t: f64, dt: f64) -> S {
+    let mut control = world
+        .get_resource_or_insert_with(AdaptiveStep::default)
+        .clone();
+
+    let mut workspace = take_workspace(world, state, 7);
+    let mut state = state.clone();
+    let mut error_state = state.clone();
+    evaluate_state_into(world, &state, t, &mut workspace.derivatives[0]);
+    let mut elapsed = 0.;
+    let mut step = control.step_size.clamp(control.min_step, control.max_step);
+
+    while elapsed < dt {
+        // don't step past the end of the fixed time step
+        let remaining = dt - elapsed;
+        let truncated = step >= remaining;
+        let h = if truncated { remaining } else { step };
+
+        // the last stage is evaluated at the 5th order solution (first same as last)
+        let (new_state, k) = (&mut workspace.stage, &mut workspace.derivatives);
+        for (stage, a) in DP_A.iter().enumerate() {
+            new_state.clone_from(&state);
+            for (weight, derivative) in a.iter().zip(k.iter()) {
+                if *weight != 0. {
+                    new_state.axpy(h * weight, derivative);
+                }
+            }
+            evaluate_state_into(world, new_state, t + elapsed + DP_C[stage + 1] * h, &mut k[stage + 1]);
+        }
+
+        error_state.set_scaled(h * DP_E[0], &k[0]);
+        for (weight, derivative) in DP_E.iter().zip(k.iter()).skip(1) {
+            if *weight != 0. {
+                error_state.axpy(h * weight, derivative);
+            }
+        }
+        let error = error_norm(&error_state, &state, new_state, &control);
+
+        let accepted = error <= 1. || h <= control.min_step;
+        if accepted {
+            control.accepted += 1;
+            elapsed = if truncated { dt } else { elapsed + h };
+            std::mem::swap(&mut state, &mut workspace.stage);
+            workspace.derivatives.swap(0, 6);
+        } else {
+            control.rejected += 1;
+        }
+
+        // propose the next step size
+        let factor = if error.is_finite() {
+            (SAFETY_FACTOR * error.powf(-0.2)).clamp(MIN_FACTOR, MAX_FACTOR)
+        } else {
+            MIN_FACTOR
+        };
+        let proposed = (h * factor).clamp(control.min_step, control.max_step);
+        step = if accepted && truncated {
+            proposed.max(step)
+        } else {
+            proposed
+        };
+    }
+
+    control.step_size = step;
+    world.insert_resource(control);
+    world.insert_resource(workspace);
+    state
 }
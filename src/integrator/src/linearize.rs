@@ -0,0 +1,220 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use bevy::prelude::*;
+use nalgebra::{DMatrix, DVector};
+
+use crate::{evaluate_state, IntegratedState, SimTime, SolverState, Stateful};
+
+// Inputs of a linearized model, e.g. the driver controls of the cars
+pub trait LinearInputs {
+    fn names(&self, world: &mut World) -> Vec<String>;
+    fn get(&self, world: &mut World) -> Vec<f64>;
+    fn set(&self, world: &mut World, inputs: &[f64]);
+}
+
+// no inputs, for the A matrix only
+impl LinearInputs for () {
+    fn names(&self, _world: &mut World) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn get(&self, _world: &mut World) -> Vec<f64> {
+        Vec::new()
+    }
+
+    fn set(&self, _world: &mut World, _inputs: &[f64]) {}
+}
+
+// Finite difference settings. The perturbations are relative to max(|x|, 1).
+#[derive(Clone, Copy, Debug)]
+pub struct LinearizationSettings {
+    pub state_perturbation: f64,
+    pub input_perturbation: f64,
+    pub central_differences: bool, // two evaluations per column instead of one, but second order
+}
+
+impl Default for LinearizationSettings {
+    fn default() -> Self {
+        Self {
+            state_perturbation: 1e-6,
+            input_perturbation: 1e-4,
+            central_differences: true,
+        }
+    }
+}
+
+// xd = f(x, u) linearized about an operating point: dxd = A dx + B du. x is in the tangent
+// coordinates of the states (StateVector::tangent), e.g. a rotation vector for an orientation,
+// perturbed about the operating point, so A has no directions that leave the state constraints.
+#[derive(Clone, Debug)]
+pub struct Linearization {
+    pub time: f64,
+    pub state_names: Vec<String>,
+    pub input_names: Vec<String>,
+    pub state: DVector<f64>,
+    pub input: DVector<f64>,
+    pub derivative: DVector<f64>,
+    pub a: DMatrix<f64>,
+    pub b: DMatrix<f64>,
+}
+
+// Linearize the physics about the current PhysicsState (and the states registered with
+// add_stateful) and inputs. Returns None before the physics state is initialized.
+// The state and inputs are restored afterwards.
+pub fn linearize<T: Component + Stateful>(
+    world: &mut World,
+    inputs: &impl LinearInputs,
+    settings: LinearizationSettings,
+) -> Option<Linearization> {
+    let state = IntegratedState::<T>::read(world)?;
+    let time = world
+        .get_resource::<SimTime>()
+        .map_or(0., |time| time.time());
+    let state_names = IntegratedState::<T>::tangent_names(world);
+    let input_names = inputs.names(world);
+
+    let x = DVector::from_vec(state.to_tangent());
+    let u = DVector::from_vec(inputs.get(world));
+    // derivative of the tangent coordinates with the states moved by dx
+    let evaluate = |world: &mut World, dx: &DVector<f64>| {
+        let components = state.retract(dx.as_slice());
+        let mut perturbed = state.clone();
+        perturbed.set_components(&components);
+        let derivative = evaluate_state(world, &perturbed, time).to_components();
+        DVector::from_vec(state.tangent_derivative(&components, &derivative))
+    };
+
+    let dx_0 = DVector::zeros(x.len());
+    let derivative = evaluate(world, &dx_0);
+
+    let mut a = DMatrix::zeros(x.len(), x.len());
+    for j in 0..x.len() {
+        let delta = settings.state_perturbation * x[j].abs().max(1.);
+        let column = difference(delta, settings.central_differences, &derivative, |sign| {
+            let mut dx = dx_0.clone();
+            dx[j] = sign * delta;
+            evaluate(world, &dx)
+        });
+        a.set_column(j, &column);
+    }
+
+    let mut b = DMatrix::zeros(x.len(), u.len());
+    for j in 0..u.len() {
+        let delta = settings.input_perturbation * u[j].abs().max(1.);
+        let column = difference(delta, settings.central_differences, &derivative, |sign| {
+            let mut perturbed = u.clone();
+            perturbed[j] += sign * delta;
+            inputs.set(world, perturbed.as_slice());
+            evaluate(world, &dx_0)
+        });
+        b.set_column(j, &column);
+    }
+
+    // back to the operating point
+    inputs.set(world, u.as_slice());
+    evaluate_state(world, &state, time);
+
+    Some(Linearization {
+        time,
+        state_names,
+        input_names,
+        state: x,
+        input: u,
+        derivative,
+        a,
+        b,
+    })
+}
+
+// one column of a Jacobian, evaluate(sign) gives the derivative with the perturbation sign * delta
+fn difference(
+    delta: f64,
+    central: bool,
+    derivative: &DVector<f64>,
+    mut evaluate: impl FnMut(f64) -> DVector<f64>,
+) -> DVector<f64> {
+    if central {
+        (evaluate(1.) - evaluate(-1.)) / (2. * delta)
+    } else {
+        (evaluate(1.) - derivative) / delta
+    }
+}
+
+impl Linearization {
+    // A and B as CSV, with the state and input names as the header and first column
+    pub fn write_csv(&self, a_path: &str, b_path: &str) -> std::io::Result<()> {
+        write_matrix_csv(a_path, &self.a, &self.state_names, &self.state_names)?;
+        write_matrix_csv(b_path, &self.b, &self.state_names, &self.input_names)
+    }
+
+    // Everything in one JSON object, matrices as arrays of rows. Non-finite values are null.
+    pub fn write_json(&self, path: &str) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{{")?;
+        writeln!(writer, "  \"time\": {},", json_number(self.time))?;
+        writeln!(writer, "  \"states\": {},", json_strings(&self.state_names))?;
+        writeln!(writer, "  \"inputs\": {},", json_strings(&self.input_names))?;
+        writeln!(writer, "  \"x\": {},", json_numbers(self.state.iter()))?;
+        writeln!(writer, "  \"u\": {},", json_numbers(self.input.iter()))?;
+        writeln!(
+            writer,
+            "  \"xd\": {},",
+            json_numbers(self.derivative.iter())
+        )?;
+        writeln!(writer, "  \"a\": {},", json_matrix(&self.a))?;
+        writeln!(writer, "  \"b\": {}", json_matrix(&self.b))?;
+        writeln!(writer, "}}")?;
+        writer.flush()
+    }
+}
+
+fn write_matrix_csv(
+    path: &str,
+    matrix: &DMatrix<f64>,
+    row_names: &[String],
+    column_names: &[String],
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, ",{}", column_names.join(","))?;
+    for (i, row_name) in row_names.iter().enumerate() {
+        let values: Vec<String> = matrix
+            .row(i)
+            .iter()
+            .map(|value| value.to_string())
+            .collect();
+        writeln!(writer, "{},{}", row_name, values.join(","))?;
+    }
+    writer.flush()
+}
+
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+fn json_numbers<'a>(values: impl Iterator<Item = &'a f64>) -> String {
+    let values: Vec<String> = values.map(|value| json_number(*value)).collect();
+    format!("[{}]", values.join(", "))
+}
+
+fn json_strings(strings: &[String]) -> String {
+    let strings: Vec<String> = strings
+        .iter()
+        .map(|string| format!("\"{}\"", string.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect();
+    format!("[{}]", strings.join(", "))
+}
+
+fn json_matrix(matrix: &DMatrix<f64>) -> String {
+    let rows: Vec<String> = matrix
+        .row_iter()
+        .map(|row| format!("    {}", json_numbers(row.iter())))
+        .collect();
+    format!("[\n{}\n  ]", rows.join(",\n"))
+}
@@ -0,0 +1,179 @@
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics, RegisterDiagnostic},
+    prelude::*,
+    utils::Instant,
+};
+
+use crate::SimTime;
+
+// What integrator_schedule does when the physics can't keep up with the wall clock
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverrunPolicy {
+    // take every fixed step bevy asks for (bounded by the max delta of Time<Virtual>)
+    CatchUp,
+    // stop stepping once the physics used max_frame_cost seconds of wall-clock time in a frame,
+    // the remaining steps are dropped and the simulation runs slower than real time
+    DropTime { max_frame_cost: f64 },
+    // take at most this many steps per frame, dropping the rest
+    CapSteps(usize),
+}
+
+// Wall-clock cost and real-time factor of the physics, measured once per frame
+#[derive(Resource, Clone, Debug)]
+pub struct PhysicsTiming {
+    pub policy: OverrunPolicy,
+    pub real_time_factor: f64, // simulated seconds per wall-clock second
+    pub step_cost: f64, // wall-clock seconds per integrator step
+    pub steps_per_frame: usize,
+    pub dropped_per_frame: usize,
+    frame_steps: usize,
+    frame_dropped: usize,
+    frame_cost: f64,
+    last_sim_time: Option<f64>,
+}
+
+impl PhysicsTiming {
+    pub fn new(policy: OverrunPolicy) -> Self {
+        Self {
+            policy,
+            real_time_factor: 0.,
+            step_cost: 0.,
+            steps_per_frame: 0,
+            dropped_per_frame: 0,
+            frame_steps: 0,
+            frame_dropped: 0,
+            frame_cost: 0.,
+            last_sim_time: None,
+        }
+    }
+
+    // whether the policy allows another step in this frame
+    pub fn allows_step(&self) -> bool {
+        match self.policy {
+            OverrunPolicy::CatchUp => true,
+            OverrunPolicy::DropTime { max_frame_cost } => self.frame_cost < max_frame_cost,
+            OverrunPolicy::CapSteps(max_steps) => self.frame_steps < max_steps,
+        }
+    }
+
+    pub fn record_step(&mut self, cost: f64) {
+        self.frame_steps += 1;
+        self.frame_cost += cost;
+    }
+
+    pub fn record_dropped(&mut self, n_steps: usize) {
+        self.frame_dropped += n_steps;
+    }
+
+    // close the measurements of a frame that took real_delta seconds
+    fn finish_frame(&mut self, sim_time: Option<f64>, real_delta: f64) {
+        if let (Some(time), Some(last_time)) = (sim_time, self.last_sim_time) {
+            if real_delta > 0. {
+                self.real_time_factor = (time - last_time) / real_delta;
+            }
+        }
+        if self.frame_steps > 0 {
+            self.step_cost = self.frame_cost / self.frame_steps as f64;
+        }
+        self.steps_per_frame = self.frame_steps;
+        self.dropped_per_frame = self.frame_dropped;
+
+        self.last_sim_time = sim_time;
+        self.frame_steps = 0;
+        self.frame_dropped = 0;
+        self.frame_cost = 0.;
+    }
+}
+
+impl Default for PhysicsTiming {
+    fn default() -> Self {
+        Self::new(OverrunPolicy::CatchUp)
+    }
+}
+
+// Measures the physics against the wall clock, applies the overrun policy and publishes the
+// results as bevy diagnostics (e.g. shown by LogDiagnosticsPlugin)
+pub struct PhysicsDiagnosticsPlugin {
+    pub policy: OverrunPolicy,
+}
+
+impl PhysicsDiagnosticsPlugin {
+    pub const REAL_TIME_FACTOR: DiagnosticId =
+        DiagnosticId::from_u128(197612416380417351462371809354627419721);
+    pub const STEP_TIME: DiagnosticId =
+        DiagnosticId::from_u128(52896235105813926781530941282160528436);
+    pub const STEPS_PER_FRAME: DiagnosticId =
+        DiagnosticId::from_u128(287151270355937208913040553128463801117);
+    pub const DROPPED_STEPS: DiagnosticId =
+        DiagnosticId::from_u128(110492755371260519684837203620145596254);
+
+    pub fn new(policy: OverrunPolicy) -> Self {
+        Self { policy }
+    }
+}
+
+impl Plugin for PhysicsDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PhysicsTiming::new(self.policy))
+            .register_diagnostic(Diagnostic::new(
+                Self::REAL_TIME_FACTOR,
+                "physics_real_time_factor",
+                20,
+            ))
+            .register_diagnostic(
+                Diagnostic::new(Self::STEP_TIME, "physics_step_time", 20).with_suffix("ms"),
+            )
+            .register_diagnostic(Diagnostic::new(
+                Self::STEPS_PER_FRAME,
+                "physics_steps_per_frame",
+                20,
+            ))
+            .register_diagnostic(Diagnostic::new(
+                Self::DROPPED_STEPS,
+                "physics_dropped_steps",
+                20,
+            ))
+            // before the fixed update loop, so each frame covers one run of it
+            .add_systems(PreUpdate, physics_timing_system);
+    }
+}
+
+fn physics_timing_system(
+    mut timing: ResMut<PhysicsTiming>,
+    sim_time: Option<Res<SimTime>>,
+    real_time: Option<Res<Time<Real>>>,
+    mut diagnostics: Diagnostics,
+) {
+    let real_delta = real_time.map_or(0., |time| time.delta_seconds_f64());
+    timing.finish_frame(sim_time.map(|time| time.time()), real_delta);
+
+    diagnostics.add_measurement(PhysicsDiagnosticsPlugin::REAL_TIME_FACTOR, || {
+        timing.real_time_factor
+    });
+    diagnostics.add_measurement(PhysicsDiagnosticsPlugin::STEP_TIME, || {
+        timing.step_cost * 1000.
+    });
+    diagnostics.add_measurement(PhysicsDiagnosticsPlugin::STEPS_PER_FRAME, || {
+        timing.steps_per_frame as f64
+    });
+    diagnostics.add_measurement(PhysicsDiagnosticsPlugin::DROPPED_STEPS, || {
+        timing.dropped_per_frame as f64
+    });
+}
+
+// Check the overrun policy before a step. Returns false if the step should be dropped.
+pub(crate) fn allow_step(world: &World) -> bool {
+    world
+        .get_resource::<PhysicsTiming>()
+        .is_none_or(|timing| timing.allows_step())
+}
+
+// run a step and add its wall-clock cost to the frame
+pub(crate) fn timed_step(world: &mut World, step: impl FnOnce(&mut World)) {
+    let start = Instant::now();
+    step(world);
+    let cost = start.elapsed().as_secs_f64();
+    if let Some(mut timing) = world.get_resource_mut::<PhysicsTiming>() {
+        timing.record_step(cost);
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    marker::PhantomData,
+};
+
+use bevy::{ecs::system::RunSystemOnce, prelude::*};
+
+use crate::{
+    integrator_schedule, state_set::state_name, ExitEvent, PhysicsSchedule, PhysicsState, SimTime,
+    StateVector, Stateful,
+};
+
+// magic bytes and version at the start of the binary recording format
+const BINARY_MAGIC: &[u8; 4] = b"BREC";
+const BINARY_VERSION: u32 = 1;
+
+// Records the time, state and state derivative of every entity once per accepted step.
+// Each row is written to the configured CSV and/or binary file as it is recorded, and the files
+// are flushed on exit.
+pub struct RecorderPlugin<T: Stateful> {
+    pub decimation: usize,
+    pub csv_path: Option<String>,
+    pub binary_path: Option<String>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Stateful> RecorderPlugin<T> {
+    pub fn new(decimation: usize, csv_path: Option<String>, binary_path: Option<String>) -> Self {
+        Self {
+            decimation,
+            csv_path,
+            binary_path,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Component + Stateful> Plugin for RecorderPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Recorder::<T>::new(
+            self.decimation,
+            self.csv_path.clone(),
+            self.binary_path.clone(),
+        ))
+        .add_systems(
+            FixedUpdate,
+            record_system::<T>.after(integrator_schedule::<T>),
+        )
+        .add_systems(PostUpdate, save_recording_system::<T>);
+    }
+}
+
+#[derive(Resource)]
+pub struct Recorder<T: Stateful> {
+    pub decimation: usize, // record every n-th step
+    pub csv_path: Option<String>,
+    pub binary_path: Option<String>,
+    columns: Vec<String>,
+    entities: Vec<(Entity, usize)>, // recorded entities and the size of their state
+    opened: bool,                   // the columns are fixed and the files created
+    csv: Option<BufWriter<File>>,
+    binary: Option<BufWriter<File>>,
+    last_index: Option<usize>, // SimTime index of the last recorded step
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Stateful> Recorder<T> {
+    pub fn new(decimation: usize, csv_path: Option<String>, binary_path: Option<String>) -> Self {
+        Self {
+            decimation,
+            csv_path,
+            binary_path,
+            columns: Vec::new(),
+            entities: Vec::new(),
+            opened: false,
+            csv: None,
+            binary: None,
+            last_index: None,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn columns(&self) -> &Vec<String> {
+        &self.columns
+    }
+
+    // End the recording. The next record starts new files with the columns at that time.
+    pub fn clear(&mut self) {
+        self.flush();
+        self.csv = None;
+        self.binary = None;
+        self.columns.clear();
+        self.entities.clear();
+        self.opened = false;
+        self.last_index = None;
+    }
+
+    // create the files and write the column names, once the columns are fixed
+    fn open(&mut self) {
+        self.csv = self
+            .csv_path
+            .as_deref()
+            .and_then(|path| create_file(path, &self.columns, write_csv_header));
+        self.binary = self
+            .binary_path
+            .as_deref()
+            .and_then(|path| create_file(path, &self.columns, write_binary_header));
+        self.opened = true;
+    }
+
+    // A file that fails to write is closed and the recording goes on in the other one
+    fn write_row(&mut self, row: &[f64]) {
+        if let Some(writer) = &mut self.csv {
+            if let Err(error) = write_csv_row(writer, row) {
+                warn!(
+                    "Failed to write recording to {:?}: {}",
+                    self.csv_path, error
+                );
+                self.csv = None;
+            }
+        }
+        if let Some(writer) = &mut self.binary {
+            if let Err(error) = write_binary_row(writer, row) {
+                warn!(
+                    "Failed to write recording to {:?}: {}",
+                    self.binary_path, error
+                );
+                self.binary = None;
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(writer) = &mut self.csv {
+            if let Err(error) = writer.flush() {
+                warn!(
+                    "Failed to write recording to {:?}: {}",
+                    self.csv_path, error
+                );
+            }
+        }
+        if let Some(writer) = &mut self.binary {
+            if let Err(error) = writer.flush() {
+                warn!(
+                    "Failed to write recording to {:?}: {}",
+                    self.binary_path, error
+                );
+            }
+        }
+    }
+}
+
+fn create_file(
+    path: &str,
+    columns: &[String],
+    write_header: fn(&mut BufWriter<File>, &[String]) -> std::io::Result<()>,
+) -> Option<BufWriter<File>> {
+    let mut writer = match File::create(path) {
+        Ok(file) => BufWriter::new(file),
+        Err(error) => {
+            warn!("Failed to write recording to {}: {}", path, error);
+            return None;
+        }
+    };
+    match write_header(&mut writer, columns) {
+        Ok(()) => Some(writer),
+        Err(error) => {
+            warn!("Failed to write recording to {}: {}", path, error);
+            None
+        }
+    }
+}
+
+fn write_csv_header(writer: &mut BufWriter<File>, columns: &[String]) -> std::io::Result<()> {
+    writeln!(writer, "{}", columns.join(","))
+}
+
+fn write_csv_row(writer: &mut BufWriter<File>, row: &[f64]) -> std::io::Result<()> {
+    let values: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+    writeln!(writer, "{}", values.join(","))
+}
+
+// Layout (little endian): magic, version (u32), number of columns (u32),
+// each column name as length (u32) + utf-8 bytes, then rows of f64 values until the end of the file.
+fn write_binary_header(writer: &mut BufWriter<File>, columns: &[String]) -> std::io::Result<()> {
+    writer.write_all(BINARY_MAGIC)?;
+    writer.write_all(&BINARY_VERSION.to_le_bytes())?;
+    writer.write_all(&(columns.len() as u32).to_le_bytes())?;
+    for column in columns.iter() {
+        writer.write_all(&(column.len() as u32).to_le_bytes())?;
+        writer.write_all(column.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn write_binary_row(writer: &mut BufWriter<File>, row: &[f64]) -> std::io::Result<()> {
+    for value in row.iter() {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+// Read a binary recording, returning the column names and rows
+pub fn read_binary(path: &str) -> std::io::Result<(Vec<String>, Vec<Vec<f64>>)> {
+    let invalid = |message: &str| std::io::Error::new(ErrorKind::InvalidData, message.to_string());
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != BINARY_MAGIC {
+        return Err(invalid("not a recording"));
+    }
+    if read_u32(&mut reader)? != BINARY_VERSION {
+        return Err(invalid("unsupported recording version"));
+    }
+
+    let n_columns = read_u32(&mut reader)? as usize;
+    let mut columns = Vec::with_capacity(n_columns);
+    for _ in 0..n_columns {
+        let mut name = vec![0; read_u32(&mut reader)? as usize];
+        reader.read_exact(&mut name)?;
+        columns.push(String::from_utf8(name).map_err(|_| invalid("invalid column name"))?);
+    }
+
+    // rows continue until the end of the file
+    let mut rows = Vec::new();
+    if n_columns == 0 {
+        return Ok((columns, rows));
+    }
+    let mut row_bytes = vec![0; 8 * n_columns];
+    loop {
+        match reader.read_exact(&mut row_bytes) {
+            Ok(()) => {}
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => break,
+            Err(error) => return Err(error),
+        }
+        let row = row_bytes
+            .chunks_exact(8)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        rows.push(row);
+    }
+    Ok((columns, rows))
+}
+
+// The state is the accepted state at SimTime::time(), after the post step schedule. The physics
+// is evaluated again at that state for the derivative, only on the recorded steps, since the last
+// evaluation of a multi-stage solver is at an intermediate state.
+// `%` rather than is_multiple_of, which needs Rust 1.87
+#[allow(clippy::manual_is_multiple_of)]
+pub fn record_system<T: Component + Stateful>(world: &mut World) {
+    if !world.contains_resource::<PhysicsState<T>>() {
+        return;
+    }
+    let index = world.resource::<SimTime>().index;
+    let mut recorder = world.resource_mut::<Recorder<T>>();
+
+    // only record once per integrator step
+    if recorder.last_index == Some(index) {
+        return;
+    }
+    recorder.last_index = Some(index);
+    if index % recorder.decimation.max(1) != 0 {
+        return;
+    }
+
+    world.run_schedule(PhysicsSchedule);
+    world.run_system_once(record_row_system::<T>);
+}
+
+fn record_row_system<T: Component + Stateful>(
+    sim_time: Res<SimTime>,
+    physics_state: Res<PhysicsState<T>>,
+    query: Query<(Entity, &T)>,
+    mut recorder: ResMut<Recorder<T>>,
+) {
+    // fix the columns on the first record, ordered by name, and named as in the linearization
+    // export, e.g. "wheel.q", with "wheel.q_dot" for its derivative
+    if !recorder.opened {
+        let mut components: Vec<(Entity, &T)> = query.iter().collect();
+        components.sort_by_key(|(entity, component)| (component.get_name(), *entity));
+
+        let mut columns = vec!["time".to_string()];
+        let mut entities = Vec::new();
+        for (entity, component) in components {
+            let name = component.get_name();
+            let state = match physics_state.states.get(&entity) {
+                Some(state) => state.clone(),
+                None => component.get_state(),
+            };
+            let names: Vec<String> = state
+                .component_names()
+                .iter()
+                .map(|component| state_name(&name, component))
+                .collect();
+            columns.extend(names.iter().cloned());
+            columns.extend(names.iter().map(|name| format!("{}_dot", name)));
+            entities.push((entity, names.len()));
+        }
+        recorder.columns = columns;
+        recorder.entities = entities;
+        recorder.open();
+    }
+
+    let mut row = Vec::with_capacity(recorder.columns.len());
+    row.push(sim_time.time());
+    for (entity, size) in recorder.entities.iter() {
+        match query.get(*entity) {
+            Ok((_, component)) => {
+                let state = match physics_state.states.get(entity) {
+                    Some(state) => state.to_vec(),
+                    None => component.get_state().to_vec(),
+                };
+                let dstate = match physics_state.dstates.get(entity) {
+                    Some(dstate) => dstate.to_vec(),
+                    None => component.get_dstate().to_vec(),
+                };
+                row.extend(state);
+                row.extend(dstate);
+            }
+            Err(_) => {
+                // entity was removed, keep the columns aligned
+                row.extend(std::iter::repeat_n(f64::NAN, 2 * size));
+            }
+        }
+    }
+    recorder.write_row(&row);
+}
+
+fn save_recording_system<T: Component + Stateful>(
+    mut recorder: ResMut<Recorder<T>>,
+    exit_request: EventReader<ExitEvent>,
+) {
+    if !exit_request.is_empty() {
+        recorder.flush();
+    }
+}
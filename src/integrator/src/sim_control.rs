@@ -0,0 +1,169 @@
+use bevy::prelude::*;
+
+// time scales selected by the slower/faster keys
+const TIME_SCALES: [f64; 6] = [0.1, 0.25, 0.5, 1., 2., 4.];
+const MIN_TIME_SCALE: f64 = 0.1;
+const MAX_TIME_SCALE: f64 = 4.;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimMode {
+    Running,
+    Paused,
+}
+
+// Controls how many integrator steps integrator_schedule takes per fixed update.
+// The time scale is applied by skipping or repeating steps, so each step keeps the fixed dt.
+#[derive(Resource, Clone, Debug)]
+pub struct SimControl {
+    pub mode: SimMode,
+    pub time_scale: f64,
+    pending_steps: usize, // single steps requested while paused
+    accumulator: f64,     // fraction of a step carried over to the next fixed update
+}
+
+impl SimControl {
+    pub fn new(time_scale: f64) -> Self {
+        Self {
+            mode: SimMode::Running,
+            time_scale: time_scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE),
+            pending_steps: 0,
+            accumulator: 0.,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == SimMode::Paused
+    }
+
+    pub fn pause(&mut self) {
+        self.mode = SimMode::Paused;
+    }
+
+    pub fn resume(&mut self) {
+        self.mode = SimMode::Running;
+        self.pending_steps = 0;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.is_paused() {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    // pause and advance n steps, one per fixed update
+    pub fn step(&mut self, n_steps: usize) {
+        self.pause();
+        self.pending_steps += n_steps;
+    }
+
+    pub fn pending_steps(&self) -> usize {
+        self.pending_steps
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: f64) {
+        self.time_scale = time_scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+    }
+
+    pub fn faster(&mut self) {
+        let next = TIME_SCALES.iter().find(|scale| **scale > self.time_scale + 1e-9);
+        self.set_time_scale(*next.unwrap_or(&MAX_TIME_SCALE));
+    }
+
+    pub fn slower(&mut self) {
+        let next = TIME_SCALES
+            .iter()
+            .rev()
+            .find(|scale| **scale < self.time_scale - 1e-9);
+        self.set_time_scale(*next.unwrap_or(&MIN_TIME_SCALE));
+    }
+
+    // number of integrator steps to take in this fixed update
+    pub fn steps_due(&mut self) -> usize {
+        match self.mode {
+            SimMode::Paused => {
+                if self.pending_steps > 0 {
+                    self.pending_steps -= 1;
+                    1
+                } else {
+                    0
+                }
+            }
+            SimMode::Running => {
+                self.accumulator += self.time_scale;
+                let steps = self.accumulator.floor();
+                self.accumulator -= steps;
+                steps as usize
+            }
+        }
+    }
+}
+
+impl Default for SimControl {
+    fn default() -> Self {
+        Self::new(1.)
+    }
+}
+
+// Adds SimControl and the hotkeys to pause, single step and change the time scale
+pub struct SimControlPlugin {
+    pub pause_key: KeyCode,
+    pub step_key: KeyCode,
+    pub slower_key: KeyCode,
+    pub faster_key: KeyCode,
+}
+
+impl Default for SimControlPlugin {
+    fn default() -> Self {
+        Self {
+            pause_key: KeyCode::P,
+            step_key: KeyCode::N,
+            slower_key: KeyCode::BracketLeft,
+            faster_key: KeyCode::BracketRight,
+        }
+    }
+}
+
+impl Plugin for SimControlPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimControl>()
+            .insert_resource(SimControlKeys {
+                pause: self.pause_key,
+                step: self.step_key,
+                slower: self.slower_key,
+                faster: self.faster_key,
+            })
+            .add_systems(Update, sim_control_input_system);
+    }
+}
+
+#[derive(Resource, Clone, Copy)]
+struct SimControlKeys {
+    pause: KeyCode,
+    step: KeyCode,
+    slower: KeyCode,
+    faster: KeyCode,
+}
+
+fn sim_control_input_system(
+    keys: Res<SimControlKeys>,
+    input: Res<Input<KeyCode>>,
+    mut control: ResMut<SimControl>,
+) {
+    if input.just_pressed(keys.pause) {
+        control.toggle_pause();
+        info!("physics {}", if control.is_paused() { "paused" } else { "running" });
+    }
+    if input.just_pressed(keys.step) {
+        control.step(1);
+    }
+    if input.just_pressed(keys.slower) {
+        control.slower();
+        info!("physics time scale: {}", control.time_scale);
+    }
+    if input.just_pressed(keys.faster) {
+        control.faster();
+        info!("physics time scale: {}", control.time_scale);
+    }
+}
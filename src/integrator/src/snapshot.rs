@@ -0,0 +1,252 @@
+use std::{any::Any, collections::VecDeque, marker::PhantomData};
+
+use bevy::prelude::*;
+
+use crate::{
+    evaluate_state, implicit::ImplicitStep, integrator_schedule, IntegratedState, PhysicsState,
+    SimTime, Stateful,
+};
+
+type ComponentData = Box<dyn Any + Send + Sync>;
+
+// Keeps a ring buffer of snapshots of the last `duration` seconds, taken every `interval` steps.
+// Pressing `rewind_key` restores the snapshot `rewind_seconds` before the current time.
+pub struct SnapshotPlugin<T: Stateful> {
+    pub duration: f64,
+    pub interval: usize,
+    pub rewind_key: KeyCode,
+    pub rewind_seconds: f64,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Stateful> SnapshotPlugin<T> {
+    pub fn new(duration: f64, interval: usize, rewind_key: KeyCode, rewind_seconds: f64) -> Self {
+        Self {
+            duration,
+            interval,
+            rewind_key,
+            rewind_seconds,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Component + Stateful> Plugin for SnapshotPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SnapshotRegistry>()
+            .insert_resource(SnapshotBuffer::<T>::new(
+                self.duration,
+                self.interval,
+                self.rewind_key,
+                self.rewind_seconds,
+            ))
+            .add_systems(
+                FixedUpdate,
+                snapshot_buffer_system::<T>.after(integrator_schedule::<T>),
+            )
+            .add_systems(Update, rewind_input_system::<T>);
+    }
+}
+
+// Component types whose internal state is not part of the Stateful state, but is needed to
+// continue the simulation from a snapshot (e.g. filters and controls)
+#[derive(Resource, Default)]
+pub struct SnapshotRegistry {
+    captures: Vec<fn(&mut World) -> ComponentSnapshot>,
+}
+
+pub trait SnapshotAppExt {
+    fn register_snapshot_component<C: Component + Clone>(&mut self) -> &mut Self;
+}
+
+impl SnapshotAppExt for App {
+    fn register_snapshot_component<C: Component + Clone>(&mut self) -> &mut Self {
+        self.init_resource::<SnapshotRegistry>();
+        self.world
+            .resource_mut::<SnapshotRegistry>()
+            .captures
+            .push(capture_component::<C>);
+        self
+    }
+}
+
+struct ComponentSnapshot {
+    data: ComponentData,
+    restore: fn(&mut World, &ComponentData),
+}
+
+fn capture_component<C: Component + Clone>(world: &mut World) -> ComponentSnapshot {
+    let mut query = world.query::<(Entity, &C)>();
+    let components: Vec<(Entity, C)> = query
+        .iter(world)
+        .map(|(entity, component)| (entity, component.clone()))
+        .collect();
+    ComponentSnapshot {
+        data: Box::new(components),
+        restore: restore_component::<C>,
+    }
+}
+
+fn restore_component<C: Component + Clone>(world: &mut World, data: &ComponentData) {
+    if let Some(components) = data.downcast_ref::<Vec<(Entity, C)>>() {
+        for (entity, component) in components.iter() {
+            // entities removed since the snapshot are skipped
+            if let Some(mut current) = world.get_mut::<C>(*entity) {
+                *current = component.clone();
+            }
+        }
+    }
+}
+
+// the state includes the types registered with add_stateful
+pub struct Snapshot<T: Stateful> {
+    pub time: SimTime,
+    pub state: IntegratedState<T>,
+    components: Vec<ComponentSnapshot>,
+}
+
+pub fn take_snapshot<T: Stateful>(world: &mut World) -> Option<Snapshot<T>> {
+    let state = IntegratedState::<T>::read(world)?;
+    let time = world.get_resource::<SimTime>()?.clone();
+    let captures = world
+        .get_resource::<SnapshotRegistry>()
+        .map(|registry| registry.captures.clone())
+        .unwrap_or_default();
+    let components = captures.iter().map(|capture| capture(world)).collect();
+
+    Some(Snapshot {
+        time,
+        state,
+        components,
+    })
+}
+
+pub fn restore_snapshot<T: Stateful>(world: &mut World, snapshot: &Snapshot<T>) {
+    world.insert_resource(snapshot.time.clone());
+
+    // the Jacobian belongs to a different state now
+    if let Some(mut implicit) = world.get_resource_mut::<ImplicitStep>() {
+        implicit.reset_jacobian();
+    }
+
+    // evaluate the physics once, so the joints (and rendering) match the restored state
+    if world.contains_resource::<PhysicsState<T>>() {
+        evaluate_state(world, &snapshot.state, snapshot.time.time());
+    }
+
+    // restore components last, the evaluation above may have changed them
+    for component in snapshot.components.iter() {
+        (component.restore)(world, &component.data);
+    }
+}
+
+#[derive(Resource)]
+pub struct SnapshotBuffer<T: Stateful> {
+    pub duration: f64,
+    pub interval: usize,
+    pub rewind_key: KeyCode,
+    pub rewind_seconds: f64,
+    snapshots: VecDeque<Snapshot<T>>,
+    last_index: Option<usize>, // SimTime index of the last snapshot
+}
+
+impl<T: Stateful> SnapshotBuffer<T> {
+    pub fn new(duration: f64, interval: usize, rewind_key: KeyCode, rewind_seconds: f64) -> Self {
+        Self {
+            duration,
+            interval,
+            rewind_key,
+            rewind_seconds,
+            snapshots: VecDeque::new(),
+            last_index: None,
+        }
+    }
+
+    pub fn snapshots(&self) -> &VecDeque<Snapshot<T>> {
+        &self.snapshots
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.last_index = None;
+    }
+
+    fn push(&mut self, snapshot: Snapshot<T>) {
+        let time = snapshot.time.time();
+        self.last_index = Some(snapshot.time.index);
+        self.snapshots.push_back(snapshot);
+        while let Some(oldest) = self.snapshots.front() {
+            if time - oldest.time.time() <= self.duration {
+                break;
+            }
+            self.snapshots.pop_front();
+        }
+    }
+}
+
+pub fn snapshot_buffer_system<T: Stateful>(world: &mut World) {
+    let Some(index) = world.get_resource::<SimTime>().map(|time| time.index) else {
+        return;
+    };
+    let Some(buffer) = world.get_resource::<SnapshotBuffer<T>>() else {
+        return;
+    };
+
+    // only take one snapshot per integrator step
+    if buffer.last_index == Some(index) || !index.is_multiple_of(buffer.interval.max(1)) {
+        return;
+    }
+
+    if let Some(snapshot) = take_snapshot::<T>(world) {
+        world.resource_mut::<SnapshotBuffer<T>>().push(snapshot);
+    }
+}
+
+// Restore the latest buffered snapshot at least `seconds` before the current time.
+// Newer snapshots are discarded, so repeated rewinds go further back.
+// Returns false if there is nothing to rewind to.
+pub fn rewind<T: Stateful>(world: &mut World, seconds: f64) -> bool {
+    let Some(time) = world.get_resource::<SimTime>().map(|time| time.time()) else {
+        return false;
+    };
+    if !world.contains_resource::<SnapshotBuffer<T>>() {
+        return false;
+    }
+
+    world.resource_scope(|world, mut buffer: Mut<SnapshotBuffer<T>>| {
+        while buffer.snapshots.len() > 1 {
+            match buffer.snapshots.back() {
+                Some(snapshot) if snapshot.time.time() > time - seconds => {
+                    buffer.snapshots.pop_back();
+                }
+                _ => break,
+            }
+        }
+
+        match buffer.snapshots.back() {
+            Some(snapshot) => {
+                let index = snapshot.time.index;
+                restore_snapshot(world, snapshot);
+                buffer.last_index = Some(index);
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+fn rewind_input_system<T: Stateful>(world: &mut World) {
+    let Some((key, seconds)) = world
+        .get_resource::<SnapshotBuffer<T>>()
+        .map(|buffer| (buffer.rewind_key, buffer.rewind_seconds))
+    else {
+        return;
+    };
+
+    let pressed = world
+        .get_resource::<Input<KeyCode>>()
+        .is_some_and(|input| input.just_pressed(key));
+    if pressed {
+        rewind::<T>(world, seconds);
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    collections::HashMap,
+    ops::{Add, Mul},
+    sync::Arc,
+};
+
+use bevy::prelude::{Entity, World};
+
+use crate::{state_set::SolverState, PhysicsState, StateNorm, StateVector, Stateful};
+
+// Entity to index table. Maps cloned from each other share the same table, so arithmetic
+// between them works index by index without any lookups.
+#[derive(Default, Debug, Clone)]
+struct StateIndex {
+    entities: Vec<Entity>,
+    indices: HashMap<Entity, usize>,
+}
+
+// States stored contiguously, with a stable entity to index table
+pub struct StateMap<T: Stateful> {
+    index: Arc<StateIndex>,
+    states: Vec<T::State>,
+}
+
+impl<T: Stateful> StateMap<T> {
+    pub fn new() -> Self {
+        Self {
+            index: Arc::new(StateIndex::default()),
+            states: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    pub fn get(&self, entity: &Entity) -> Option<&T::State> {
+        self.index
+            .indices
+            .get(entity)
+            .map(|index| &self.states[*index])
+    }
+
+    pub fn get_mut(&mut self, entity: &Entity) -> Option<&mut T::State> {
+        self.index
+            .indices
+            .get(entity)
+            .map(|index| &mut self.states[*index])
+    }
+
+    // Replaces the state of a known entity in place. New entities are appended, which copies
+    // the index table if it is shared with other maps.
+    pub fn insert(&mut self, entity: Entity, state: T::State) {
+        match self.index.indices.get(&entity) {
+            Some(index) => self.states[*index] = state,
+            None => {
+                let index = Arc::make_mut(&mut self.index);
+                index.indices.insert(entity, index.entities.len());
+                index.entities.push(entity);
+                self.states.push(state);
+            }
+        }
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.index.entities
+    }
+
+    pub fn states(&self) -> &[T::State] {
+        &self.states
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T::State)> {
+        self.index.entities.iter().copied().zip(self.states.iter())
+    }
+
+    fn same_layout(&self, other: &StateMap<T>) -> bool {
+        Arc::ptr_eq(&self.index, &other.index) || self.index.entities == other.index.entities
+    }
+}
+
+impl<T: Stateful> SolverState for StateMap<T> {
+    // Entities missing from x are left unchanged
+    fn axpy(&mut self, a: f64, x: &Self) {
+        if self.same_layout(x) {
+            for (state, x_state) in self.states.iter_mut().zip(x.states.iter()) {
+                state.axpy(a, x_state);
+            }
+        } else {
+            for (entity, state) in self.index.entities.iter().zip(self.states.iter_mut()) {
+                if let Some(x_state) = x.get(entity) {
+                    state.axpy(a, x_state);
+                }
+            }
+        }
+    }
+
+    fn scale(&mut self, a: f64) {
+        for state in self.states.iter_mut() {
+            *state = state.clone() * a;
+        }
+    }
+
+    fn error_sum(
+        &self,
+        state_0: &Self,
+        state_1: &Self,
+        absolute_tolerance: f64,
+        relative_tolerance: f64,
+    ) -> (f64, usize) {
+        let mut sum = 0.;
+        for (entity, state_error) in self.iter() {
+            let size_0 = state_0.get(&entity).map_or(0., |state| state.norm());
+            let size_1 = state_1.get(&entity).map_or(0., |state| state.norm());
+            let scale = absolute_tolerance + relative_tolerance * size_0.max(size_1);
+            sum += (state_error.norm() / scale).powi(2);
+        }
+        (sum, self.len())
+    }
+
+    fn layout(&self) -> Vec<Entity> {
+        self.index.entities.clone()
+    }
+
+    fn to_components(&self) -> Vec<f64> {
+        let mut components = Vec::new();
+        for state in self.states.iter() {
+            components.extend(state.to_vec());
+        }
+        components
+    }
+
+    fn set_components(&mut self, components: &[f64]) -> usize {
+        let mut start = 0;
+        for state in self.states.iter_mut() {
+            let size = state.to_vec().len();
+            *state = T::State::from_slice(&components[start..start + size]);
+            start += size;
+        }
+        start
+    }
+
+    fn to_tangent(&self) -> Vec<f64> {
+        let mut tangent = Vec::new();
+        for state in self.states.iter() {
+            tangent.extend(state.tangent());
+        }
+        tangent
+    }
+
+    fn retract(&self, delta: &[f64]) -> Vec<f64> {
+        let mut components = Vec::new();
+        let mut start = 0;
+        for state in self.states.iter() {
+            let size = state.tangent().len();
+            components.extend(state.retract(&delta[start..start + size]));
+            start += size;
+        }
+        components
+    }
+
+    fn tangent_derivative(&self, components: &[f64], derivative: &[f64]) -> Vec<f64> {
+        let mut tangent = Vec::new();
+        let mut start = 0;
+        for state in self.states.iter() {
+            let end = start + state.to_vec().len();
+            tangent
+                .extend(state.tangent_derivative(&components[start..end], &derivative[start..end]));
+            start = end;
+        }
+        tangent
+    }
+
+    fn write_states(&self, world: &mut World) {
+        let states = &mut world.resource_mut::<PhysicsState<T>>().into_inner().states;
+        states.clone_from(self);
+        for state in states.states.iter_mut() {
+            state.normalize();
+        }
+    }
+
+    fn read_derivatives(&mut self, world: &World) {
+        self.clone_from(&world.resource::<PhysicsState<T>>().dstates);
+    }
+}
+
+impl<T: Stateful> Default for StateMap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Stateful> Clone for StateMap<T> {
+    fn clone(&self) -> Self {
+        Self {
+            index: self.index.clone(),
+            states: self.states.clone(),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        if !Arc::ptr_eq(&self.index, &source.index) {
+            self.index = source.index.clone();
+        }
+        self.states.clone_from(&source.states);
+    }
+}
+
+impl<T: Stateful> Mul<f64> for &StateMap<T> {
+    type Output = StateMap<T>;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        let mut result = self.clone();
+        result.scale(rhs);
+        result
+    }
+}
+
+impl<T: Stateful> Add for &StateMap<T> {
+    type Output = StateMap<T>;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        let mut result = self.clone();
+        result.axpy(1., rhs);
+        result
+    }
+}
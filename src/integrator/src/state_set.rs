@@ -0,0 +1,470 @@
+use std::any::Any;
+
+use bevy::{ecs::system::RunSystemOnce, prelude::*};
+
+use crate::{
+    initialize_state,
+    recorder::{record_system, Recorder},
+    PhysicsSchedule, PhysicsScheduleExt, PhysicsState, StateMap, StateVector, Stateful,
+};
+
+// The operations the solvers need on the state they integrate
+pub trait SolverState: Clone + Send + Sync + 'static {
+    // self = self + a * x, in place
+    fn axpy(&mut self, a: f64, x: &Self);
+
+    // self = a * self, in place
+    fn scale(&mut self, a: f64);
+
+    // self = a * x, reusing the allocation of self
+    fn set_scaled(&mut self, a: f64, x: &Self) {
+        self.clone_from(x);
+        self.scale(a);
+    }
+
+    // sum of the squared errors scaled by the tolerances, and the number of terms
+    fn error_sum(
+        &self,
+        state_0: &Self,
+        state_1: &Self,
+        absolute_tolerance: f64,
+        relative_tolerance: f64,
+    ) -> (f64, usize);
+
+    // entities in the order of the flattened components
+    fn layout(&self) -> Vec<Entity>;
+
+    fn to_components(&self) -> Vec<f64>;
+
+    // overwrite the states from flattened components, returns the number of components used
+    fn set_components(&mut self, components: &[f64]) -> usize;
+
+    // flattened tangent coordinates, see StateVector::tangent
+    fn to_tangent(&self) -> Vec<f64>;
+
+    // flattened components of the states moved by a perturbation in tangent coordinates
+    fn retract(&self, delta: &[f64]) -> Vec<f64>;
+
+    // derivative of the tangent coordinates about these states, from the flattened components
+    // and derivatives of perturbed states
+    fn tangent_derivative(&self, components: &[f64], derivative: &[f64]) -> Vec<f64>;
+
+    // assign the states to the PhysicsState resources
+    fn write_states(&self, world: &mut World);
+
+    // copy the state derivatives from the PhysicsState resources
+    fn read_derivatives(&mut self, world: &World);
+}
+
+// Type erased SolverState, for the additional Stateful types registered with add_stateful
+trait DynState: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn clone_box(&self) -> Box<dyn DynState>;
+    fn clone_from_dyn(&mut self, source: &dyn DynState);
+    fn axpy_dyn(&mut self, a: f64, x: &dyn DynState);
+    fn scale_dyn(&mut self, a: f64);
+    fn error_sum_dyn(
+        &self,
+        state_0: &dyn DynState,
+        state_1: &dyn DynState,
+        absolute_tolerance: f64,
+        relative_tolerance: f64,
+    ) -> (f64, usize);
+    fn layout_dyn(&self) -> Vec<Entity>;
+    fn to_components_dyn(&self) -> Vec<f64>;
+    fn set_components_dyn(&mut self, components: &[f64]) -> usize;
+    fn to_tangent_dyn(&self) -> Vec<f64>;
+    fn retract_dyn(&self, delta: &[f64]) -> Vec<f64>;
+    fn tangent_derivative_dyn(&self, components: &[f64], derivative: &[f64]) -> Vec<f64>;
+    fn write_states_dyn(&self, world: &mut World);
+    fn read_derivatives_dyn(&mut self, world: &World);
+}
+
+// the registry keeps the types in a fixed order, so the downcasts only fail on a bug
+const MISMATCHED_STATE: &str = "mismatched state types";
+
+impl<S: SolverState> DynState for S {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn clone_box(&self) -> Box<dyn DynState> {
+        Box::new(self.clone())
+    }
+
+    fn clone_from_dyn(&mut self, source: &dyn DynState) {
+        self.clone_from(source.as_any().downcast_ref::<S>().expect(MISMATCHED_STATE));
+    }
+
+    fn axpy_dyn(&mut self, a: f64, x: &dyn DynState) {
+        self.axpy(a, x.as_any().downcast_ref::<S>().expect(MISMATCHED_STATE));
+    }
+
+    fn scale_dyn(&mut self, a: f64) {
+        self.scale(a);
+    }
+
+    fn error_sum_dyn(
+        &self,
+        state_0: &dyn DynState,
+        state_1: &dyn DynState,
+        absolute_tolerance: f64,
+        relative_tolerance: f64,
+    ) -> (f64, usize) {
+        self.error_sum(
+            state_0.as_any().downcast_ref::<S>().expect(MISMATCHED_STATE),
+            state_1.as_any().downcast_ref::<S>().expect(MISMATCHED_STATE),
+            absolute_tolerance,
+            relative_tolerance,
+        )
+    }
+
+    fn layout_dyn(&self) -> Vec<Entity> {
+        self.layout()
+    }
+
+    fn to_components_dyn(&self) -> Vec<f64> {
+        self.to_components()
+    }
+
+    fn set_components_dyn(&mut self, components: &[f64]) -> usize {
+        self.set_components(components)
+    }
+
+    fn to_tangent_dyn(&self) -> Vec<f64> {
+        self.to_tangent()
+    }
+
+    fn retract_dyn(&self, delta: &[f64]) -> Vec<f64> {
+        self.retract(delta)
+    }
+
+    fn tangent_derivative_dyn(&self, components: &[f64], derivative: &[f64]) -> Vec<f64> {
+        self.tangent_derivative(components, derivative)
+    }
+
+    fn write_states_dyn(&self, world: &mut World) {
+        self.write_states(world);
+    }
+
+    fn read_derivatives_dyn(&mut self, world: &World) {
+        self.read_derivatives(world);
+    }
+}
+
+// The state advanced by integrator_schedule::<T>: the states of T and of every type
+// registered with add_stateful, so they all share the same solver stages.
+pub struct IntegratedState<T: Stateful> {
+    pub states: StateMap<T>,
+    extra: Vec<Box<dyn DynState>>,
+}
+
+impl<T: Stateful> IntegratedState<T> {
+    // Read the current states from the world. The PhysicsState of registered types is
+    // initialized from their components the first time. Returns None before T is initialized.
+    pub fn read(world: &mut World) -> Option<Self> {
+        let states = world.get_resource::<PhysicsState<T>>()?.states.clone();
+        let registered = world
+            .get_resource::<StatefulRegistry>()
+            .map(|registry| registry.entries.clone())
+            .unwrap_or_default();
+        let extra = registered
+            .iter()
+            .map(|entry| (entry.read_states)(world))
+            .collect();
+        Some(Self { states, extra })
+    }
+
+    // names of the flattened components, in the order of to_components
+    pub fn component_names(world: &World) -> Vec<String>
+    where
+        T: Component,
+    {
+        let mut names = component_names::<T>(world);
+        if let Some(registry) = world.get_resource::<StatefulRegistry>() {
+            for entry in registry.entries.iter() {
+                names.extend((entry.component_names)(world));
+            }
+        }
+        names
+    }
+
+    // names of the flattened tangent coordinates, in the order of to_tangent
+    pub fn tangent_names(world: &World) -> Vec<String>
+    where
+        T: Component,
+    {
+        let mut names = tangent_names::<T>(world);
+        if let Some(registry) = world.get_resource::<StatefulRegistry>() {
+            for entry in registry.entries.iter() {
+                names.extend((entry.tangent_names)(world));
+            }
+        }
+        names
+    }
+
+    // the sizes of the components and the tangent coordinates of T and each registered type
+    fn part_sizes(&self) -> Vec<(usize, usize)> {
+        let mut sizes = vec![(
+            self.states.to_components().len(),
+            self.states.to_tangent().len(),
+        )];
+        for states in self.extra.iter() {
+            sizes.push((
+                states.to_components_dyn().len(),
+                states.to_tangent_dyn().len(),
+            ));
+        }
+        sizes
+    }
+
+    // extra state of type U, if U is registered with add_stateful
+    pub fn get<U: Stateful>(&self) -> Option<&StateMap<U>> {
+        self.extra
+            .iter()
+            .find_map(|states| states.as_any().downcast_ref::<StateMap<U>>())
+    }
+}
+
+impl<T: Stateful> Clone for IntegratedState<T> {
+    fn clone(&self) -> Self {
+        Self {
+            states: self.states.clone(),
+            extra: self.extra.iter().map(|states| states.clone_box()).collect(),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.states.clone_from(&source.states);
+        if self.extra.len() == source.extra.len() {
+            for (states, source_states) in self.extra.iter_mut().zip(source.extra.iter()) {
+                states.clone_from_dyn(source_states.as_ref());
+            }
+        } else {
+            self.extra = source.extra.iter().map(|states| states.clone_box()).collect();
+        }
+    }
+}
+
+impl<T: Stateful> SolverState for IntegratedState<T> {
+    fn axpy(&mut self, a: f64, x: &Self) {
+        self.states.axpy(a, &x.states);
+        for (states, x_states) in self.extra.iter_mut().zip(x.extra.iter()) {
+            states.axpy_dyn(a, x_states.as_ref());
+        }
+    }
+
+    fn scale(&mut self, a: f64) {
+        self.states.scale(a);
+        for states in self.extra.iter_mut() {
+            states.scale_dyn(a);
+        }
+    }
+
+    fn error_sum(
+        &self,
+        state_0: &Self,
+        state_1: &Self,
+        absolute_tolerance: f64,
+        relative_tolerance: f64,
+    ) -> (f64, usize) {
+        let (mut sum, mut count) = self.states.error_sum(
+            &state_0.states,
+            &state_1.states,
+            absolute_tolerance,
+            relative_tolerance,
+        );
+        for ((error, states_0), states_1) in self
+            .extra
+            .iter()
+            .zip(state_0.extra.iter())
+            .zip(state_1.extra.iter())
+        {
+            let (extra_sum, extra_count) = error.error_sum_dyn(
+                states_0.as_ref(),
+                states_1.as_ref(),
+                absolute_tolerance,
+                relative_tolerance,
+            );
+            sum += extra_sum;
+            count += extra_count;
+        }
+        (sum, count)
+    }
+
+    fn layout(&self) -> Vec<Entity> {
+        let mut layout = self.states.layout();
+        for states in self.extra.iter() {
+            layout.extend(states.layout_dyn());
+        }
+        layout
+    }
+
+    fn to_components(&self) -> Vec<f64> {
+        let mut components = self.states.to_components();
+        for states in self.extra.iter() {
+            components.extend(states.to_components_dyn());
+        }
+        components
+    }
+
+    fn set_components(&mut self, components: &[f64]) -> usize {
+        let mut start = self.states.set_components(components);
+        for states in self.extra.iter_mut() {
+            start += states.set_components_dyn(&components[start..]);
+        }
+        start
+    }
+
+    fn to_tangent(&self) -> Vec<f64> {
+        let mut tangent = self.states.to_tangent();
+        for states in self.extra.iter() {
+            tangent.extend(states.to_tangent_dyn());
+        }
+        tangent
+    }
+
+    fn retract(&self, delta: &[f64]) -> Vec<f64> {
+        let sizes = self.part_sizes();
+        let mut start = sizes[0].1;
+        let mut components = self.states.retract(&delta[..start]);
+        for (states, (_, tangent_size)) in self.extra.iter().zip(sizes[1..].iter()) {
+            components.extend(states.retract_dyn(&delta[start..start + tangent_size]));
+            start += tangent_size;
+        }
+        components
+    }
+
+    fn tangent_derivative(&self, components: &[f64], derivative: &[f64]) -> Vec<f64> {
+        let sizes = self.part_sizes();
+        let size = sizes[0].0;
+        let mut tangent =
+            self.states
+                .tangent_derivative(&components[..size], &derivative[..size]);
+        let mut start = size;
+        for (states, (size, _)) in self.extra.iter().zip(sizes[1..].iter()) {
+            let end = start + size;
+            tangent.extend(
+                states.tangent_derivative_dyn(&components[start..end], &derivative[start..end]),
+            );
+            start = end;
+        }
+        tangent
+    }
+
+    fn write_states(&self, world: &mut World) {
+        self.states.write_states(world);
+        for states in self.extra.iter() {
+            states.write_states_dyn(world);
+        }
+    }
+
+    fn read_derivatives(&mut self, world: &World) {
+        self.states.read_derivatives(world);
+        for states in self.extra.iter_mut() {
+            states.read_derivatives_dyn(world);
+        }
+    }
+}
+
+// Additional Stateful types integrated together with the main type of integrator_schedule
+#[derive(Resource, Default)]
+pub struct StatefulRegistry {
+    entries: Vec<RegisteredState>,
+}
+
+#[derive(Clone, Copy)]
+struct RegisteredState {
+    read_states: fn(&mut World) -> Box<dyn DynState>,
+    post_step: fn(&mut World),
+    component_names: fn(&World) -> Vec<String>,
+    tangent_names: fn(&World) -> Vec<String>,
+}
+
+fn read_states<U: Component + Stateful>(world: &mut World) -> Box<dyn DynState> {
+    if !world.contains_resource::<PhysicsState<U>>() {
+        world.run_system_once(initialize_state::<U>);
+    }
+    Box::new(world.resource::<PhysicsState<U>>().states.clone())
+}
+
+// "name.component" for every component of the PhysicsState of U
+fn component_names<U: Component + Stateful>(world: &World) -> Vec<String> {
+    state_names::<U>(world, |state| state.component_names())
+}
+
+// "name.coordinate" for every tangent coordinate of the PhysicsState of U
+fn tangent_names<U: Component + Stateful>(world: &World) -> Vec<String> {
+    state_names::<U>(world, |state| state.tangent_names())
+}
+
+fn state_names<U: Component + Stateful>(
+    world: &World,
+    names_of: fn(&U::State) -> Vec<String>,
+) -> Vec<String> {
+    let Some(physics_state) = world.get_resource::<PhysicsState<U>>() else {
+        return Vec::new();
+    };
+    let mut names = Vec::new();
+    for (entity, state) in physics_state.states.iter() {
+        let name = world
+            .get::<U>(entity)
+            .map_or(format!("{:?}", entity), |component| component.get_name());
+        names.extend(
+            names_of(state)
+                .iter()
+                .map(|component| state_name(&name, component)),
+        );
+    }
+    names
+}
+
+// "name.component", or just the name for a state with a single unnamed component
+pub(crate) fn state_name(name: &str, component: &str) -> String {
+    if component.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", name, component)
+    }
+}
+
+// systems that follow each step when stepping manually
+pub(crate) fn post_step<U: Component + Stateful>(world: &mut World) {
+    if world.contains_resource::<Recorder<U>>() {
+        world.run_system_once(record_system::<U>);
+    }
+}
+
+pub(crate) fn registered_post_steps(world: &mut World) {
+    let registered = world
+        .get_resource::<StatefulRegistry>()
+        .map(|registry| registry.entries.clone())
+        .unwrap_or_default();
+    for entry in registered.iter() {
+        (entry.post_step)(world);
+    }
+}
+
+pub trait StatefulAppExt {
+    // Integrate the states of U together with the joints (or whichever type the integrator
+    // schedule runs for), e.g. in a simulation setup.
+    fn add_stateful<U: Component + Stateful>(&mut self) -> &mut Self;
+}
+
+impl StatefulAppExt for App {
+    fn add_stateful<U: Component + Stateful>(&mut self) -> &mut Self {
+        self.init_resource::<StatefulRegistry>();
+        self.world
+            .resource_mut::<StatefulRegistry>()
+            .entries
+            .push(RegisteredState {
+                read_states: read_states::<U>,
+                post_step: post_step::<U>,
+                component_names: component_names::<U>,
+                tangent_names: tangent_names::<U>,
+            });
+        self.edit_schedule(PhysicsSchedule, |schedule| {
+            schedule.add_stateful_systems::<U>();
+        })
+    }
+}
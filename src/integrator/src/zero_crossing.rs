@@ -0,0 +1,246 @@
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+
+use crate::{
+    evaluate_state, implicit::ImplicitStep, solve_step, AdaptiveStep, IntegratedState, Solver,
+    StateMap, Stateful,
+};
+
+// Adds the ZeroCrossings resource, which systems use to register event functions of the state.
+// The integrator checks them after every step and sends a ZeroCrossingEvent for each sign change.
+pub struct ZeroCrossingPlugin<T: Stateful> {
+    pub method: CrossingMethod,
+    pub log: bool, // log every crossing
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Stateful> ZeroCrossingPlugin<T> {
+    pub fn new(method: CrossingMethod, log: bool) -> Self {
+        Self {
+            method,
+            log,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Stateful> Plugin for ZeroCrossingPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ZeroCrossingEvent>()
+            .insert_resource(ZeroCrossings::<T>::new(self.method));
+        if self.log {
+            app.add_systems(PostUpdate, log_zero_crossings_system);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrossingDirection {
+    Rising,  // negative to positive
+    Falling, // positive to negative
+    Both,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CrossingMethod {
+    // linear interpolation of the function between the start and end of the step
+    Interpolation,
+    // repeat the step with bisected step sizes until the crossing is bracketed within tolerance
+    // (in seconds), then interpolate. Every iteration costs a full solver step.
+    Bisection {
+        tolerance: f64,
+        max_iterations: usize,
+    },
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct ZeroCrossingEvent {
+    pub name: String,
+    pub time: f64,
+    pub direction: CrossingDirection,
+}
+
+type CrossingFunction<T> = Box<dyn Fn(&StateMap<T>) -> f64 + Send + Sync>;
+
+struct ZeroCrossingFunction<T: Stateful> {
+    name: String,
+    direction: CrossingDirection,
+    function: CrossingFunction<T>,
+}
+
+#[derive(Resource)]
+pub struct ZeroCrossings<T: Stateful> {
+    pub method: CrossingMethod,
+    functions: Vec<ZeroCrossingFunction<T>>,
+}
+
+impl<T: Stateful> ZeroCrossings<T> {
+    pub fn new(method: CrossingMethod) -> Self {
+        Self {
+            method,
+            functions: Vec::new(),
+        }
+    }
+
+    // Register a scalar function of the state. An event is sent whenever it changes sign in
+    // the given direction. Functions with the same name are replaced.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        direction: CrossingDirection,
+        function: impl Fn(&StateMap<T>) -> f64 + Send + Sync + 'static,
+    ) {
+        let name = name.into();
+        self.remove(&name);
+        self.functions.push(ZeroCrossingFunction {
+            name,
+            direction,
+            function: Box::new(function),
+        });
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.functions.retain(|function| function.name != name);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+}
+
+// the direction of a sign change from value_0 to value_1, if there is one
+fn sign_change(value_0: f64, value_1: f64) -> Option<CrossingDirection> {
+    if !value_0.is_finite() || !value_1.is_finite() {
+        None
+    } else if value_0 < 0. && value_1 >= 0. {
+        Some(CrossingDirection::Rising)
+    } else if value_0 > 0. && value_1 <= 0. {
+        Some(CrossingDirection::Falling)
+    } else {
+        None
+    }
+}
+
+// zero of the line through (t_0, value_0) and (t_1, value_1)
+fn interpolate(t_0: f64, value_0: f64, t_1: f64, value_1: f64) -> f64 {
+    if value_1 == value_0 {
+        return t_1;
+    }
+    t_0 - value_0 * (t_1 - t_0) / (value_1 - value_0)
+}
+
+// Look for sign changes of the registered functions over the step from state_0 at t_0 to
+// state_1 at t_0 + dt, and send an event for each, in order of time.
+pub(crate) fn detect_zero_crossings<T: Stateful>(
+    world: &mut World,
+    state_0: &IntegratedState<T>,
+    state_1: &IntegratedState<T>,
+    t_0: f64,
+    dt: f64,
+) {
+    let Some(crossings) = world.remove_resource::<ZeroCrossings<T>>() else {
+        return;
+    };
+
+    let mut events = Vec::new();
+    let mut resync = false;
+    for function in crossings.functions.iter() {
+        let value_0 = (function.function)(&state_0.states);
+        let value_1 = (function.function)(&state_1.states);
+        let Some(direction) = sign_change(value_0, value_1) else {
+            continue;
+        };
+        if function.direction != CrossingDirection::Both && function.direction != direction {
+            continue;
+        }
+
+        let time = match crossings.method {
+            CrossingMethod::Interpolation => interpolate(t_0, value_0, t_0 + dt, value_1),
+            CrossingMethod::Bisection {
+                tolerance,
+                max_iterations,
+            } => {
+                resync = true;
+                bisect(
+                    world,
+                    function,
+                    state_0,
+                    t_0,
+                    (0., value_0),
+                    (dt, value_1),
+                    tolerance,
+                    max_iterations,
+                )
+            }
+        };
+        events.push(ZeroCrossingEvent {
+            name: function.name.clone(),
+            time,
+            direction,
+        });
+    }
+
+    // the bisection steps left the components at an intermediate state
+    if resync {
+        evaluate_state(world, state_1, t_0 + dt);
+    }
+
+    events.sort_by(|a, b| a.time.total_cmp(&b.time));
+    world.send_event_batch(events);
+    world.insert_resource(crossings);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn bisect<T: Stateful>(
+    world: &mut World,
+    function: &ZeroCrossingFunction<T>,
+    state_0: &IntegratedState<T>,
+    t_0: f64,
+    mut low: (f64, f64),
+    mut high: (f64, f64),
+    tolerance: f64,
+    max_iterations: usize,
+) -> f64 {
+    let Some(solver) = world.get_resource::<Solver>().copied() else {
+        return t_0 + interpolate(low.0, low.1, high.0, high.1);
+    };
+    // the repeated steps shouldn't change the step size control of the adaptive solver, or the
+    // Jacobian and counts of the implicit solvers
+    let adaptive_step = world.get_resource::<AdaptiveStep>().cloned();
+    let implicit_step = world.get_resource::<ImplicitStep>().cloned();
+
+    for _ in 0..max_iterations {
+        if high.0 - low.0 <= tolerance {
+            break;
+        }
+        let h = 0.5 * (low.0 + high.0);
+        let state = solve_step(world, solver, state_0, t_0 + h, h);
+        let value = (function.function)(&state.states);
+        if !value.is_finite() {
+            break;
+        }
+        if sign_change(low.1, value).is_some() {
+            high = (h, value);
+        } else {
+            low = (h, value);
+        }
+    }
+
+    if let Some(adaptive_step) = adaptive_step {
+        world.insert_resource(adaptive_step);
+    }
+    if let Some(implicit_step) = implicit_step {
+        world.insert_resource(implicit_step);
+    }
+    t_0 + interpolate(low.0, low.1, high.0, high.1)
+}
+
+fn log_zero_crossings_system(mut events: EventReader<ZeroCrossingEvent>) {
+    for event in events.read() {
+        info!(
+            "zero crossing {} ({:?}) at t = {:.6}",
+            event.name, event.direction, event.time
+        );
+    }
+}
@@ -0,0 +1,58 @@
+// shared by the benchmarks
+use bevy::prelude::*;
+
+use rigid_body::{
+    joint::{Base, Joint},
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+// The joint layout of car::build with a floating chassis, without meshes and forces
+pub fn car_tree_startup_system(mut commands: Commands, n_cars: usize) {
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    for car in 0..n_cars {
+        let chassis_inertia = Inertia::new(
+            1000.,
+            Vector::new(0., 0., 0.),
+            Matrix::from_diagonal(&Vector::new(400., 2000., 2000.)),
+        );
+        let mut chassis =
+            Joint::floating("chassis".to_string(), chassis_inertia, Xform::identity());
+        chassis
+            .floating
+            .set_pose([0., 5. * car as f64, 0.55], [0., 0., 1.57]);
+        let chassis_id = commands.spawn(chassis).set_parent(base_id).id();
+
+        let corners = [
+            ("fl", 1.5, 0.8),
+            ("fr", 1.5, -0.8),
+            ("rl", -1.5, 0.8),
+            ("rr", -1.5, -0.8),
+        ];
+        for (name, x, y) in corners {
+            let mut parent_id = chassis_id;
+            let mut xt_susp = Xform::new(Vector::new(x, y, -0.3), Matrix::identity());
+
+            // the front corners are steered
+            if x > 0. {
+                let steer = Joint::rz(format!("steer_{}", name), Inertia::zero(), xt_susp);
+                parent_id = commands.spawn(steer).set_parent(parent_id).id();
+                xt_susp = Xform::identity();
+            }
+
+            let susp_inertia = Inertia::new(20., Vector::zeros(), Matrix::identity());
+            let susp = Joint::pz(format!("susp_{}", name), susp_inertia, xt_susp);
+            let susp_id = commands.spawn(susp).set_parent(parent_id).id();
+
+            let wheel_inertia = Inertia::new(
+                10.,
+                Vector::zeros(),
+                Matrix::from_diagonal(&Vector::new(0.5, 1., 0.5)),
+            );
+            let mut wheel = Joint::ry(format!("wheel_{}", name), wheel_inertia, Xform::identity());
+            wheel.qd = 10.;
+            commands.spawn(wheel).set_parent(susp_id);
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::{hint::black_box, time::Instant};
+
+use bevy::{
+    ecs::schedule::ExecutorKind,
+    prelude::*,
+    tasks::{ComputeTaskPool, TaskPool},
+};
+
+use bevy_integrator::{step, SimTime, Solver};
+use rigid_body::{
+    algorithms::{apply_external_update, loop_1_update, loop_2_update, loop_3_update},
+    joint::{Base, Joint},
+    plugin::HeadlessRigidBodyPlugin,
+    structure::{external_forces_loop_23, loop_1, JointTree},
+};
+
+mod common;
+use common::car_tree_startup_system;
+
+// Compares the articulated-body passes of one physics evaluation (loop 1, the external forces,
+// loops 2 and 3) done by the old recursive traversal of the Children hierarchy with the passes
+// over the flattened JointTree, on the joint trees of 1, 2 and 10 cars. The flattened passes are
+// also timed on the calling thread only, to show the speedup of evaluating the cars in chunks on
+// the compute task pool, which needs more than one thread.
+// Run with `cargo bench -p rigid_body --bench joint_tree`.
+
+const CAR_COUNTS: [usize; 3] = [1, 2, 10];
+const ITERATIONS: usize = 20_000;
+
+fn main() {
+    let threads = ComputeTaskPool::get_or_init(TaskPool::default).thread_num();
+    println!("compute threads: {}", threads);
+    for n_cars in CAR_COUNTS {
+        let mut app = App::new();
+        app.add_plugins(HeadlessRigidBodyPlugin {
+            time: SimTime::new(0.002, 0.0, None),
+            solver: Solver::RK4,
+            simulation_setup: vec![],
+        })
+        .add_systems(Startup, move |commands: Commands| {
+            car_tree_startup_system(commands, n_cars)
+        });
+        app.update();
+
+        // one step to flatten the joint tree and initialize the physics state
+        step::<Joint>(&mut app.world, 1);
+        let n_joints = app.world.query::<&Joint>().iter(&app.world).count();
+        println!("cars: {}, joints: {}", n_cars, n_joints);
+
+        let recursive = time_per_evaluation(&mut app.world, "recursive", recursive_passes);
+        let flattened = time_per_evaluation(
+            &mut app.world,
+            "flattened",
+            (loop_1, external_forces_loop_23).chain(),
+        );
+
+        // all subtrees on the calling thread
+        app.world
+            .resource_mut::<JointTree>()
+            .set_parallel_min_joints(usize::MAX);
+        let serial = time_per_evaluation(
+            &mut app.world,
+            "serial",
+            (loop_1, external_forces_loop_23).chain(),
+        );
+        println!(
+            "speedup: {:.1}x over recursive, {:.1}x over serial",
+            recursive / flattened,
+            serial / flattened
+        );
+    }
+}
+
+// returns the average time per evaluation in microseconds
+fn time_per_evaluation<M>(world: &mut World, name: &str, passes: impl IntoSystemConfigs<M>) -> f64 {
+    // run the systems one after the other, so only the traversals are compared
+    let mut schedule = Schedule::default();
+    schedule
+        .set_executor_kind(ExecutorKind::SingleThreaded)
+        .add_systems(passes);
+    // warm up, this also builds the schedule
+    for _ in 0..ITERATIONS / 10 {
+        schedule.run(world);
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        schedule.run(world);
+        black_box(&*world);
+    }
+    let micros = start.elapsed().as_secs_f64() * 1e6 / ITERATIONS as f64;
+    println!("  {:<12} {:>10.3} us/evaluation", name, micros);
+    micros
+}
+
+type OutwardFn = fn(&mut Joint, &Joint);
+type InwardFn = fn(&mut Joint, Option<&mut Joint>);
+
+// The passes before the joint tree was flattened: a recursive walk down the Children of each
+// Base, looking up every parent and child pair in the query.
+fn recursive_passes(
+    base_query: Query<Entity, With<Base>>,
+    joint_children_query: Query<&Children, With<Joint>>,
+    mut joint_query: Query<&mut Joint>,
+) {
+    let passes: [(Option<OutwardFn>, Option<InwardFn>); 4] = [
+        (Some(loop_1_update), None),
+        (Some(apply_external_update), None),
+        (None, Some(loop_2_update)),
+        (Some(loop_3_update), None),
+    ];
+    for (fn_out, fn_in) in passes {
+        for base_entity in base_query.iter() {
+            if let Ok(children) = joint_children_query.get(base_entity) {
+                for child_entity in children.iter() {
+                    recursive_loop(
+                        base_entity,
+                        child_entity,
+                        &joint_children_query,
+                        &mut joint_query,
+                        fn_out,
+                        fn_in,
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn recursive_loop(
+    parent_entity: Entity,
+    joint_entity: &Entity,
+    joint_children_query: &Query<&Children, With<Joint>>,
+    joint_query: &mut Query<&mut Joint>,
+    fn_out: Option<OutwardFn>,
+    fn_in: Option<InwardFn>,
+) {
+    if let Some(f) = fn_out {
+        if let Ok([parent, mut joint]) = joint_query.get_many_mut([parent_entity, *joint_entity]) {
+            f(&mut joint, &parent);
+        }
+    }
+
+    if let Ok(children) = joint_children_query.get(*joint_entity) {
+        for child_entity in children.iter() {
+            recursive_loop(
+                *joint_entity,
+                child_entity,
+                joint_children_query,
+                joint_query,
+                fn_out,
+                fn_in,
+            );
+        }
+    }
+
+    if let Some(f) = fn_in {
+        if let Ok([mut parent, mut joint]) =
+            joint_query.get_many_mut([parent_entity, *joint_entity])
+        {
+            f(&mut joint, Some(&mut parent));
+        }
+    }
+}
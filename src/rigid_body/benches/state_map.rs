@@ -0,0 +1,157 @@
+use std::{collections::HashMap, hint::black_box, time::Instant};
+
+use bevy::prelude::*;
+
+use bevy_integrator::{step, PhysicsState, SimTime, Solver, SolverState, StateMap};
+use rigid_body::{
+    joint::{Joint, JointState},
+    plugin::HeadlessRigidBodyPlugin,
+};
+
+mod common;
+use common::car_tree_startup_system;
+
+// Compares the RK4 state arithmetic of the old HashMap based StateMap with the dense StateMap,
+// on the joint tree of two cars from the car example (base + 2 x 11 joints).
+// Run with `cargo bench -p rigid_body --bench state_map`.
+
+const N_CARS: usize = 2;
+const DT: f64 = 0.002;
+const ITERATIONS: usize = 100_000;
+const PHYSICS_STEPS: usize = 5_000;
+
+fn main() {
+    let mut app = App::new();
+    app.add_plugins(HeadlessRigidBodyPlugin {
+        time: SimTime::new(DT, 0.0, None),
+        solver: Solver::RK4,
+        simulation_setup: vec![],
+    })
+    .add_systems(Startup, |commands: Commands| {
+        car_tree_startup_system(commands, N_CARS)
+    });
+    app.update();
+
+    // one step to initialize the physics state
+    step::<Joint>(&mut app.world, 1);
+    let physics_state = app.world.resource::<PhysicsState<Joint>>();
+    let states = physics_state.states.clone();
+    let dstates = physics_state.dstates.clone();
+    println!("joints: {}", states.len());
+
+    let hash_states: HashMap<Entity, JointState> = states
+        .iter()
+        .map(|(entity, state)| (entity, state.clone()))
+        .collect();
+    let hash_dstates: HashMap<Entity, JointState> = dstates
+        .iter()
+        .map(|(entity, state)| (entity, state.clone()))
+        .collect();
+
+    let hash_map = time_per_step("HashMap rk4 arithmetic", ITERATIONS, || {
+        black_box(hash_map_rk4(&hash_states, &hash_dstates, DT));
+    });
+
+    let mut stage = states.clone();
+    let mut physics_states = states.clone();
+    let mut k: Vec<StateMap<Joint>> = (0..4).map(|_| dstates.clone()).collect();
+    let state_map = time_per_step("StateMap rk4 arithmetic", ITERATIONS, || {
+        black_box(state_map_rk4(
+            &states,
+            &dstates,
+            &mut stage,
+            &mut physics_states,
+            &mut k,
+            DT,
+        ));
+    });
+    println!("speedup: {:.1}x", hash_map / state_map);
+
+    // the full step including the physics, for reference
+    time_per_step("full rk4 step", PHYSICS_STEPS, || {
+        step::<Joint>(&mut app.world, 1);
+    });
+}
+
+// returns the average time per iteration in microseconds
+fn time_per_step(name: &str, iterations: usize, mut f: impl FnMut()) -> f64 {
+    // warm up
+    for _ in 0..iterations / 10 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    let micros = start.elapsed().as_secs_f64() * 1e6 / iterations as f64;
+    println!("{:<24} {:>10.3} us/step", name, micros);
+    micros
+}
+
+// The state handling of one rk4 step before the redesign: every evaluation copies the state
+// in and the derivatives out, and every Add/Mul builds a new hash map.
+fn hash_map_rk4(
+    state: &HashMap<Entity, JointState>,
+    dstate: &HashMap<Entity, JointState>,
+    dt: f64,
+) -> HashMap<Entity, JointState> {
+    let mul = |a: &HashMap<Entity, JointState>, b: f64| {
+        let mut result = HashMap::new();
+        for (entity, state) in a.iter() {
+            result.insert(*entity, state.clone() * b);
+        }
+        result
+    };
+    let add = |a: &HashMap<Entity, JointState>, b: &HashMap<Entity, JointState>| {
+        let mut result = HashMap::new();
+        for (entity, state) in a.iter() {
+            result.insert(*entity, state.clone() + b.get(entity).unwrap().clone());
+        }
+        result
+    };
+    let evaluate = |state: &HashMap<Entity, JointState>| {
+        black_box(state.clone());
+        dstate.clone()
+    };
+
+    let k1 = evaluate(&state.clone());
+    let k2 = evaluate(&add(state, &mul(&k1, dt * 0.5)));
+    let k3 = evaluate(&add(state, &mul(&k2, dt * 0.5)));
+    let k4 = evaluate(&add(state, &mul(&k3, dt)));
+    let state_change = add(&add(&add(&k1, &mul(&k2, 2.)), &mul(&k3, 2.)), &k4);
+    add(state, &mul(&state_change, dt / 6.))
+}
+
+// The same step with the dense StateMap, mirroring the solver and evaluate_state_into
+fn state_map_rk4(
+    state: &StateMap<Joint>,
+    dstate: &StateMap<Joint>,
+    stage: &mut StateMap<Joint>,
+    physics_states: &mut StateMap<Joint>,
+    k: &mut [StateMap<Joint>],
+    dt: f64,
+) -> StateMap<Joint> {
+    let mut evaluate = |stage: &StateMap<Joint>, k: &mut StateMap<Joint>| {
+        physics_states.clone_from(stage);
+        black_box(&physics_states);
+        k.clone_from(dstate);
+    };
+
+    evaluate(state, &mut k[0]);
+    stage.clone_from(state);
+    stage.axpy(dt * 0.5, &k[0]);
+    evaluate(stage, &mut k[1]);
+    stage.clone_from(state);
+    stage.axpy(dt * 0.5, &k[1]);
+    evaluate(stage, &mut k[2]);
+    stage.clone_from(state);
+    stage.axpy(dt, &k[2]);
+    evaluate(stage, &mut k[3]);
+
+    let mut updated_state = state.clone();
+    updated_state.axpy(dt / 6., &k[0]);
+    updated_state.axpy(dt / 3., &k[1]);
+    updated_state.axpy(dt / 3., &k[2]);
+    updated_state.axpy(dt / 6., &k[3]);
+    updated_state
+}
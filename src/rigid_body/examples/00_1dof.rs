@@ -6,6 +6,7 @@ use bevy_integrator::{PhysicsSchedule, PhysicsSet, SimTime, Solver};
 use cameras::camera_az_el::{self, camera_builder};
 use rigid_body::{
     definitions::{MeshDef, MeshTypeDef, TransformDef},
+    forces::{spring_damper_system, SpringDamper},
     joint::{Base, Joint},
     plugin::RigidBodyPlugin,
     sva::{Inertia, Matrix, Motion, Vector, Xform},
@@ -101,21 +102,3 @@ fn environment_startup_system(mut commands: Commands) {
         ..default()
     });
 }
-
-#[derive(Component)]
-pub struct SpringDamper {
-    stiffness: f64,
-    damping: f64,
-}
-
-impl SpringDamper {
-    pub fn new(stiffness: f64, damping: f64) -> Self {
-        Self { stiffness, damping }
-    }
-}
-
-pub fn spring_damper_system(mut joints: Query<(&mut Joint, &SpringDamper)>) {
-    for (mut joint, spring_damper) in joints.iter_mut() {
-        joint.tau -= spring_damper.stiffness * joint.q + spring_damper.damping * joint.qd;
-    }
-}
@@ -0,0 +1,79 @@
+use std::f64::consts::PI;
+
+use bevy::prelude::*;
+
+use bevy_integrator::{
+    step,
+    zero_crossing::{
+        CrossingDirection, CrossingMethod, ZeroCrossingEvent, ZeroCrossingPlugin, ZeroCrossings,
+    },
+    PhysicsState, SimTime, Solver,
+};
+use rigid_body::{
+    joint::{Base, Joint},
+    plugin::HeadlessRigidBodyPlugin,
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+// Main function
+fn main() {
+    // Create App without a window or renderer
+    let mut app = App::new();
+    app.add_plugins(HeadlessRigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, Some(10.)),
+        solver: Solver::RK4,
+        simulation_setup: vec![],
+    })
+    .add_plugins(ZeroCrossingPlugin::<Joint>::new(
+        CrossingMethod::Bisection {
+            tolerance: 1e-9,
+            max_iterations: 30,
+        },
+        false,
+    ))
+    .add_systems(Startup, startup_system);
+
+    // run the startup systems
+    app.update();
+
+    // advance the simulation one second at a time
+    for _ in 0..10 {
+        step::<Joint>(&mut app.world, 500);
+
+        let time = app.world.resource::<SimTime>().time();
+        let physics_state = app.world.resource::<PhysicsState<Joint>>();
+        for (_entity, state) in physics_state.states.iter() {
+            println!("t: {:.3}, q: {:.6}, qd: {:.6}", time, state.q, state.qd);
+        }
+
+        // times the pendulum passed through the bottom
+        let mut events = app.world.resource_mut::<Events<ZeroCrossingEvent>>();
+        for event in events.drain() {
+            println!("  {} ({:?}) at t: {:.9}", event.name, event.direction, event.time);
+        }
+    }
+}
+
+fn startup_system(mut commands: Commands, mut crossings: ResMut<ZeroCrossings<Joint>>) {
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    let mass: f64 = 1.;
+    let length: f64 = 1.0;
+    let inertia = Inertia::new(
+        mass,
+        Vector::new(0.0, 0.0, -length),
+        Matrix::from_diagonal(&Vector::new(0., 0., 0.)),
+    );
+
+    let mut ry = Joint::ry("body_ry".to_string(), inertia, Xform::identity());
+    ry.q = 0.5 * PI;
+    let mut ry_e = commands.spawn(ry);
+    ry_e.set_parent(base_id);
+
+    // event when the pendulum swings through the bottom
+    let ry_id = ry_e.id();
+    crossings.register("pendulum_bottom", CrossingDirection::Both, move |states| {
+        states.get(&ry_id).map_or(f64::NAN, |state| state.q)
+    });
+}
@@ -0,0 +1,103 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{step, SimTime, Solver};
+use rigid_body::{
+    constraints::{LoopConstraint, LoopConstraintPlugin},
+    joint::{Base, Joint},
+    plugin::HeadlessRigidBodyPlugin,
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+// A parallelogram four-bar linkage swinging under gravity. The crank and the coupler are a
+// joint chain from the base, the rocker hangs from the base on its own, and a loop constraint
+// joins the end of the coupler to the end of the rocker.
+fn main() {
+    // Create App without a window or renderer
+    let mut app = App::new();
+    app.add_plugins(HeadlessRigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, Some(10.)),
+        solver: Solver::RK4,
+        simulation_setup: vec![],
+    })
+    .add_plugins(LoopConstraintPlugin)
+    .add_systems(Startup, startup_system);
+
+    // run the startup systems
+    app.update();
+
+    // advance the simulation one second at a time
+    for _ in 0..10 {
+        step::<Joint>(&mut app.world, 500);
+
+        let time = app.world.resource::<SimTime>().time();
+        let mut joint_query = app.world.query::<&Joint>();
+        let mut q = |name: &str| {
+            joint_query
+                .iter(&app.world)
+                .find(|joint| joint.name == name)
+                .map_or(f64::NAN, |joint| joint.q)
+        };
+        let (crank, coupler, rocker) = (q("crank"), q("coupler"), q("rocker"));
+
+        // the rocker follows the crank and the coupler stays level
+        println!(
+            "t: {:.3}, crank: {:.6}, rocker - crank: {:.2e}, coupler + crank: {:.2e}",
+            time,
+            crank,
+            rocker - crank,
+            coupler + crank
+        );
+
+        let mut constraint_query = app.world.query::<&LoopConstraint>();
+        for constraint in constraint_query.iter(&app.world) {
+            println!("  {} force: {:.4?}", constraint.name, constraint.force.f);
+        }
+    }
+}
+
+fn startup_system(mut commands: Commands) {
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    let mass: f64 = 1.;
+    let length: f64 = 1.0;
+    let moi = 1. / 12. * mass * length.powi(2);
+    let link = |center: Vector| {
+        Inertia::new(
+            mass,
+            center,
+            Matrix::from_diagonal(&Vector::new(moi, moi, moi)),
+        )
+    };
+
+    // crank and rocker hang down from pivots 2 m apart, the coupler joins their ends
+    let mut crank = Joint::ry(
+        "crank".to_string(),
+        link(Vector::new(0., 0., -length / 2.)),
+        Xform::identity(),
+    );
+    crank.q = 0.5;
+    let crank_id = commands.spawn(crank).set_parent(base_id).id();
+
+    let mut coupler = Joint::ry(
+        "coupler".to_string(),
+        link(Vector::new(length, 0., 0.)),
+        Xform::posz(-length),
+    );
+    coupler.q = -0.5;
+    let coupler_id = commands.spawn(coupler).set_parent(crank_id).id();
+
+    let mut rocker = Joint::ry(
+        "rocker".to_string(),
+        link(Vector::new(0., 0., -length / 2.)),
+        Xform::posx(2. * length),
+    );
+    rocker.q = 0.5;
+    let rocker_id = commands.spawn(rocker).set_parent(base_id).id();
+
+    commands.spawn(LoopConstraint::ball(
+        "coupler_rocker".to_string(),
+        (coupler_id, Xform::posx(2. * length)),
+        (rocker_id, Xform::posz(-length)),
+    ));
+}
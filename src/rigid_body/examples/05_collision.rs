@@ -0,0 +1,97 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{step, SimTime, Solver};
+use rigid_body::{
+    collision::{Collider, CollisionPlugin, CollisionShape, Contacts},
+    joint::{Base, Joint},
+    plugin::HeadlessRigidBodyPlugin,
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+// A ball rolls into a box resting on the ground and pushes it along. The ground is a collider
+// on the base, and the ball and the box are floating bodies.
+fn main() {
+    // Create App without a window or renderer
+    let mut app = App::new();
+    app.add_plugins(HeadlessRigidBodyPlugin {
+        time: SimTime::new(0.001, 0.0, Some(5.)),
+        solver: Solver::RK4,
+        simulation_setup: vec![],
+    })
+    .add_plugins(CollisionPlugin)
+    .add_systems(Startup, startup_system);
+
+    // run the startup systems
+    app.update();
+
+    // advance the simulation half a second at a time
+    for _ in 0..10 {
+        step::<Joint>(&mut app.world, 500);
+
+        let time = app.world.resource::<SimTime>().time();
+        let mut joint_query = app.world.query::<&Joint>();
+        for joint in joint_query.iter(&app.world) {
+            if !joint.name.is_empty() {
+                println!(
+                    "t: {:.3}, {}: position {:.3?}",
+                    time,
+                    joint.name,
+                    joint.floating.position.as_slice()
+                );
+            }
+        }
+        for contact in app.world.resource::<Contacts>().contacts.iter() {
+            println!(
+                "  contact at {:.3?}, depth: {:.2e}, force: {:.2?}",
+                contact.point.as_slice(),
+                contact.depth,
+                contact.force.as_slice()
+            );
+        }
+    }
+}
+
+fn startup_system(mut commands: Commands) {
+    // the top of the ground is at z = 0
+    let ground = Collider::new(
+        CollisionShape::Box {
+            half_extents: Vector::new(20., 20., 0.5),
+        },
+        Xform::posz(-0.5),
+    );
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base, ground)).id();
+
+    // ball
+    let (mass, radius): (f64, f64) = (5., 0.3);
+    let moi = 2. / 5. * mass * radius.powi(2);
+    let inertia = Inertia::new(
+        mass,
+        Vector::zeros(),
+        Matrix::from_diagonal(&Vector::new(moi, moi, moi)),
+    );
+    let mut ball = Joint::floating("ball".to_string(), inertia, Xform::identity());
+    ball.floating.set_pose([-2., 0., radius], [0., 0., 0.]);
+    ball.floating.velocity = Motion::new([3., 0., 0.], [0., 3. / radius, 0.]);
+    let collider = Collider::new(CollisionShape::Sphere { radius }, Xform::identity());
+    commands.spawn((ball, collider)).set_parent(base_id);
+
+    // box
+    let (mass, size): (f64, f64) = (2., 0.5);
+    let moi = mass * size.powi(2) / 6.;
+    let inertia = Inertia::new(
+        mass,
+        Vector::zeros(),
+        Matrix::from_diagonal(&Vector::new(moi, moi, moi)),
+    );
+    let mut block = Joint::floating("box".to_string(), inertia, Xform::identity());
+    block.floating.set_pose([0., 0., size / 2.], [0., 0., 0.]);
+    let collider = Collider::new(
+        CollisionShape::Box {
+            half_extents: Vector::new(size, size, size) / 2.,
+        },
+        Xform::identity(),
+    )
+    .with_contact(1e5, 1e3, 0.3);
+    commands.spawn((block, collider)).set_parent(base_id);
+}
@@ -0,0 +1,158 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{step, SimTime, Solver};
+use rigid_body::{
+    joint::{Base, Joint},
+    plugin::HeadlessRigidBodyPlugin,
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+    urdf::Urdf,
+};
+
+// A double pendulum with a tip mass, loaded from a URDF description. The shoulder frame is
+// turned about z so its x axis is the world y axis, and the tip mass is a fixed link, merged
+// into the lower arm. The same pendulum built by hand swings alongside for comparison.
+const DOUBLE_PENDULUM: &str = r#"
+<robot name="double_pendulum">
+  <material name="red">
+    <color rgba="1 0 0 1"/>
+  </material>
+  <link name="mount">
+    <visual>
+      <geometry><box size="0.2 0.2 0.05"/></geometry>
+    </visual>
+  </link>
+  <link name="upper_arm">
+    <inertial>
+      <origin xyz="0 0 -0.5"/>
+      <mass value="1"/>
+      <inertia ixx="0.0835" ixy="0" ixz="0" iyy="0.0835" iyz="0" izz="0.0005"/>
+    </inertial>
+    <visual>
+      <origin xyz="0 0 -0.5"/>
+      <geometry><cylinder radius="0.025" length="1"/></geometry>
+      <material name="red"/>
+    </visual>
+  </link>
+  <link name="lower_arm">
+    <inertial>
+      <origin xyz="0 0 -0.5"/>
+      <mass value="1"/>
+      <inertia ixx="0.0835" ixy="0" ixz="0" iyy="0.0835" iyz="0" izz="0.0005"/>
+    </inertial>
+    <visual>
+      <origin xyz="0 0 -0.5"/>
+      <geometry><cylinder radius="0.025" length="1"/></geometry>
+      <material name="red"/>
+    </visual>
+  </link>
+  <link name="tip">
+    <inertial>
+      <mass value="0.5"/>
+      <inertia ixx="0.001" ixy="0" ixz="0" iyy="0.001" iyz="0" izz="0.001"/>
+    </inertial>
+  </link>
+  <joint name="shoulder" type="continuous">
+    <parent link="mount"/>
+    <child link="upper_arm"/>
+    <origin xyz="0 0 0" rpy="0 0 1.5707963267948966"/>
+    <axis xyz="1 0 0"/>
+  </joint>
+  <joint name="elbow" type="revolute">
+    <parent link="upper_arm"/>
+    <child link="lower_arm"/>
+    <origin xyz="0 0 -1"/>
+    <axis xyz="1 0 0"/>
+    <limit lower="-3" upper="3" effort="10" velocity="10"/>
+  </joint>
+  <joint name="tip_mass" type="fixed">
+    <parent link="lower_arm"/>
+    <child link="tip"/>
+    <origin xyz="0 0 -1"/>
+  </joint>
+</robot>
+"#;
+
+fn main() {
+    // Create App without a window or renderer
+    let mut app = App::new();
+    app.add_plugins(HeadlessRigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, Some(10.)),
+        solver: Solver::RK4,
+        simulation_setup: vec![],
+    })
+    .add_systems(Startup, startup_system);
+
+    // run the startup systems
+    app.update();
+
+    // start both pendulums at the same angles
+    let mut joint_query = app.world.query::<&mut Joint>();
+    for mut joint in joint_query.iter_mut(&mut app.world) {
+        match joint.name.as_str() {
+            "shoulder" | "hand_shoulder" => joint.q = 1.,
+            "elbow" | "hand_elbow" => joint.q = 0.5,
+            _ => {}
+        }
+    }
+
+    // advance the simulation one second at a time
+    for _ in 0..10 {
+        step::<Joint>(&mut app.world, 500);
+
+        let time = app.world.resource::<SimTime>().time();
+        let mut joint_query = app.world.query::<&Joint>();
+        let mut q = |name: &str| {
+            joint_query
+                .iter(&app.world)
+                .find(|joint| joint.name == name)
+                .map_or(f64::NAN, |joint| joint.q)
+        };
+        let (shoulder, elbow) = (q("shoulder"), q("elbow"));
+        let (hand_shoulder, hand_elbow) = (q("hand_shoulder"), q("hand_elbow"));
+
+        // the URDF pendulum follows the one built by hand
+        println!(
+            "t: {:.3}, shoulder: {:.6}, elbow: {:.6}, differences: {:.2e}, {:.2e}",
+            time,
+            shoulder,
+            elbow,
+            shoulder - hand_shoulder,
+            elbow - hand_elbow
+        );
+    }
+}
+
+fn startup_system(mut commands: Commands) {
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    let urdf = Urdf::parse(DOUBLE_PENDULUM).expect("invalid URDF");
+    let links = urdf
+        .spawn(&mut commands, base_id, Xform::identity(), false)
+        .expect("invalid URDF");
+    for (link, entity) in links.iter() {
+        println!("link {} is body {:?}", link, entity);
+    }
+    for joint in urdf.joints.iter() {
+        println!("joint {} limits {:?}", joint.name, joint.limit);
+    }
+
+    // the same pendulum by hand
+    let arm = Inertia::new(
+        1.,
+        Vector::new(0., 0., -0.5),
+        Matrix::from_diagonal(&Vector::new(0.0835, 0.0835, 0.0005)),
+    );
+    // the lower arm and the tip about their common center of mass, 2/3 m below the elbow
+    let (c, d_arm, d_tip) = (-2. / 3., 1. / 6., 1. / 3.);
+    let moi_xy = 0.0835 + 0.001 + 1. * d_arm * d_arm + 0.5 * d_tip * d_tip;
+    let lower_arm = Inertia::new(
+        1.5,
+        Vector::new(0., 0., c),
+        Matrix::from_diagonal(&Vector::new(moi_xy, moi_xy, 0.0015)),
+    );
+    let shoulder = Joint::ry("hand_shoulder".to_string(), arm, Xform::identity());
+    let shoulder_id = commands.spawn(shoulder).set_parent(base_id).id();
+    let elbow = Joint::ry("hand_elbow".to_string(), lower_arm, Xform::posz(-1.));
+    commands.spawn(elbow).set_parent(shoulder_id);
+}
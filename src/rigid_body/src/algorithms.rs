@@ -8,7 +8,17 @@ pub fn loop_1_update(joint: &mut Joint, parent: &Joint) {
     joint.f_ext = Force::zero();
     joint.qdd = 0.;
     joint.a = Motion::zero();
+    joint.floating.tau = Force::zero();
+    joint.floating.acceleration = Motion::zero();
 
+    kinematics_update(joint, parent);
+
+    joint.c = joint.v.cross_v(joint.vj);
+    articulated_reset_update(joint, parent);
+}
+
+// positions and velocities, without touching the forces or accelerations
+pub fn kinematics_update(joint: &mut Joint, parent: &Joint) {
     // joint transform
     joint.xj = match joint.joint_type {
         JointType::Base => Xform::identity(),
@@ -18,15 +28,24 @@ pub fn loop_1_update(joint: &mut Joint, parent: &Joint) {
         JointType::Px => Xform::posx(joint.q),
         JointType::Py => Xform::posy(joint.q),
         JointType::Pz => Xform::posz(joint.q),
+        JointType::Revolute(axis) => Xform::rot(axis, joint.q),
+        JointType::Prismatic(axis) => Xform::pos_axis(axis, joint.q),
+        JointType::Helical { axis, pitch } => Xform::helical(axis, joint.q, pitch),
+        JointType::Floating => joint.floating.xj(),
     };
 
-    joint.vj = joint.qd * joint.s;
+    joint.vj = match joint.joint_type {
+        JointType::Floating => joint.floating.velocity,
+        _ => joint.qd * joint.s,
+    };
     joint.xl = joint.xj * joint.xt;
 
     joint.x = joint.xl * parent.x;
     joint.v = (joint.xl * parent.v) + joint.vj;
+}
 
-    joint.c = joint.v.cross_v(joint.vj);
+// articulated-body inertia and bias force of the body alone, to run the passes again
+pub fn articulated_reset_update(joint: &mut Joint, _parent: &Joint) {
     joint.iaa = joint.i.into();
     joint.paa = joint.v.cross_f(joint.i * joint.v);
 }
@@ -36,6 +55,15 @@ pub fn apply_external_update(joint: &mut Joint, _parent: &Joint) {
 }
 
 pub fn loop_2_update(joint: &mut Joint, parent_option: Option<&mut Joint>) {
+    if let JointType::Floating = joint.joint_type {
+        // S is the identity, so U = D = IA and u = tau - pA. Nothing of the articulated
+        // inertia is passed on to the parent, only the joint force.
+        if let Some(parent) = parent_option {
+            parent.paa += joint.xl.inverse() * joint.floating.tau;
+        }
+        return;
+    }
+
     joint.uu = joint.iaa * joint.s;
     joint.dd = joint.s.w.dot(&joint.uu.m) + joint.s.v.dot(&joint.uu.f);
     joint.u = joint.tau - (joint.s.w.dot(&joint.paa.m) + joint.s.v.dot(&joint.paa.f));
@@ -56,6 +84,17 @@ pub fn loop_2_update(joint: &mut Joint, parent_option: Option<&mut Joint>) {
 pub fn loop_3_update(joint: &mut Joint, parent: &Joint) {
     let ap = joint.xl * parent.a + joint.c;
 
+    if let JointType::Floating = joint.joint_type {
+        // a = ap + qdd = IA^-1 (tau - pA)
+        let a = joint
+            .iaa
+            .solve(joint.floating.tau - joint.paa)
+            .unwrap_or(ap);
+        joint.floating.acceleration = a - ap;
+        joint.a = a;
+        return;
+    }
+
     let dd_inv = 1. / joint.dd;
     let te = joint.u - (joint.uu.m.dot(&ap.w) + joint.uu.f.dot(&ap.v));
     joint.qdd = dd_inv * te;
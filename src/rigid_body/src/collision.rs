@@ -0,0 +1,816 @@
+use std::fs;
+
+use bevy::prelude::*;
+use bevy_integrator::{PhysicsSchedule, PhysicsSet};
+
+use crate::{
+    definitions::{MeshDef, MeshTypeDef},
+    forces::regularized_friction,
+    joint::Joint,
+    sva::{Force, Matrix, Vector, Xform},
+};
+
+// Contact between bodies of the joint trees, e.g. car to car or a car pushing an obstacle. The
+// Collider of each body is a set of convex shapes. Candidate pairs are found by sweep and prune
+// of the bounding boxes, the penetration of each pair by GJK and EPA, and a penalty force at each
+// contact point acts on both bodies through f_ext in PhysicsSet::Evaluate. Boxes and hulls touching
+// along a face get up to four contact points, so they rest flat instead of rocking between corners.
+pub struct CollisionPlugin;
+
+impl Plugin for CollisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Contacts>().add_systems(
+            PhysicsSchedule,
+            collision_system.in_set(PhysicsSet::Evaluate),
+        );
+    }
+}
+
+// Convex shapes in their own coordinates
+#[derive(Clone, Debug)]
+pub enum CollisionShape {
+    Sphere { radius: f64 },
+    Capsule { radius: f64, half_length: f64 }, // along z
+    Box { half_extents: Vector },
+    ConvexHull { points: Vec<Vector> },
+}
+
+impl CollisionShape {
+    // point of the shape furthest along the direction
+    fn support(&self, direction: Vector) -> Vector {
+        let unit = direction.try_normalize(f64::EPSILON).unwrap_or(Vector::z());
+        match self {
+            CollisionShape::Sphere { radius } => *radius * unit,
+            CollisionShape::Capsule {
+                radius,
+                half_length,
+            } => Vector::new(0., 0., half_length.copysign(direction.z)) + *radius * unit,
+            CollisionShape::Box { half_extents } => Vector::new(
+                half_extents.x.copysign(direction.x),
+                half_extents.y.copysign(direction.y),
+                half_extents.z.copysign(direction.z),
+            ),
+            CollisionShape::ConvexHull { points } => points
+                .iter()
+                .copied()
+                .max_by(|a, b| a.dot(&direction).total_cmp(&b.dot(&direction)))
+                .unwrap_or(Vector::zeros()),
+        }
+    }
+
+    // points around a cylinder along y, the axis of bevy cylinders and of the wheels
+    fn cylinder(radius: f64, height: f64) -> Self {
+        let segments = 16;
+        let points = (0..segments)
+            .flat_map(|i| {
+                let angle = i as f64 / segments as f64 * std::f64::consts::TAU;
+                let (x, z) = (radius * angle.cos(), radius * angle.sin());
+                [
+                    Vector::new(x, -height / 2., z),
+                    Vector::new(x, height / 2., z),
+                ]
+            })
+            .collect();
+        CollisionShape::ConvexHull { points }
+    }
+}
+
+// The collision shapes of a body, on the joint entity. Bodies with the same group don't collide
+// with each other, e.g. the parts of one car.
+#[derive(Component, Clone, Debug)]
+pub struct Collider {
+    pub shapes: Vec<(CollisionShape, Xform)>, // shape and its frame relative to the body
+    pub stiffness: f64,                       // per contact point
+    pub damping: f64,                         // per contact point
+    pub friction: f64,
+    pub group: Option<u32>,
+}
+
+impl Collider {
+    pub fn new(shape: CollisionShape, frame: Xform) -> Self {
+        Self {
+            shapes: vec![(shape, frame)],
+            stiffness: 1e5,
+            damping: 1e3,
+            friction: 0.5,
+            group: None,
+        }
+    }
+
+    pub fn with_shape(mut self, shape: CollisionShape, frame: Xform) -> Self {
+        self.shapes.push((shape, frame));
+        self
+    }
+
+    pub fn with_contact(mut self, stiffness: f64, damping: f64, friction: f64) -> Self {
+        self.stiffness = stiffness;
+        self.damping = damping;
+        self.friction = friction;
+        self
+    }
+
+    pub fn with_group(mut self, group: u32) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    // The shape of a mesh definition: a box, the convex hull of a cylinder or wheel, or the
+    // convex hull of the vertices of an .obj file in the assets folder. None for other files.
+    pub fn from_mesh_def(mesh_def: &MeshDef) -> Option<Self> {
+        let shape = match &mesh_def.mesh_type {
+            MeshTypeDef::Box { dimensions } => CollisionShape::Box {
+                half_extents: Vector::new(
+                    dimensions[0] as f64 / 2.,
+                    dimensions[1] as f64 / 2.,
+                    dimensions[2] as f64 / 2.,
+                ),
+            },
+            MeshTypeDef::Cylinder { height, radius } => {
+                CollisionShape::cylinder(*radius as f64, *height as f64)
+            }
+            MeshTypeDef::Wheel { radius, width } => {
+                CollisionShape::cylinder(*radius as f64, *width as f64)
+            }
+            MeshTypeDef::File { file_name } => CollisionShape::ConvexHull {
+                points: obj_vertices(file_name)?,
+            },
+        };
+        Some(Self::new(shape, (&mesh_def.transform).into()))
+    }
+}
+
+// vertices of a wavefront .obj file, relative to the assets folder
+fn obj_vertices(file_name: &str) -> Option<Vec<Vector>> {
+    if !file_name.ends_with(".obj") {
+        return None;
+    }
+    let contents = fs::read_to_string(format!("assets/{}", file_name)).ok()?;
+    let points: Vec<Vector> = contents
+        .lines()
+        .filter_map(|line| {
+            let mut values = line.strip_prefix("v ")?.split_whitespace();
+            let mut next = || values.next()?.parse::<f64>().ok();
+            Some(Vector::new(next()?, next()?, next()?))
+        })
+        .collect();
+    (!points.is_empty()).then_some(points)
+}
+
+// Contacts of the last evaluation of the dynamics
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Contacts {
+    pub contacts: Vec<Contact>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    pub body_a: Entity,
+    pub body_b: Entity,
+    pub point: Vector,  // in world coordinates
+    pub normal: Vector, // from body_a to body_b
+    pub depth: f64,
+    pub force: Vector, // on body_b, body_a gets -force
+}
+
+// a shape placed in the world
+struct WorldShape<'a> {
+    shape: &'a CollisionShape,
+    rotation: Matrix, // shape to world
+    position: Vector,
+}
+
+impl WorldShape<'_> {
+    fn support(&self, direction: Vector) -> Vector {
+        self.position + self.rotation * self.shape.support(self.rotation.transpose() * direction)
+    }
+
+    fn bounds(&self) -> (Vector, Vector) {
+        let mut min = Vector::zeros();
+        let mut max = Vector::zeros();
+        for axis in 0..3 {
+            let mut direction = Vector::zeros();
+            direction[axis] = 1.;
+            max[axis] = self.support(direction)[axis];
+            min[axis] = self.support(-direction)[axis];
+        }
+        (min, max)
+    }
+}
+
+// point of the Minkowski difference A - B, with the points of A and B it came from
+#[derive(Clone, Copy, Debug)]
+struct SupportPoint {
+    w: Vector,
+    a: Vector,
+    b: Vector,
+}
+
+fn support(a: &WorldShape, b: &WorldShape, direction: Vector) -> SupportPoint {
+    let a = a.support(direction);
+    let b = b.support(-direction);
+    SupportPoint { w: a - b, a, b }
+}
+
+const MAX_ITERATIONS: usize = 64;
+const TOLERANCE: f64 = 1e-9;
+const SLIP_SPEED: f64 = 0.1; // sliding speed of full friction
+const MAX_MANIFOLD: usize = 4; // contact points of a pair of faces
+const FACE_TOLERANCE: f64 = 1e-4; // distance of hull points from their face
+const MIN_FACE_ALIGNMENT: f64 = 0.9; // cosine of the angle between a face and the contact normal
+
+// closest point to the origin on a simplex of up to 4 points, which is reduced to the points of
+// the closest feature. Returns None if the origin is inside the tetrahedron.
+fn closest_on_simplex(simplex: &mut Vec<SupportPoint>) -> Option<Vector> {
+    match simplex.len() {
+        1 => Some(simplex[0].w),
+        2 => Some(closest_on_segment(simplex)),
+        3 => Some(closest_on_triangle(simplex)),
+        _ => {
+            let mut best: Option<(Vec<SupportPoint>, Vector)> = None;
+            for (i, j, k, opposite) in [(0, 1, 2, 3), (0, 3, 1, 2), (0, 2, 3, 1), (1, 3, 2, 0)] {
+                let [a, b, c, d] = [
+                    simplex[i].w,
+                    simplex[j].w,
+                    simplex[k].w,
+                    simplex[opposite].w,
+                ];
+                let mut normal = (b - a).cross(&(c - a));
+                if normal.dot(&(d - a)) > 0. {
+                    normal = -normal;
+                }
+                // the origin is outside this face
+                if normal.dot(&-a) > 0. {
+                    let mut face = vec![simplex[i], simplex[j], simplex[k]];
+                    let closest = closest_on_triangle(&mut face);
+                    if best
+                        .as_ref()
+                        .is_none_or(|(_, best)| closest.norm() < best.norm())
+                    {
+                        best = Some((face, closest));
+                    }
+                }
+            }
+            let (face, closest) = best?;
+            *simplex = face;
+            Some(closest)
+        }
+    }
+}
+
+fn closest_on_segment(simplex: &mut Vec<SupportPoint>) -> Vector {
+    let (a, b) = (simplex[0].w, simplex[1].w);
+    let ab = b - a;
+    let t = (-a).dot(&ab) / ab.norm_squared().max(f64::MIN_POSITIVE);
+    if t <= 0. {
+        simplex.truncate(1);
+        a
+    } else if t >= 1. {
+        simplex.remove(0);
+        b
+    } else {
+        a + t * ab
+    }
+}
+
+// closest point on a triangle (Ericson, Real-Time Collision Detection 5.1.5)
+fn closest_on_triangle(simplex: &mut Vec<SupportPoint>) -> Vector {
+    let [a, b, c] = [simplex[0].w, simplex[1].w, simplex[2].w];
+    let (ab, ac, ap) = (b - a, c - a, -a);
+    let (d1, d2) = (ab.dot(&ap), ac.dot(&ap));
+    if d1 <= 0. && d2 <= 0. {
+        *simplex = vec![simplex[0]];
+        return a;
+    }
+    let bp = -b;
+    let (d3, d4) = (ab.dot(&bp), ac.dot(&bp));
+    if d3 >= 0. && d4 <= d3 {
+        *simplex = vec![simplex[1]];
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0. && d1 >= 0. && d3 <= 0. {
+        *simplex = vec![simplex[0], simplex[1]];
+        return a + d1 / (d1 - d3) * ab;
+    }
+    let cp = -c;
+    let (d5, d6) = (ab.dot(&cp), ac.dot(&cp));
+    if d6 >= 0. && d5 <= d6 {
+        *simplex = vec![simplex[2]];
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0. && d2 >= 0. && d6 <= 0. {
+        *simplex = vec![simplex[0], simplex[2]];
+        return a + d2 / (d2 - d6) * ac;
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0. && d4 - d3 >= 0. && d5 - d6 >= 0. {
+        *simplex = vec![simplex[1], simplex[2]];
+        return b + (d4 - d3) / ((d4 - d3) + (d5 - d6)) * (c - b);
+    }
+    let denominator = 1. / (va + vb + vc);
+    a + ab * vb * denominator + ac * vc * denominator
+}
+
+// GJK: a simplex of the Minkowski difference that encloses the origin, None if the shapes are apart
+fn gjk(a: &WorldShape, b: &WorldShape) -> Option<Vec<SupportPoint>> {
+    let mut direction = a.position - b.position;
+    if direction.norm_squared() < TOLERANCE {
+        direction = Vector::x();
+    }
+    let mut simplex = vec![support(a, b, direction)];
+    for _ in 0..MAX_ITERATIONS {
+        let Some(closest) = closest_on_simplex(&mut simplex) else {
+            return Some(simplex);
+        };
+        if closest.norm_squared() < TOLERANCE * TOLERANCE {
+            return Some(simplex);
+        }
+        direction = -closest;
+        let point = support(a, b, direction);
+        // no progress towards the origin, so it is outside
+        if point.w.dot(&direction) - closest.dot(&direction) < TOLERANCE * direction.norm() {
+            return None;
+        }
+        if point.w.dot(&direction) < 0. {
+            return None;
+        }
+        simplex.push(point);
+    }
+    None
+}
+
+// grow a degenerate simplex that touches the origin into a tetrahedron
+fn complete_tetrahedron(
+    a: &WorldShape,
+    b: &WorldShape,
+    simplex: &mut Vec<SupportPoint>,
+) -> Option<()> {
+    let directions = [
+        Vector::x(),
+        Vector::y(),
+        Vector::z(),
+        -Vector::x(),
+        -Vector::y(),
+        -Vector::z(),
+    ];
+    while simplex.len() < 4 {
+        let mut candidates: Vec<Vector> = match simplex.len() {
+            3 => {
+                let normal = (simplex[1].w - simplex[0].w).cross(&(simplex[2].w - simplex[0].w));
+                vec![normal, -normal]
+            }
+            2 => {
+                let axis = simplex[1].w - simplex[0].w;
+                directions.iter().map(|d| axis.cross(d)).collect()
+            }
+            _ => directions.to_vec(),
+        };
+        candidates.retain(|d| d.norm_squared() > TOLERANCE);
+        let added = candidates.into_iter().find_map(|direction| {
+            let point = support(a, b, direction);
+            let new = simplex
+                .iter()
+                .all(|p| (p.w - point.w).norm_squared() > TOLERANCE);
+            let volume = match simplex.len() {
+                3 => (simplex[1].w - simplex[0].w)
+                    .cross(&(simplex[2].w - simplex[0].w))
+                    .dot(&(point.w - simplex[0].w))
+                    .abs(),
+                2 => (simplex[1].w - simplex[0].w)
+                    .cross(&(point.w - simplex[0].w))
+                    .norm(),
+                _ => (point.w - simplex[0].w).norm(),
+            };
+            (new && volume > TOLERANCE).then_some(point)
+        })?;
+        simplex.push(added);
+    }
+    Some(())
+}
+
+struct Face {
+    vertices: [usize; 3], // counterclockwise seen from outside
+    normal: Vector,       // outwards
+    distance: f64,        // of the plane from the origin
+}
+
+fn face(points: &[SupportPoint], vertices: [usize; 3]) -> Option<Face> {
+    let [a, b, c] = vertices.map(|i| points[i].w);
+    let normal = (b - a).cross(&(c - a)).try_normalize(f64::MIN_POSITIVE)?;
+    Some(Face {
+        vertices,
+        normal,
+        distance: normal.dot(&a),
+    })
+}
+
+// normal, depth and the points on A and B of the projection of the origin on a face
+fn face_contact(points: &[SupportPoint], face: &Face) -> (Vector, f64, Vector, Vector) {
+    let [p0, p1, p2] = face.vertices.map(|i| points[i]);
+    let projection = face.normal * face.distance;
+    let (v0, v1, v2) = (p1.w - p0.w, p2.w - p0.w, projection - p0.w);
+    let (d00, d01, d11) = (v0.dot(&v0), v0.dot(&v1), v1.dot(&v1));
+    let (d20, d21) = (v2.dot(&v0), v2.dot(&v1));
+    let denominator = d00 * d11 - d01 * d01;
+    let (v, w) = if denominator.abs() > f64::MIN_POSITIVE {
+        (
+            (d11 * d20 - d01 * d21) / denominator,
+            (d00 * d21 - d01 * d20) / denominator,
+        )
+    } else {
+        (0., 0.)
+    };
+    let u = 1. - v - w;
+    let point_a = u * p0.a + v * p1.a + w * p2.a;
+    let point_b = u * p0.b + v * p1.b + w * p2.b;
+    (face.normal, face.distance, point_a, point_b)
+}
+
+// EPA: penetration normal (from A to B), depth, and the contact points on A and B
+fn epa(
+    a: &WorldShape,
+    b: &WorldShape,
+    mut points: Vec<SupportPoint>,
+) -> Option<(Vector, f64, Vector, Vector)> {
+    // orient the faces of the tetrahedron outwards from its centroid
+    let centroid = points.iter().map(|p| p.w).sum::<Vector>() / 4.;
+    let mut faces: Vec<Face> = [[0, 1, 2], [0, 3, 1], [0, 2, 3], [1, 3, 2]]
+        .into_iter()
+        .filter_map(|[i, j, k]| {
+            let outward = face(&points, [i, j, k])?;
+            if outward.normal.dot(&(points[i].w - centroid)) < 0. {
+                face(&points, [i, k, j])
+            } else {
+                Some(outward)
+            }
+        })
+        .collect();
+
+    for _ in 0..MAX_ITERATIONS {
+        let closest = faces
+            .iter()
+            .min_by(|f, g| f.distance.total_cmp(&g.distance))?;
+        let point = support(a, b, closest.normal);
+        let known = points
+            .iter()
+            .any(|p| (p.w - point.w).norm_squared() < TOLERANCE);
+        if known || point.w.dot(&closest.normal) - closest.distance < 1e-6 {
+            return Some(face_contact(&points, closest));
+        }
+
+        // remove the faces that see the new point, and close the hole from its horizon
+        let index = points.len();
+        points.push(point);
+        let mut horizon: Vec<[usize; 2]> = Vec::new();
+        faces.retain(|face| {
+            let visible = face.normal.dot(&(point.w - points[face.vertices[0]].w)) > TOLERANCE;
+            if visible {
+                let [i, j, k] = face.vertices;
+                for edge in [[i, j], [j, k], [k, i]] {
+                    match horizon.iter().position(|e| *e == [edge[1], edge[0]]) {
+                        Some(shared) => {
+                            horizon.swap_remove(shared);
+                        }
+                        None => horizon.push(edge),
+                    }
+                }
+            }
+            !visible
+        });
+        for [i, j] in horizon {
+            if let Some(face) = face(&points, [i, j, index]) {
+                faces.push(face);
+            }
+        }
+    }
+    let closest = faces
+        .iter()
+        .min_by(|f, g| f.distance.total_cmp(&g.distance))?;
+    Some(face_contact(&points, closest))
+}
+
+// penetration of two shapes: normal from A to B, depth and contact point
+fn penetration(a: &WorldShape, b: &WorldShape) -> Option<(Vector, f64, Vector)> {
+    let mut simplex = gjk(a, b)?;
+    complete_tetrahedron(a, b, &mut simplex)?;
+    let (normal, depth, point_a, point_b) = epa(a, b, simplex)?;
+    Some((normal, depth, 0.5 * (point_a + point_b)))
+}
+
+// The face of a box or convex hull furthest along a direction, as a polygon in world coordinates
+// and its outward normal. A box gives the face whose normal is closest to the direction. A hull
+// gives the points within tolerance of its support plane, with the direction as the normal, so
+// fewer than three points are an edge or a vertex.
+fn support_face(
+    shape: &WorldShape,
+    direction: Vector,
+    tolerance: f64,
+) -> Option<(Vec<Vector>, Vector)> {
+    match shape.shape {
+        CollisionShape::Box { half_extents } => {
+            let local = shape.rotation.transpose() * direction;
+            let axis = local.iamax();
+            let sign = local[axis].signum();
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let polygon = [(1., 1.), (-1., 1.), (-1., -1.), (1., -1.)]
+                .iter()
+                .map(|(su, sv)| {
+                    let mut corner = Vector::zeros();
+                    corner[axis] = sign * half_extents[axis];
+                    corner[u] = su * half_extents[u];
+                    corner[v] = sv * half_extents[v];
+                    shape.position + shape.rotation * corner
+                })
+                .collect();
+            let mut normal = Vector::zeros();
+            normal[axis] = sign;
+            Some((polygon, shape.rotation * normal))
+        }
+        CollisionShape::ConvexHull { points } => {
+            let normal = direction.try_normalize(f64::EPSILON)?;
+            let local = shape.rotation.transpose() * normal;
+            let height = shape.shape.support(local).dot(&local);
+            let points = points
+                .iter()
+                .filter(|point| point.dot(&local) >= height - tolerance)
+                .map(|point| shape.position + shape.rotation * point)
+                .collect();
+            Some((convex_polygon(points, normal), normal))
+        }
+        _ => None,
+    }
+}
+
+// the convex hull of points in a plane, in order around the normal (monotone chain)
+fn convex_polygon(points: Vec<Vector>, normal: Vector) -> Vec<Vector> {
+    if points.len() < 3 {
+        return points;
+    }
+    let u = normal
+        .cross(&Vector::x())
+        .try_normalize(1e-6)
+        .unwrap_or_else(|| normal.cross(&Vector::y()).normalize());
+    let v = normal.cross(&u);
+    let mut sorted: Vec<(f64, f64, Vector)> =
+        points.iter().map(|p| (p.dot(&u), p.dot(&v), *p)).collect();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    let cross = |o: &(f64, f64, Vector), a: &(f64, f64, Vector), b: &(f64, f64, Vector)| {
+        (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+    };
+    let mut hull: Vec<(f64, f64, Vector)> = Vec::new();
+    // the lower chain, then the upper chain over the points in reverse
+    for _ in 0..2 {
+        let start = hull.len();
+        for point in &sorted {
+            while hull.len() >= start + 2
+                && cross(&hull[hull.len() - 2], &hull[hull.len() - 1], point) <= 0.
+            {
+                hull.pop();
+            }
+            hull.push(*point);
+        }
+        hull.pop();
+        sorted.reverse();
+    }
+    hull.into_iter().map(|(_, _, point)| point).collect()
+}
+
+// Sutherland-Hodgman clipping of a polygon (or an edge, or a point) to the side planes of the
+// edges of a convex reference face
+fn clip_polygon(mut polygon: Vec<Vector>, face: &[Vector], normal: Vector) -> Vec<Vector> {
+    let center = face.iter().sum::<Vector>() / face.len() as f64;
+    for (i, a) in face.iter().enumerate() {
+        let b = face[(i + 1) % face.len()];
+        let Some(mut side) = normal.cross(&(b - a)).try_normalize(1e-12) else {
+            continue;
+        };
+        // the side normal points into the face
+        if (center - a).dot(&side) < 0. {
+            side = -side;
+        }
+        let distance = |point: &Vector| (point - a).dot(&side);
+        let mut clipped = Vec::new();
+        for (j, current) in polygon.iter().enumerate() {
+            let previous = polygon[(j + polygon.len() - 1) % polygon.len()];
+            let (d_current, d_previous) = (distance(current), distance(&previous));
+            if (d_current >= 0.) != (d_previous >= 0.) {
+                let t = d_previous / (d_previous - d_current);
+                clipped.push(previous + t * (current - previous));
+            }
+            if d_current >= 0. {
+                clipped.push(*current);
+            }
+        }
+        polygon = clipped;
+        if polygon.is_empty() {
+            break;
+        }
+    }
+    polygon
+}
+
+// at most MAX_MANIFOLD points spanning the largest area, starting from the deepest
+fn reduce_manifold(mut points: Vec<(f64, Vector)>, normal: Vector) -> Vec<(f64, Vector)> {
+    if points.len() <= MAX_MANIFOLD {
+        return points;
+    }
+    let mut kept = Vec::with_capacity(MAX_MANIFOLD);
+    let deepest = (0..points.len())
+        .max_by(|&i, &j| points[i].0.total_cmp(&points[j].0))
+        .unwrap();
+    kept.push(points.swap_remove(deepest));
+    let area = |a: Vector, b: Vector, c: Vector| (b - a).cross(&(c - a)).dot(&normal);
+    while kept.len() < MAX_MANIFOLD && !points.is_empty() {
+        // the point that adds the most area: the farthest for the second point, then the area
+        // outside of the edges of the points kept so far
+        let gain = |point: &Vector| match kept.len() {
+            1 => (point - kept[0].1).norm_squared(),
+            _ => (0..kept.len())
+                .map(|i| {
+                    let (a, b) = (kept[i].1, kept[(i + 1) % kept.len()].1);
+                    area(a, b, *point).abs()
+                })
+                .fold(0., f64::max),
+        };
+        let best = (0..points.len())
+            .max_by(|&i, &j| gain(&points[i].1).total_cmp(&gain(&points[j].1)))
+            .unwrap();
+        kept.push(points.swap_remove(best));
+    }
+    kept
+}
+
+// Contact points of two boxes or hulls in contact along the normal (from A to B): the incident
+// face of one shape clipped to the reference face of the other, where it is below it. None if the
+// shapes aren't polyhedra or neither has a face along the normal.
+fn manifold(
+    a: &WorldShape,
+    b: &WorldShape,
+    normal: Vector,
+    depth: f64,
+) -> Option<Vec<(Vector, f64, Vector)>> {
+    let (face_a, normal_a) = support_face(a, normal, FACE_TOLERANCE)?;
+    let (face_b, normal_b) = support_face(b, -normal, FACE_TOLERANCE)?;
+    // a face at an angle to the normal is an edge contact, e.g. two tilted boxes
+    let alignment = |face: &Vec<Vector>, alignment: f64| {
+        (face.len() >= 3 && alignment >= MIN_FACE_ALIGNMENT).then_some(alignment)
+    };
+    let alignment_a = alignment(&face_a, normal_a.dot(&normal));
+    let alignment_b = alignment(&face_b, -normal_b.dot(&normal));
+
+    // the reference face is the one closer to the normal, preferring A
+    let (reference, reference_normal, incident, sign) =
+        if alignment_a.is_none() && alignment_b.is_none() {
+            return None;
+        } else if alignment_a.unwrap_or(-1.) + FACE_TOLERANCE >= alignment_b.unwrap_or(-1.) {
+            let (incident, _) = support_face(b, -normal, depth + FACE_TOLERANCE)?;
+            (face_a, normal_a, incident, 1.)
+        } else {
+            let (incident, _) = support_face(a, normal, depth + FACE_TOLERANCE)?;
+            (face_b, normal_b, incident, -1.)
+        };
+
+    let height = reference[0].dot(&reference_normal);
+    let points: Vec<(f64, Vector)> = clip_polygon(incident, &reference, reference_normal)
+        .into_iter()
+        .map(|point| (height - point.dot(&reference_normal), point))
+        .filter(|(depth, _)| *depth > 0.)
+        .fold(Vec::new(), |mut points, (depth, point)| {
+            // clipping an edge can give the same point twice
+            if points
+                .iter()
+                .all(|(_, other): &(f64, Vector)| (other - point).norm() > FACE_TOLERANCE)
+            {
+                points.push((depth, point));
+            }
+            points
+        });
+    let points = reduce_manifold(points, reference_normal);
+    (!points.is_empty()).then(|| {
+        points
+            .into_iter()
+            .map(|(depth, point)| {
+                (
+                    sign * reference_normal,
+                    depth,
+                    point + 0.5 * depth * reference_normal,
+                )
+            })
+            .collect()
+    })
+}
+
+// Contact points of two shapes: normal from A to B, depth and point. Boxes and hulls in contact
+// along a face get up to MAX_MANIFOLD points, so they rest on it, and other pairs one point.
+fn contact_points(a: &WorldShape, b: &WorldShape) -> Vec<(Vector, f64, Vector)> {
+    let Some((normal, depth, point)) = penetration(a, b) else {
+        return Vec::new();
+    };
+    manifold(a, b, normal, depth).unwrap_or_else(|| vec![(normal, depth, point)])
+}
+
+struct ColliderInstance<'a> {
+    body: Entity,
+    collider: &'a Collider,
+    shape: WorldShape<'a>,
+    bounds: (Vector, Vector),
+}
+
+// the combined stiffness or damping of two surfaces in contact, as springs in series
+fn series(a: f64, b: f64) -> f64 {
+    if a + b > 0. {
+        a * b / (a + b)
+    } else {
+        0.
+    }
+}
+
+pub fn collision_system(
+    mut joints: Query<&mut Joint>,
+    colliders: Query<(Entity, &Collider)>,
+    mut contacts: ResMut<Contacts>,
+) {
+    contacts.contacts.clear();
+
+    let mut instances = Vec::new();
+    for (body, collider) in colliders.iter() {
+        let Ok(joint) = joints.get(body) else {
+            continue;
+        };
+        for (shape, frame) in collider.shapes.iter() {
+            let x = *frame * joint.x;
+            let shape = WorldShape {
+                shape,
+                rotation: x.rotation.transpose(),
+                position: x.position,
+            };
+            let bounds = shape.bounds();
+            instances.push(ColliderInstance {
+                body,
+                collider,
+                shape,
+                bounds,
+            });
+        }
+    }
+
+    // sweep and prune along x
+    instances.sort_by(|a, b| a.bounds.0.x.total_cmp(&b.bounds.0.x));
+    let mut pairs = Vec::new();
+    for (i, a) in instances.iter().enumerate() {
+        for (j, b) in instances.iter().enumerate().skip(i + 1) {
+            if b.bounds.0.x > a.bounds.1.x {
+                break;
+            }
+            let same_group = a.collider.group.is_some() && a.collider.group == b.collider.group;
+            let overlap = (1..3).all(|axis| {
+                a.bounds.0[axis] <= b.bounds.1[axis] && b.bounds.0[axis] <= a.bounds.1[axis]
+            });
+            if a.body != b.body && !same_group && overlap {
+                pairs.push((i, j));
+            }
+        }
+    }
+
+    let points = pairs.into_iter().flat_map(|(i, j)| {
+        let (a, b) = (&instances[i], &instances[j]);
+        contact_points(&a.shape, &b.shape)
+            .into_iter()
+            .map(move |(normal, depth, point)| (a, b, normal, depth, point))
+    });
+    for (a, b, normal, depth, point) in points {
+        let (Ok(joint_a), Ok(joint_b)) = (joints.get(a.body), joints.get(b.body)) else {
+            continue;
+        };
+        // velocity of body b relative to body a at the contact point
+        let velocity = (joint_b.x.inverse() * joint_b.v).velocity_point(point).vel
+            - (joint_a.x.inverse() * joint_a.v).velocity_point(point).vel;
+        let normal_velocity = velocity.dot(&normal);
+        let tangential_velocity = velocity - normal_velocity * normal;
+
+        let stiffness = series(a.collider.stiffness, b.collider.stiffness);
+        let damping = series(a.collider.damping, b.collider.damping);
+        let friction = (a.collider.friction * b.collider.friction).sqrt();
+        let normal_force = (stiffness * depth - damping * normal_velocity).max(0.);
+
+        let friction_force =
+            regularized_friction(normal_force, tangential_velocity, friction, SLIP_SPEED);
+
+        let force = normal_force * normal + friction_force;
+        if let Ok(mut joint) = joints.get_mut(b.body) {
+            joint.f_ext += Force::force_point(force, point);
+        }
+        if let Ok(mut joint) = joints.get_mut(a.body) {
+            joint.f_ext -= Force::force_point(force, point);
+        }
+        contacts.contacts.push(Contact {
+            body_a: a.body,
+            body_b: b.body,
+            point,
+            normal,
+            depth,
+            force,
+        });
+    }
+}
@@ -0,0 +1,509 @@
+use bevy::prelude::*;
+use bevy_integrator::{
+    PhysicsSchedule, PhysicsSet, PhysicsState, PostStepSchedule, StateMap, Stateful,
+};
+use nalgebra::{DMatrix, DVector, Rotation3, UnitQuaternion};
+
+use crate::{
+    algorithms::{
+        apply_external_update, articulated_reset_update, kinematics_update, loop_2_update,
+        loop_3_update,
+    },
+    health::health_monitor_system,
+    joint::Joint,
+    structure::{outward_pass, JointTree, TreeJoints},
+    sva::{Force, Motion, Vector, Xform},
+};
+
+// Closed kinematic loops, e.g. suspension linkages and anti-roll bars. Each LoopConstraint joins
+// a frame on one body to a frame on another body of the joint tree (or a Base). The constraint
+// forces are solved for after the articulated-body passes, and applied as external forces.
+pub struct LoopConstraintPlugin;
+
+impl Plugin for LoopConstraintPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PhysicsSchedule,
+            loop_constraint_system.in_set(PhysicsSet::Post),
+        )
+        // after every step, so the projected state is recorded and snapshotted
+        .add_systems(
+            PostStepSchedule,
+            constraint_projection_system.after(health_monitor_system),
+        );
+    }
+}
+
+// How the drift of the constraint positions and velocities is corrected
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stabilization {
+    None,
+    // the constraint acceleration is -2 alpha * velocity error - beta^2 * position error
+    Baumgarte { alpha: f64, beta: f64 },
+    // project the positions and then the velocities onto the constraints after each step
+    Projection { iterations: usize, tolerance: f64 },
+}
+
+impl Default for Stabilization {
+    fn default() -> Self {
+        Stabilization::Baumgarte {
+            alpha: 20.,
+            beta: 20.,
+        }
+    }
+}
+
+// Constrains the relative motion of frame_b on body_b to frame_a on body_a. The frames are
+// given relative to the body coordinates like Joint::xt, and axes selects the constrained
+// rotations and translations (x, y, z each) along the axes of frame_a.
+#[derive(Component, Clone, Debug)]
+pub struct LoopConstraint {
+    pub name: String,
+    pub body_a: Entity,
+    pub frame_a: Xform,
+    pub body_b: Entity,
+    pub frame_b: Xform,
+    pub axes: [bool; 6], // rotations x, y, z, then translations x, y, z
+    pub stabilization: Stabilization,
+    pub force: Force, // last constraint force on body_a in base coordinates, body_b gets -force
+}
+
+impl LoopConstraint {
+    pub fn new(
+        name: String,
+        (body_a, frame_a): (Entity, Xform),
+        (body_b, frame_b): (Entity, Xform),
+        axes: [bool; 6],
+    ) -> Self {
+        Self {
+            name,
+            body_a,
+            frame_a,
+            body_b,
+            frame_b,
+            axes,
+            stabilization: Stabilization::default(),
+            force: Force::zero(),
+        }
+    }
+
+    // the frames coincide
+    pub fn weld(name: String, a: (Entity, Xform), b: (Entity, Xform)) -> Self {
+        Self::new(name, a, b, [true; 6])
+    }
+
+    // the frame origins coincide, e.g. a ball joint at the end of a link
+    pub fn ball(name: String, a: (Entity, Xform), b: (Entity, Xform)) -> Self {
+        Self::new(name, a, b, [false, false, false, true, true, true])
+    }
+
+    // the frame origins and z axes coincide, a revolute joint about z
+    pub fn hinge(name: String, a: (Entity, Xform), b: (Entity, Xform)) -> Self {
+        Self::new(name, a, b, [true, true, false, true, true, true])
+    }
+
+    pub fn with_stabilization(mut self, stabilization: Stabilization) -> Self {
+        self.stabilization = stabilization;
+        self
+    }
+}
+
+// The constrained directions of a LoopConstraint as unit forces on body_a in base coordinates,
+// and the position and velocity errors along them. Bushings use them for their deflections.
+pub(crate) struct ConstraintRows {
+    pub directions: Vec<Force>,
+    pub position_error: Vec<f64>,
+    pub velocity_error: Vec<f64>,
+    pub bias: Vec<f64>, // rate of change of the directions times the relative velocity
+}
+
+fn velocity_in_base(joint: &Joint) -> Motion {
+    joint.x.inverse() * joint.v
+}
+
+fn acceleration_in_base(joint: &Joint) -> Motion {
+    joint.x.inverse() * joint.a
+}
+
+fn constraint_rows(constraint: &LoopConstraint, a: &Joint, b: &Joint) -> ConstraintRows {
+    frame_rows(
+        constraint.frame_a,
+        constraint.frame_b,
+        constraint.axes,
+        a,
+        b,
+    )
+}
+
+// rows of the selected axes of frame_a on body a, relative to frame_b on body b
+pub(crate) fn frame_rows(
+    frame_a: Xform,
+    frame_b: Xform,
+    axes: [bool; 6],
+    a: &Joint,
+    b: &Joint,
+) -> ConstraintRows {
+    let xa = frame_a * a.x;
+    let xb = frame_b * b.x;
+    let rotation_a = xa.rotation.transpose();
+    let rotation_b = xb.rotation.transpose();
+
+    // rotation from frame b to frame a, and the offset of the origins
+    let rotation_error =
+        Rotation3::from_matrix_unchecked(rotation_a * rotation_b.transpose()).scaled_axis();
+    let position_error = xa.position - xb.position;
+
+    let va = velocity_in_base(a);
+    let relative_velocity = va - velocity_in_base(b);
+
+    let mut rows = ConstraintRows {
+        directions: Vec::new(),
+        position_error: Vec::new(),
+        velocity_error: Vec::new(),
+        bias: Vec::new(),
+    };
+    for (index, constrained) in axes.iter().enumerate() {
+        if !*constrained {
+            continue;
+        }
+        let axis: Vector = rotation_a.column(index % 3).into();
+        let (direction, error) = if index < 3 {
+            (
+                Force {
+                    f: Vector::zeros(),
+                    m: axis,
+                },
+                axis.dot(&rotation_error),
+            )
+        } else {
+            (
+                Force::force_point(axis, xa.position),
+                axis.dot(&position_error),
+            )
+        };
+        rows.directions.push(direction);
+        rows.position_error.push(error);
+        rows.velocity_error.push(&direction * &relative_velocity);
+        // the directions move with body a
+        rows.bias.push(&va.cross_f(direction) * &relative_velocity);
+    }
+    rows
+}
+
+// constraint accelerations of all rows, with the current joint accelerations
+fn constraint_accelerations(
+    constraints: &[(Entity, Entity, ConstraintRows)],
+    joint_query: &Query<(Entity, &mut Joint)>,
+) -> DVector<f64> {
+    let mut accelerations = Vec::new();
+    for (body_a, body_b, rows) in constraints.iter() {
+        let (Ok((_, a)), Ok((_, b))) = (joint_query.get(*body_a), joint_query.get(*body_b)) else {
+            continue;
+        };
+        let relative_acceleration = acceleration_in_base(a) - acceleration_in_base(b);
+        for (direction, bias) in rows.directions.iter().zip(rows.bias.iter()) {
+            accelerations.push(direction * &relative_acceleration + bias);
+        }
+    }
+    DVector::from_vec(accelerations)
+}
+
+// run the inward and outward passes again after the external forces changed
+fn articulated_passes(tree: &JointTree, joint_query: &mut Query<(Entity, &mut Joint)>) {
+    let mut joints = TreeJoints::gather(tree, joint_query);
+    joints.outward_pass(articulated_reset_update);
+    joints.outward_pass(apply_external_update);
+    joints.inward_pass(loop_2_update);
+    joints.outward_pass(loop_3_update);
+}
+
+fn add_constraint_force(
+    joint_query: &mut Query<(Entity, &mut Joint)>,
+    body_a: Entity,
+    body_b: Entity,
+    force: Force,
+) {
+    if let Ok((_, mut a)) = joint_query.get_mut(body_a) {
+        a.f_ext += force;
+    }
+    if let Ok((_, mut b)) = joint_query.get_mut(body_b) {
+        b.f_ext -= force;
+    }
+}
+
+// least squares solution of minimum norm, as the rows of loops are often redundant
+fn least_squares(matrix: DMatrix<f64>, rhs: &DVector<f64>) -> DVector<f64> {
+    matrix
+        .svd(true, true)
+        .solve(rhs, 1e-9)
+        .unwrap_or_else(|_| DVector::zeros(rhs.len()))
+}
+
+// The accelerations are affine in the external forces, so the response of the constraint
+// accelerations to each constraint direction is found by running the passes again with a unit
+// force along it. This costs one extra inward and outward pass per constrained direction.
+pub fn loop_constraint_system(
+    tree: Res<JointTree>,
+    mut joint_query: Query<(Entity, &mut Joint)>,
+    mut constraint_query: Query<&mut LoopConstraint>,
+) {
+    let mut constraints = Vec::new();
+    let mut target = Vec::new();
+    for constraint in constraint_query.iter() {
+        let (Ok((_, a)), Ok((_, b))) = (
+            joint_query.get(constraint.body_a),
+            joint_query.get(constraint.body_b),
+        ) else {
+            continue;
+        };
+        let rows = constraint_rows(constraint, a, b);
+        for (error, velocity_error) in rows.position_error.iter().zip(rows.velocity_error.iter()) {
+            target.push(match constraint.stabilization {
+                Stabilization::Baumgarte { alpha, beta } => {
+                    -2. * alpha * velocity_error - beta.powi(2) * error
+                }
+                _ => 0.,
+            });
+        }
+        constraints.push((constraint.body_a, constraint.body_b, rows));
+    }
+    if target.is_empty() {
+        return;
+    }
+
+    let accelerations = constraint_accelerations(&constraints, &joint_query);
+    let mut response = DMatrix::zeros(target.len(), target.len());
+    let mut column = 0;
+    for (body_a, body_b, rows) in constraints.iter() {
+        for direction in rows.directions.iter() {
+            add_constraint_force(&mut joint_query, *body_a, *body_b, *direction);
+            articulated_passes(&tree, &mut joint_query);
+            let probe = constraint_accelerations(&constraints, &joint_query);
+            response.set_column(column, &(probe - &accelerations));
+            add_constraint_force(&mut joint_query, *body_a, *body_b, -1. * *direction);
+            column += 1;
+        }
+    }
+
+    let lambda = least_squares(response, &(DVector::from_vec(target) - accelerations));
+
+    let mut row = 0;
+    let mut constraint_iter = constraint_query.iter_mut();
+    for (body_a, body_b, rows) in constraints.iter() {
+        let mut force = Force::zero();
+        for direction in rows.directions.iter() {
+            force += lambda[row] * *direction;
+            row += 1;
+        }
+        add_constraint_force(&mut joint_query, *body_a, *body_b, force);
+        // the constraints were collected in query order, skipping those with missing bodies
+        for mut constraint in constraint_iter.by_ref() {
+            if constraint.body_a == *body_a && constraint.body_b == *body_b {
+                constraint.force = force;
+                break;
+            }
+        }
+    }
+    articulated_passes(&tree, &mut joint_query);
+}
+
+// the joints between a body and its Base
+fn path_to_base(tree: &JointTree, body: Entity) -> Vec<Entity> {
+    let mut path = Vec::new();
+    let mut next = Some(body);
+    while let Some(entity) = next {
+        path.push(entity);
+        next = tree.parent(entity);
+    }
+    path
+}
+
+struct ProjectionColumns {
+    joints: Vec<(Entity, usize)>, // joint and its first column
+    len: usize,
+}
+
+fn projection_columns(
+    paths: &[(Vec<Entity>, Vec<Entity>)],
+    joint_query: &Query<(Entity, &mut Joint)>,
+) -> ProjectionColumns {
+    let mut columns = ProjectionColumns {
+        joints: Vec::new(),
+        len: 0,
+    };
+    for (path_a, path_b) in paths.iter() {
+        for entity in path_a.iter().chain(path_b.iter()) {
+            if columns.joints.iter().any(|(joint, _)| joint == entity) {
+                continue;
+            }
+            let Ok((_, joint)) = joint_query.get(*entity) else {
+                continue;
+            };
+            let size = joint.motion_subspace().len();
+            if size > 0 {
+                columns.joints.push((*entity, columns.len));
+                columns.len += size;
+            }
+        }
+    }
+    columns
+}
+
+// the constraint Jacobian with respect to the joint velocities, and the errors
+fn constraint_jacobian(
+    constraints: &[&LoopConstraint],
+    paths: &[(Vec<Entity>, Vec<Entity>)],
+    columns: &ProjectionColumns,
+    joint_query: &Query<(Entity, &mut Joint)>,
+) -> (DMatrix<f64>, DVector<f64>, DVector<f64>) {
+    let mut jacobian_rows = Vec::new();
+    let mut position_error = Vec::new();
+    let mut velocity_error = Vec::new();
+    for (constraint, (path_a, path_b)) in constraints.iter().zip(paths.iter()) {
+        let (Ok((_, a)), Ok((_, b))) = (
+            joint_query.get(constraint.body_a),
+            joint_query.get(constraint.body_b),
+        ) else {
+            continue;
+        };
+        let rows = constraint_rows(constraint, a, b);
+        for direction in rows.directions.iter() {
+            let mut jacobian_row = vec![0.; columns.len];
+            for (entity, start) in columns.joints.iter() {
+                let sign = path_a.contains(entity) as i32 - path_b.contains(entity) as i32;
+                if sign == 0 {
+                    continue;
+                }
+                let Ok((_, joint)) = joint_query.get(*entity) else {
+                    continue;
+                };
+                let x_inverse = joint.x.inverse();
+                for (index, s) in joint.motion_subspace().into_iter().enumerate() {
+                    jacobian_row[start + index] = sign as f64 * (direction * &(x_inverse * s));
+                }
+            }
+            jacobian_rows.push(jacobian_row);
+        }
+        position_error.extend(rows.position_error);
+        velocity_error.extend(rows.velocity_error);
+    }
+    let jacobian = DMatrix::from_fn(jacobian_rows.len(), columns.len, |i, j| jacobian_rows[i][j]);
+    (
+        jacobian,
+        DVector::from_vec(position_error),
+        DVector::from_vec(velocity_error),
+    )
+}
+
+fn set_joint_states(
+    tree: &JointTree,
+    states: &StateMap<Joint>,
+    joint_query: &mut Query<(Entity, &mut Joint)>,
+) {
+    for (entity, state) in states.iter() {
+        if let Ok((_, mut joint)) = joint_query.get_mut(entity) {
+            joint.set_state(state);
+        }
+    }
+    outward_pass(tree, joint_query, kinematics_update);
+}
+
+// add a correction of the joint velocities (or a small displacement, with positions) to the states
+fn apply_correction(
+    states: &mut StateMap<Joint>,
+    columns: &ProjectionColumns,
+    correction: &DVector<f64>,
+    positions: bool,
+) {
+    for (entity, start) in columns.joints.iter() {
+        let Some(state) = states.get_mut(entity) else {
+            continue;
+        };
+        match &mut state.floating {
+            Some(floating) => {
+                let v = Vector::new(
+                    correction[*start],
+                    correction[start + 1],
+                    correction[start + 2],
+                );
+                let w = Vector::new(
+                    correction[start + 3],
+                    correction[start + 4],
+                    correction[start + 5],
+                );
+                if positions {
+                    let orientation = UnitQuaternion::from_quaternion(floating.orientation);
+                    floating.position += orientation * v;
+                    floating.orientation =
+                        (orientation * UnitQuaternion::from_scaled_axis(w)).into_inner();
+                } else {
+                    floating.velocity = floating.velocity + Motion { v, w };
+                }
+            }
+            None if positions => state.q += correction[*start],
+            None => state.qd += correction[*start],
+        }
+    }
+}
+
+// Newton iterations on the positions, then one projection of the velocities, for the constraints
+// with Stabilization::Projection. The corrections are the smallest ones in joint coordinates.
+fn constraint_projection_system(
+    tree: Res<JointTree>,
+    physics_state: Option<ResMut<PhysicsState<Joint>>>,
+    mut joint_query: Query<(Entity, &mut Joint)>,
+    constraint_query: Query<&LoopConstraint>,
+) {
+    let Some(mut physics_state) = physics_state else {
+        return;
+    };
+    let mut iterations = 0;
+    let mut tolerance = f64::INFINITY;
+    let mut constraints = Vec::new();
+    for constraint in constraint_query.iter() {
+        if let Stabilization::Projection {
+            iterations: constraint_iterations,
+            tolerance: constraint_tolerance,
+        } = constraint.stabilization
+        {
+            iterations = iterations.max(constraint_iterations);
+            tolerance = tolerance.min(constraint_tolerance);
+            constraints.push(constraint);
+        }
+    }
+    if constraints.is_empty() {
+        return;
+    }
+
+    let paths: Vec<(Vec<Entity>, Vec<Entity>)> = constraints
+        .iter()
+        .map(|constraint| {
+            (
+                path_to_base(&tree, constraint.body_a),
+                path_to_base(&tree, constraint.body_b),
+            )
+        })
+        .collect();
+    let columns = projection_columns(&paths, &joint_query);
+    let mut states = physics_state.states.clone();
+
+    for _ in 0..iterations {
+        set_joint_states(&tree, &states, &mut joint_query);
+        let (jacobian, position_error, _) =
+            constraint_jacobian(&constraints, &paths, &columns, &joint_query);
+        if position_error.amax() <= tolerance {
+            break;
+        }
+        let correction = least_squares(jacobian, &(-position_error));
+        apply_correction(&mut states, &columns, &correction, true);
+    }
+
+    set_joint_states(&tree, &states, &mut joint_query);
+    let (jacobian, _, velocity_error) =
+        constraint_jacobian(&constraints, &paths, &columns, &joint_query);
+    let correction = least_squares(jacobian, &(-velocity_error));
+    apply_correction(&mut states, &columns, &correction, false);
+
+    set_joint_states(&tree, &states, &mut joint_query);
+    physics_state.states = states;
+}
@@ -1,7 +1,8 @@
-use crate::sva::Xform;
+use crate::sva::{Vector, Xform};
 use bevy::prelude::{Color, Component, Transform};
+use nalgebra::Rotation3;
 
-#[derive(Component, Debug)]
+#[derive(Component, Clone, Debug)]
 pub struct MeshDef {
     pub mesh_type: MeshTypeDef,
     pub transform: TransformDef,
@@ -24,6 +25,8 @@ pub enum TransformDef {
     RotationX(f64),
     RotationY(f64),
     RotationZ(f64),
+    // roll, pitch and yaw about the fixed x, y and z axes, as a URDF origin
+    Pose { position: [f64; 3], rpy: [f64; 3] },
 }
 
 impl Default for TransformDef {
@@ -41,6 +44,10 @@ impl From<&TransformDef> for Xform {
             TransformDef::RotationX(angle) => Xform::rotx(*angle),
             TransformDef::RotationY(angle) => Xform::roty(*angle),
             TransformDef::RotationZ(angle) => Xform::rotz(*angle),
+            TransformDef::Pose { position, rpy } => {
+                let rotation = Rotation3::from_euler_angles(rpy[0], rpy[1], rpy[2]);
+                Xform::new(Vector::from(*position), rotation.matrix().transpose())
+            }
             // TransformDef::RotationVector(vector) => Xform::from_rotation_vector(vector),
         }
     }
@@ -71,6 +78,15 @@ impl From<&TransformDef> for Transform {
                 let mut transform = Transform::IDENTITY;
                 transform.rotate_local_z(*angle as f32);
                 transform
+            }
+            TransformDef::Pose { position, rpy } => {
+                Transform::from_xyz(position[0] as f32, position[1] as f32, position[2] as f32)
+                    .with_rotation(bevy::math::Quat::from_euler(
+                        bevy::math::EulerRot::ZYX,
+                        rpy[2] as f32,
+                        rpy[1] as f32,
+                        rpy[0] as f32,
+                    ))
             } // TransformDef::RotationVector(vector) => Xform::from_rotation_vector(vector),
         }
     }
@@ -0,0 +1,268 @@
+use std::ops::Range;
+
+use bevy::prelude::*;
+use bevy_integrator::{StateMap, Stateful};
+use nalgebra::{DMatrix, DVector};
+
+use crate::{
+    algorithms::kinematics_update,
+    joint::{Joint, JointState, JointType},
+    structure::JointTree,
+    sva::{Force, InertiaAB, Motion},
+};
+
+#[derive(Clone, Copy, Debug)]
+enum Parent {
+    Base(usize),
+    Joint(usize),
+}
+
+// Joint-space dynamics of the joint tree: the mass matrix by the composite rigid body algorithm
+// (CRBA) and the joint forces for given accelerations by the recursive Newton-Euler algorithm
+// (RNEA). It works on copies of the joints, so the states can be changed without touching the
+// simulation, e.g. to evaluate a measured trajectory.
+//
+// Each joint has one coordinate, except floating joints with six: the velocity v then w in body
+// coordinates, as in the StateVector components. Their forces are f then m.
+pub struct JointSpace {
+    bases: Vec<Joint>,
+    joints: Vec<Joint>,
+    parents: Vec<Parent>,
+    entities: Vec<Entity>,
+    starts: Vec<usize>, // first coordinate of each joint
+    len: usize,
+}
+
+// copy of the definition and the state of a joint, the kinematics are updated from it
+fn copy_joint(joint: &Joint) -> Joint {
+    Joint {
+        joint_type: joint.joint_type,
+        name: joint.name.clone(),
+        s: joint.s,
+        i: joint.i,
+        xt: joint.xt,
+        q: joint.q,
+        qd: joint.qd,
+        qdd: joint.qdd,
+        floating: joint.floating,
+        x: joint.x,
+        a: joint.a,
+        tau: joint.tau,
+        f_ext: joint.f_ext,
+        ..default()
+    }
+}
+
+impl JointSpace {
+    // joint gives the current joint of an entity, e.g. |entity| joint_query.get(entity).ok()
+    pub fn new<'a>(tree: &JointTree, mut joint: impl FnMut(Entity) -> Option<&'a Joint>) -> Self {
+        let mut joint_space = Self {
+            bases: Vec::new(),
+            joints: Vec::new(),
+            parents: Vec::new(),
+            entities: Vec::new(),
+            starts: Vec::new(),
+            len: 0,
+        };
+        for base in tree.bases() {
+            let base_joint = joint(*base).map_or_else(|| Joint::base(Motion::zero()), copy_joint);
+            joint_space.bases.push(base_joint);
+        }
+
+        for (entity, parent) in tree.iter() {
+            let Some(child) = joint(entity) else {
+                continue;
+            };
+            let parent = match tree.bases().iter().position(|base| *base == parent) {
+                Some(base) => Parent::Base(base),
+                None => match joint_space.entities.iter().position(|e| *e == parent) {
+                    Some(index) => Parent::Joint(index),
+                    None => continue,
+                },
+            };
+            let child = copy_joint(child);
+            joint_space.starts.push(joint_space.len);
+            joint_space.len += child.motion_subspace().len();
+            joint_space.joints.push(child);
+            joint_space.parents.push(parent);
+            joint_space.entities.push(entity);
+        }
+        joint_space.update_kinematics();
+        joint_space
+    }
+
+    // all joints of the JointTree in the world
+    pub fn from_world(world: &mut World) -> Self {
+        let mut joint_query = world.query::<&Joint>();
+        let tree = world.resource::<JointTree>();
+        Self::new(tree, |entity| joint_query.get(world, entity).ok())
+    }
+
+    // number of joint coordinates
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // joints in the order of the coordinates
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    // coordinates of a joint
+    pub fn range(&self, entity: Entity) -> Option<Range<usize>> {
+        let index = self.entities.iter().position(|e| *e == entity)?;
+        let start = self.starts[index];
+        Some(start..start + self.joints[index].motion_subspace().len())
+    }
+
+    pub fn set_state(&mut self, entity: Entity, state: &JointState) {
+        if let Some(index) = self.entities.iter().position(|e| *e == entity) {
+            self.joints[index].set_state(state);
+            self.update_kinematics();
+        }
+    }
+
+    pub fn set_states(&mut self, states: &StateMap<Joint>) {
+        for (entity, joint) in self.entities.iter().zip(self.joints.iter_mut()) {
+            if let Some(state) = states.get(entity) {
+                joint.set_state(state);
+            }
+        }
+        self.update_kinematics();
+    }
+
+    // joint velocities
+    pub fn velocities(&self) -> DVector<f64> {
+        self.gather(|joint| match joint.joint_type {
+            JointType::Floating => motion_components(joint.floating.velocity),
+            _ => vec![joint.qd],
+        })
+    }
+
+    // joint accelerations of the copied joints, e.g. the articulated-body solution
+    pub fn accelerations(&self) -> DVector<f64> {
+        self.gather(|joint| match joint.joint_type {
+            JointType::Floating => motion_components(joint.floating.acceleration),
+            _ => vec![joint.qdd],
+        })
+    }
+
+    // joint forces of the copied joints
+    pub fn joint_forces(&self) -> DVector<f64> {
+        self.gather(|joint| match joint.joint_type {
+            JointType::Floating => force_components(joint.floating.tau),
+            _ => vec![joint.tau],
+        })
+    }
+
+    // Composite rigid body algorithm. The mass matrix is symmetric, and zero between joints
+    // on different branches of the tree.
+    pub fn mass_matrix(&self) -> DMatrix<f64> {
+        let mut composite: Vec<InertiaAB> =
+            self.joints.iter().map(|joint| joint.i.into()).collect();
+        for index in (0..self.joints.len()).rev() {
+            if let Parent::Joint(parent) = self.parents[index] {
+                let inertia = self.joints[index].xl.inverse() * composite[index];
+                composite[parent] += inertia;
+            }
+        }
+
+        let mut mass_matrix = DMatrix::zeros(self.len, self.len);
+        for (index, joint) in self.joints.iter().enumerate() {
+            let subspace_i = joint.motion_subspace();
+            for (column, s) in subspace_i.iter().enumerate() {
+                let i = self.starts[index] + column;
+                let mut force = composite[index] * *s;
+                for (row, s) in subspace_i.iter().enumerate() {
+                    mass_matrix[(self.starts[index] + row, i)] = &force * s;
+                }
+
+                // up the tree to the base
+                let mut child = index;
+                while let Parent::Joint(parent) = self.parents[child] {
+                    force = self.joints[child].xl.inverse() * force;
+                    for (row, s) in self.joints[parent].motion_subspace().iter().enumerate() {
+                        let j = self.starts[parent] + row;
+                        mass_matrix[(j, i)] = &force * s;
+                        mass_matrix[(i, j)] = mass_matrix[(j, i)];
+                    }
+                    child = parent;
+                }
+            }
+        }
+        mass_matrix
+    }
+
+    // Recursive Newton-Euler algorithm: the joint forces for the joint accelerations qdd at the
+    // current state, with the gravity of the bases and the external forces of the joints.
+    pub fn inverse_dynamics(&self, qdd: &DVector<f64>) -> DVector<f64> {
+        let mut accelerations = vec![Motion::zero(); self.joints.len()];
+        let mut forces = vec![Force::zero(); self.joints.len()];
+        for (index, joint) in self.joints.iter().enumerate() {
+            let parent_acceleration = match self.parents[index] {
+                Parent::Base(base) => self.bases[base].a,
+                Parent::Joint(parent) => accelerations[parent],
+            };
+            let mut acceleration = joint.xl * parent_acceleration + joint.c;
+            for (column, s) in joint.motion_subspace().into_iter().enumerate() {
+                acceleration = acceleration + qdd[self.starts[index] + column] * s;
+            }
+            accelerations[index] = acceleration;
+            forces[index] =
+                joint.i * acceleration + joint.v.cross_f(joint.i * joint.v) - joint.x * joint.f_ext;
+        }
+
+        let mut tau = DVector::zeros(self.len);
+        for index in (0..self.joints.len()).rev() {
+            let joint = &self.joints[index];
+            for (column, s) in joint.motion_subspace().iter().enumerate() {
+                tau[self.starts[index] + column] = &forces[index] * s;
+            }
+            if let Parent::Joint(parent) = self.parents[index] {
+                let force = joint.xl.inverse() * forces[index];
+                forces[parent] += force;
+            }
+        }
+        tau
+    }
+
+    // Coriolis, centrifugal, gravity and external forces, the joint forces for zero accelerations
+    pub fn bias_forces(&self) -> DVector<f64> {
+        self.inverse_dynamics(&DVector::zeros(self.len))
+    }
+
+    // joint accelerations for the joint forces tau, from the mass matrix
+    pub fn forward_dynamics(&self, tau: &DVector<f64>) -> Option<DVector<f64>> {
+        let cholesky = self.mass_matrix().cholesky()?;
+        Some(cholesky.solve(&(tau - self.bias_forces())))
+    }
+
+    fn update_kinematics(&mut self) {
+        for index in 0..self.joints.len() {
+            let (head, tail) = self.joints.split_at_mut(index);
+            let parent = match self.parents[index] {
+                Parent::Base(base) => &self.bases[base],
+                Parent::Joint(parent) => &head[parent],
+            };
+            let joint = &mut tail[0];
+            kinematics_update(joint, parent);
+            joint.c = joint.v.cross_v(joint.vj);
+        }
+    }
+
+    fn gather(&self, components: impl Fn(&Joint) -> Vec<f64>) -> DVector<f64> {
+        DVector::from_iterator(self.len, self.joints.iter().flat_map(components))
+    }
+}
+
+fn motion_components(motion: Motion) -> Vec<f64> {
+    motion.v.iter().chain(motion.w.iter()).copied().collect()
+}
+
+fn force_components(force: Force) -> Vec<f64> {
+    force.f.iter().chain(force.m.iter()).copied().collect()
+}
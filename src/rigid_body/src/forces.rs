@@ -0,0 +1,289 @@
+use bevy::prelude::*;
+use bevy_integrator::{PhysicsSchedule, PhysicsSet};
+
+use crate::{
+    constraints::frame_rows,
+    joint::Joint,
+    kinematics::BodyKinematics,
+    sva::{Force, Vector, Xform},
+};
+
+// Force elements between bodies: springs, dampers and bushings. Each element is a component on
+// its own entity, joining two bodies of the joint tree (or a body and a Base), and adds its
+// forces to the f_ext of both in PhysicsSet::Evaluate.
+pub struct ForceElementPlugin;
+
+impl Plugin for ForceElementPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PhysicsSchedule,
+            (
+                spring_damper_system,
+                point_spring_damper_system,
+                bushing_system,
+                rotational_spring_system,
+            )
+                .in_set(PhysicsSet::Evaluate),
+        );
+    }
+}
+
+// Linear spring-damper on the coordinate q of a 1-DoF joint, on the joint entity
+#[derive(Component, Clone, Copy, Debug)]
+pub struct SpringDamper {
+    pub stiffness: f64,
+    pub damping: f64,
+    pub preload: f64, // force at q = 0
+}
+
+impl SpringDamper {
+    pub fn new(stiffness: f64, damping: f64) -> Self {
+        Self {
+            stiffness,
+            damping,
+            preload: 0.,
+        }
+    }
+}
+
+pub fn spring_damper_system(mut joints: Query<(&mut Joint, &SpringDamper)>) {
+    for (mut joint, spring_damper) in joints.iter_mut() {
+        joint.tau -= spring_damper.stiffness * joint.q
+            + spring_damper.damping * joint.qd
+            + spring_damper.preload;
+    }
+}
+
+// Spring-damper along the line between a point on body_a and a point on body_b, e.g. a coil-over
+// or a tow rope. The points are in body coordinates. A positive tension pulls the points together.
+#[derive(Component, Clone, Debug)]
+pub struct PointSpringDamper {
+    pub name: String,
+    pub body_a: Entity,
+    pub point_a: Vector,
+    pub body_b: Entity,
+    pub point_b: Vector,
+    pub stiffness: f64,
+    pub damping: f64,
+    pub free_length: f64,
+    pub preload: f64,       // tension at the free length
+    pub tension_only: bool, // a rope or a cable, which can't push
+    pub tension: f64,       // last tension
+}
+
+impl PointSpringDamper {
+    pub fn new(
+        name: String,
+        (body_a, point_a): (Entity, Vector),
+        (body_b, point_b): (Entity, Vector),
+        stiffness: f64,
+        damping: f64,
+        free_length: f64,
+    ) -> Self {
+        Self {
+            name,
+            body_a,
+            point_a,
+            body_b,
+            point_b,
+            stiffness,
+            damping,
+            free_length,
+            preload: 0.,
+            tension_only: false,
+            tension: 0.,
+        }
+    }
+
+    // a rope: no force while slack
+    pub fn rope(
+        name: String,
+        a: (Entity, Vector),
+        b: (Entity, Vector),
+        stiffness: f64,
+        damping: f64,
+        length: f64,
+    ) -> Self {
+        Self {
+            tension_only: true,
+            ..Self::new(name, a, b, stiffness, damping, length)
+        }
+    }
+}
+
+// Six-axis bushing between frame_a on body_a and frame_b on body_b, e.g. an engine mount or a
+// suspension bushing. The stiffness and damping are along the axes of frame_a, rotations x, y, z
+// then translations x, y, z as for a LoopConstraint. It is unloaded when the frames coincide.
+#[derive(Component, Clone, Debug)]
+pub struct Bushing {
+    pub name: String,
+    pub body_a: Entity,
+    pub frame_a: Xform,
+    pub body_b: Entity,
+    pub frame_b: Xform,
+    pub stiffness: [f64; 6],
+    pub damping: [f64; 6],
+    pub force: Force, // last force on body_a in base coordinates, body_b gets -force
+}
+
+impl Bushing {
+    pub fn new(
+        name: String,
+        (body_a, frame_a): (Entity, Xform),
+        (body_b, frame_b): (Entity, Xform),
+        stiffness: [f64; 6],
+        damping: [f64; 6],
+    ) -> Self {
+        Self {
+            name,
+            body_a,
+            frame_a,
+            body_b,
+            frame_b,
+            stiffness,
+            damping,
+            force: Force::zero(),
+        }
+    }
+}
+
+// Torsion spring-damper about the z axis of frame_a, between frame_a on body_a and frame_b on
+// body_b, e.g. an anti-roll bar between the suspension arms. The angle is zero when the frames
+// coincide.
+#[derive(Component, Clone, Debug)]
+pub struct RotationalSpring {
+    pub name: String,
+    pub body_a: Entity,
+    pub frame_a: Xform,
+    pub body_b: Entity,
+    pub frame_b: Xform,
+    pub stiffness: f64,
+    pub damping: f64,
+    pub preload: f64, // torque at zero angle
+    pub torque: f64,  // last torque on body_a about the axis
+}
+
+impl RotationalSpring {
+    pub fn new(
+        name: String,
+        (body_a, frame_a): (Entity, Xform),
+        (body_b, frame_b): (Entity, Xform),
+        stiffness: f64,
+        damping: f64,
+    ) -> Self {
+        Self {
+            name,
+            body_a,
+            frame_a,
+            body_b,
+            frame_b,
+            stiffness,
+            damping,
+            preload: 0.,
+            torque: 0.,
+        }
+    }
+}
+
+// Coulomb friction on a sliding contact, regularized to be linear in the tangential velocity
+// below slip_speed so it doesn't chatter at rest. Opposes the tangential velocity.
+pub fn regularized_friction(
+    normal_force: f64,
+    tangential_velocity: Vector,
+    friction: f64,
+    slip_speed: f64,
+) -> Vector {
+    let speed = tangential_velocity.norm();
+    if speed > f64::EPSILON {
+        -friction * normal_force * (speed / slip_speed).min(1.) * tangential_velocity / speed
+    } else {
+        Vector::zeros()
+    }
+}
+
+fn add_element_force(joints: &mut Query<&mut Joint>, body_a: Entity, body_b: Entity, force: Force) {
+    if let Ok(mut a) = joints.get_mut(body_a) {
+        a.f_ext += force;
+    }
+    if let Ok(mut b) = joints.get_mut(body_b) {
+        b.f_ext -= force;
+    }
+}
+
+pub fn point_spring_damper_system(
+    mut joints: Query<&mut Joint>,
+    mut springs: Query<&mut PointSpringDamper>,
+) {
+    for mut spring in springs.iter_mut() {
+        let Ok([a, b]) = joints.get_many([spring.body_a, spring.body_b]) else {
+            continue;
+        };
+        let a = BodyKinematics::new(a, None).point(spring.point_a);
+        let b = BodyKinematics::new(b, None).point(spring.point_b);
+
+        let offset = b.position - a.position;
+        let length = offset.norm();
+        if length < f64::EPSILON {
+            spring.tension = 0.;
+            continue;
+        }
+        let direction = offset / length;
+        let rate = direction.dot(&(b.velocity - a.velocity));
+        let mut tension = spring.stiffness * (length - spring.free_length)
+            + spring.damping * rate
+            + spring.preload;
+        if spring.tension_only && (length < spring.free_length || tension < 0.) {
+            tension = 0.;
+        }
+        spring.tension = tension;
+
+        let force_a = Force::force_point(tension * direction, a.position);
+        let force_b = Force::force_point(tension * direction, b.position);
+        if let Ok(mut joint) = joints.get_mut(spring.body_a) {
+            joint.f_ext += force_a;
+        }
+        if let Ok(mut joint) = joints.get_mut(spring.body_b) {
+            joint.f_ext -= force_b;
+        }
+    }
+}
+
+pub fn bushing_system(mut joints: Query<&mut Joint>, mut bushings: Query<&mut Bushing>) {
+    for mut bushing in bushings.iter_mut() {
+        let Ok([a, b]) = joints.get_many([bushing.body_a, bushing.body_b]) else {
+            continue;
+        };
+        let rows = frame_rows(bushing.frame_a, bushing.frame_b, [true; 6], a, b);
+        let mut force = Force::zero();
+        for (index, direction) in rows.directions.iter().enumerate() {
+            let magnitude = bushing.stiffness[index] * rows.position_error[index]
+                + bushing.damping[index] * rows.velocity_error[index];
+            force -= magnitude * *direction;
+        }
+        bushing.force = force;
+        add_element_force(&mut joints, bushing.body_a, bushing.body_b, force);
+    }
+}
+
+pub fn rotational_spring_system(
+    mut joints: Query<&mut Joint>,
+    mut springs: Query<&mut RotationalSpring>,
+) {
+    let axes = [false, false, true, false, false, false];
+    for mut spring in springs.iter_mut() {
+        let Ok([a, b]) = joints.get_many([spring.body_a, spring.body_b]) else {
+            continue;
+        };
+        let rows = frame_rows(spring.frame_a, spring.frame_b, axes, a, b);
+        let torque = -(spring.stiffness * rows.position_error[0]
+            + spring.damping * rows.velocity_error[0]
+            + spring.preload);
+        spring.torque = torque;
+        add_element_force(
+            &mut joints,
+            spring.body_a,
+            spring.body_b,
+            torque * rows.directions[0],
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use std::collections::{HashMap, VecDeque};
+
+use bevy::prelude::*;
+use bevy_integrator::{
+    implicit::ImplicitStep, IntegratedState, PhysicsSchedule, PhysicsSet, PhysicsState,
+    PostStepSchedule, SimTime, SolverState, StateMap, StateVector, Stateful,
+};
+
+use crate::{
+    joint::{Joint, JointType},
+    structure::JointTree,
+};
+
+// Checks the integrator output of each tree below a Base (e.g. each car) for non-finite states,
+// exploding accelerations and kinetic energy spikes, and recovers the offending tree.
+pub struct HealthMonitorPlugin {
+    pub monitor: HealthMonitor,
+}
+
+impl Plugin for HealthMonitorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<BlowUpEvent>()
+            .insert_resource(self.monitor.clone())
+            .add_systems(
+                PhysicsSchedule,
+                initial_state_system
+                    .in_set(PhysicsSet::Pre)
+                    .run_if(resource_added::<PhysicsState<Joint>>()),
+            )
+            // after every step, so a blown up state is never recorded or snapshotted
+            .add_systems(PostStepSchedule, health_monitor_system);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Recovery {
+    // only report the blow-up
+    None,
+    // restore the tree to its state up to `steps` steps ago, and respawn it
+    // after `max_attempts` rollbacks without `steps` healthy steps in between
+    Rollback { steps: usize, max_attempts: usize },
+    // restore the tree to its initial state
+    Respawn,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlowUpReason {
+    NonFinite,
+    Acceleration(f64),
+    EnergySpike { previous: f64, current: f64 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RecoveryAction {
+    None,
+    RolledBack { steps: usize },
+    Respawned,
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct BlowUpEvent {
+    pub time: f64,
+    pub tree: Entity, // the joint below the Base at the root of the tree
+    pub joint: Entity,
+    pub joint_name: String,
+    pub reason: BlowUpReason,
+    pub action: RecoveryAction,
+}
+
+#[derive(Resource, Clone)]
+pub struct HealthMonitor {
+    pub max_acceleration: f64,    // largest allowed |qdd|
+    pub energy_spike_factor: f64, // largest allowed growth of the kinetic energy in one step
+    pub min_energy: f64,          // energies below this never count as a spike
+    pub recovery: Recovery,
+    history: HashMap<Entity, VecDeque<TreeStates>>, // recent healthy states of each tree, oldest first
+    initial: Option<StateMap<Joint>>,
+    energies: HashMap<Entity, f64>, // kinetic energy of each tree in the last step
+    attempts: HashMap<Entity, usize>,
+}
+
+impl HealthMonitor {
+    pub fn new(
+        max_acceleration: f64,
+        energy_spike_factor: f64,
+        min_energy: f64,
+        recovery: Recovery,
+    ) -> Self {
+        Self {
+            max_acceleration,
+            energy_spike_factor,
+            min_energy,
+            recovery,
+            history: HashMap::new(),
+            initial: None,
+            energies: HashMap::new(),
+            attempts: HashMap::new(),
+        }
+    }
+
+    fn history_length(&self) -> usize {
+        match self.recovery {
+            Recovery::Rollback { steps, .. } => steps.max(1),
+            _ => 0,
+        }
+    }
+}
+
+impl Default for HealthMonitor {
+    fn default() -> Self {
+        Self::new(
+            1e6,
+            10.,
+            100.,
+            Recovery::Rollback {
+                steps: 50,
+                max_attempts: 3,
+            },
+        )
+    }
+}
+
+// the states of the joints of one tree
+type TreeStates = Vec<(Entity, <Joint as Stateful>::State)>;
+
+// the joints of each tree below a Base, root first
+fn joint_trees(world: &World) -> Vec<Vec<Entity>> {
+    world
+        .get_resource::<JointTree>()
+        .map_or(Vec::new(), |tree| {
+            tree.subtrees()
+                .iter()
+                .map(|subtree| subtree.joints.clone())
+                .collect()
+        })
+}
+
+fn kinetic_energy(joint: &Joint) -> f64 {
+    0.5 * (&joint.v * &(joint.i * joint.v))
+}
+
+// joint acceleration, the magnitude of the spatial acceleration for a floating joint
+fn joint_acceleration(joint: &Joint) -> f64 {
+    match joint.joint_type {
+        JointType::Floating => {
+            let acceleration = joint.floating.acceleration;
+            (acceleration.v.norm_squared() + acceleration.w.norm_squared()).sqrt()
+        }
+        _ => joint.qdd,
+    }
+}
+
+// Keep the state the physics state was initialized with, for respawns. The first evaluation of
+// the physics after the initialization is at that state, before the first step.
+fn initial_state_system(
+    physics_state: Res<PhysicsState<Joint>>,
+    mut monitor: ResMut<HealthMonitor>,
+) {
+    monitor.initial = Some(physics_state.states.clone());
+}
+
+// The kinetic energies and accelerations are read from the joints, which hold the last solver
+// stage after a step, so the physics is evaluated again at the accepted state first, as the
+// recorder does.
+pub fn health_monitor_system(world: &mut World) {
+    if !world.contains_resource::<PhysicsState<Joint>>() {
+        return;
+    }
+    world.run_schedule(PhysicsSchedule);
+    let Some(mut monitor) = world.remove_resource::<HealthMonitor>() else {
+        return;
+    };
+
+    let time = world
+        .get_resource::<SimTime>()
+        .map_or(0., |time| time.time());
+    let mut states = world.resource::<PhysicsState<Joint>>().states.clone();
+
+    // find the first problem in each tree
+    let trees = joint_trees(world);
+    let mut joint_query = world.query::<&Joint>();
+    let mut blow_ups = Vec::new();
+    for tree in trees.iter() {
+        let root = tree[0];
+        let mut energy = 0.;
+        let mut max_energy = (root, 0.);
+        let mut problem = None;
+        for entity in tree.iter() {
+            let Ok(joint) = joint_query.get(world, *entity) else {
+                continue;
+            };
+            let joint_energy = kinetic_energy(joint);
+            energy += joint_energy;
+            if joint_energy > max_energy.1 {
+                max_energy = (*entity, joint_energy);
+            }
+            if problem.is_some() {
+                continue;
+            }
+
+            let acceleration = joint_acceleration(joint);
+            let finite = states
+                .get(entity)
+                .is_none_or(|state| state.to_vec().iter().all(|x| x.is_finite()))
+                && acceleration.is_finite()
+                && joint_energy.is_finite();
+            if !finite {
+                problem = Some((*entity, BlowUpReason::NonFinite));
+            } else if acceleration.abs() > monitor.max_acceleration {
+                problem = Some((*entity, BlowUpReason::Acceleration(acceleration)));
+            }
+        }
+
+        if problem.is_none() {
+            if let Some(previous) = monitor.energies.get(&root) {
+                if energy > monitor.energy_spike_factor * previous.max(monitor.min_energy) {
+                    let reason = BlowUpReason::EnergySpike {
+                        previous: *previous,
+                        current: energy,
+                    };
+                    problem = Some((max_energy.0, reason));
+                }
+            }
+        }
+
+        match problem {
+            Some((joint, reason)) => {
+                let joint_name = joint_query
+                    .get(world, joint)
+                    .map_or(String::new(), |joint| joint.get_name());
+                blow_ups.push((tree, joint, joint_name, reason));
+            }
+            None => {
+                monitor.energies.insert(root, energy);
+            }
+        }
+    }
+
+    // keep the recent healthy states of each tree for rollbacks
+    let length = monitor.history_length();
+    monitor
+        .history
+        .retain(|root, _| trees.iter().any(|tree| tree[0] == *root));
+    if length > 0 {
+        for tree in trees.iter() {
+            let root = tree[0];
+            if blow_ups.iter().any(|(blown_up, ..)| blown_up[0] == root) {
+                continue;
+            }
+            let history = monitor.history.entry(root).or_default();
+            if history.len() == length {
+                history.pop_front();
+                // `steps` healthy steps since the last recovery of this tree
+                monitor.attempts.remove(&root);
+            }
+            history.push_back(tree_states(&states, tree));
+        }
+    }
+
+    if blow_ups.is_empty() {
+        world.insert_resource(monitor);
+        return;
+    }
+
+    for (tree, joint, joint_name, reason) in blow_ups {
+        let root = tree[0];
+        let action = recover_tree(&mut monitor, &mut states, tree, root);
+        monitor.energies.remove(&root);
+        warn!(
+            "blow-up at t = {:.3} in joint {}: {:?}, recovery: {:?}",
+            time, joint_name, reason, action
+        );
+        world.send_event(BlowUpEvent {
+            time,
+            tree: root,
+            joint,
+            joint_name,
+            reason,
+            action,
+        });
+    }
+
+    if monitor.recovery != Recovery::None {
+        world.resource_mut::<PhysicsState<Joint>>().states = states;
+        reset_non_finite_states(world);
+        if let Some(mut implicit) = world.get_resource_mut::<ImplicitStep>() {
+            implicit.reset_jacobian();
+        }
+    }
+    world.insert_resource(monitor);
+}
+
+fn tree_states(states: &StateMap<Joint>, tree: &[Entity]) -> TreeStates {
+    tree.iter()
+        .filter_map(|entity| Some((*entity, states.get(entity)?.clone())))
+        .collect()
+}
+
+// Restores the tree, and keeps only the state it was restored to in its history. Another
+// blow-up before `steps` healthy steps rolls back to the same state, up to max_attempts times.
+fn recover_tree(
+    monitor: &mut HealthMonitor,
+    states: &mut StateMap<Joint>,
+    tree: &[Entity],
+    root: Entity,
+) -> RecoveryAction {
+    let history = monitor.history.entry(root).or_default();
+    let (target, action) = match monitor.recovery {
+        Recovery::None => return RecoveryAction::None,
+        Recovery::Rollback { max_attempts, .. } => {
+            let attempts = monitor.attempts.entry(root).or_insert(0);
+            *attempts += 1;
+            match history.front() {
+                Some(oldest) if *attempts <= max_attempts => {
+                    let steps = history.len();
+                    (Some(oldest.clone()), RecoveryAction::RolledBack { steps })
+                }
+                _ => (None, RecoveryAction::Respawned),
+            }
+        }
+        Recovery::Respawn => (None, RecoveryAction::Respawned),
+    };
+
+    let target = match target {
+        Some(target) => Some(target),
+        None => {
+            monitor.attempts.remove(&root);
+            monitor
+                .initial
+                .as_ref()
+                .map(|initial| tree_states(initial, tree))
+        }
+    };
+    history.clear();
+    if let Some(target) = target {
+        for (entity, state) in target.iter() {
+            states.insert(*entity, state.clone());
+        }
+        history.push_back(target);
+    }
+    action
+}
+
+// other integrated states (e.g. filters) may have picked up the blow-up
+fn reset_non_finite_states(world: &mut World) {
+    let Some(mut state) = IntegratedState::<Joint>::read(world) else {
+        return;
+    };
+    let mut components = state.to_components();
+    if components.iter().all(|component| component.is_finite()) {
+        return;
+    }
+    for component in components.iter_mut() {
+        if !component.is_finite() {
+            *component = 0.;
+        }
+    }
+    state.set_components(&components);
+    state.write_states(world);
+}
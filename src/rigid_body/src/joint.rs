@@ -1,13 +1,14 @@
 use bevy::prelude::*;
-use bevy_integrator::Stateful;
+use bevy_integrator::{StateNorm, StateVector, Stateful};
+use nalgebra::{Quaternion, UnitQuaternion};
 use std::ops::{Add, Mul};
 
 //use car::build::CarList;
 
 use crate::mesh::Mesh as RBDA_Mesh;
-use crate::sva::{Force, Inertia, InertiaAB, Motion, Xform};
+use crate::sva::{Force, Inertia, InertiaAB, Matrix, Motion, Vector, Xform};
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, Copy)]
 pub enum JointType {
     Base,
     #[default]
@@ -17,6 +18,74 @@ pub enum JointType {
     Px,
     Py,
     Pz,
+    Revolute(Vector),  // about a unit axis
+    Prismatic(Vector), // along a unit axis
+    // screw about a unit axis, the pitch is the translation per radian
+    Helical {
+        axis: Vector,
+        pitch: f64,
+    },
+    Floating,
+}
+
+// State of a floating (6-DoF) joint, e.g. a free body. The motion subspace is the identity,
+// so the joint velocity is the spatial velocity of the body relative to its parent, in body
+// coordinates. The orientation is a quaternion, so there is no gimbal lock.
+#[derive(Debug, Clone, Copy)]
+pub struct Floating {
+    pub position: Vector,                 // body origin in the joint frame
+    pub orientation: UnitQuaternion<f64>, // rotation from the body to the joint frame
+    pub velocity: Motion,
+    pub acceleration: Motion,
+    pub tau: Force, // joint force, in body coordinates
+}
+
+impl Default for Floating {
+    fn default() -> Self {
+        Self {
+            position: Vector::zeros(),
+            orientation: UnitQuaternion::identity(),
+            velocity: Motion::zero(),
+            acceleration: Motion::zero(),
+            tau: Force::zero(),
+        }
+    }
+}
+
+impl Floating {
+    // euler angles are roll, pitch and yaw, as for a chain of rz, ry and rx joints
+    pub fn set_pose(&mut self, position: [f64; 3], euler_angles: [f64; 3]) {
+        let [roll, pitch, yaw] = euler_angles;
+        self.position = Vector::from(position);
+        self.orientation = UnitQuaternion::from_euler_angles(roll, pitch, yaw);
+    }
+
+    pub fn euler_angles(&self) -> [f64; 3] {
+        let (roll, pitch, yaw) = self.orientation.euler_angles();
+        [roll, pitch, yaw]
+    }
+
+    pub fn xj(&self) -> Xform {
+        let rotation = self.orientation.to_rotation_matrix().into_inner();
+        Xform::new(self.position, rotation.transpose())
+    }
+
+    fn state(&self) -> FloatingState {
+        FloatingState {
+            position: self.position,
+            orientation: *self.orientation.quaternion(),
+            velocity: self.velocity,
+        }
+    }
+
+    fn dstate(&self) -> FloatingState {
+        let angular = Quaternion::from_imag(self.velocity.w);
+        FloatingState {
+            position: self.orientation * self.velocity.v,
+            orientation: self.orientation.quaternion() * angular * 0.5,
+            velocity: self.acceleration,
+        }
+    }
 }
 
 #[derive(Component, Default, Debug)]
@@ -37,6 +106,7 @@ pub struct Joint {
     pub q: f64,
     pub qd: f64,
     pub qdd: f64,
+    pub floating: Floating, // instead of q, qd, qdd and tau for JointType::Floating
 
     // common parameters
     pub xl: Xform,
@@ -140,6 +210,80 @@ impl Joint {
             ..Default::default()
         }
     }
+    // revolute, prismatic and helical joints about any axis, e.g. an inclined kingpin
+    pub fn revolute(name: String, inertia: Inertia, xt: Xform, axis: Vector) -> Self {
+        let axis = axis.normalize();
+        let s = Motion {
+            v: Vector::zeros(),
+            w: axis,
+        };
+
+        Self {
+            name,
+            i: inertia,
+            xt,
+            s,
+            joint_type: JointType::Revolute(axis),
+            ..Default::default()
+        }
+    }
+    pub fn prismatic(name: String, inertia: Inertia, xt: Xform, axis: Vector) -> Self {
+        let axis = axis.normalize();
+        let s = Motion {
+            v: axis,
+            w: Vector::zeros(),
+        };
+
+        Self {
+            name,
+            i: inertia,
+            xt,
+            s,
+            joint_type: JointType::Prismatic(axis),
+            ..Default::default()
+        }
+    }
+    pub fn helical(name: String, inertia: Inertia, xt: Xform, axis: Vector, pitch: f64) -> Self {
+        let axis = axis.normalize();
+        let s = Motion {
+            v: pitch * axis,
+            w: axis,
+        };
+
+        Self {
+            name,
+            i: inertia,
+            xt,
+            s,
+            joint_type: JointType::Helical { axis, pitch },
+            ..Default::default()
+        }
+    }
+    pub fn floating(name: String, inertia: Inertia, xt: Xform) -> Self {
+        Self {
+            name,
+            i: inertia,
+            xt,
+            joint_type: JointType::Floating,
+            ..Default::default()
+        }
+    }
+
+    // Columns of the motion subspace, in the order of the joint velocities: qd, or the linear
+    // then the angular velocity of a floating joint, as in its state. Empty for a Base.
+    pub fn motion_subspace(&self) -> Vec<Motion> {
+        match self.joint_type {
+            JointType::Base => Vec::new(),
+            JointType::Floating => (0..6)
+                .map(|index| {
+                    let mut unit = [0.; 6];
+                    unit[index] = 1.;
+                    Motion::new([unit[0], unit[1], unit[2]], [unit[3], unit[4], unit[5]])
+                })
+                .collect(),
+            _ => vec![self.s],
+        }
+    }
 }
 
 pub fn bevy_joint_positions(mut joint_transform_query: Query<(&mut Joint, &mut Transform)>) {
@@ -172,36 +316,209 @@ impl Into<f64> for JointState {
     }
 }
 
+impl StateNorm for JointState {
+    fn norm(&self) -> f64 {
+        let floating = self.floating.as_deref().map_or(0., |floating| {
+            let FloatingState {
+                position,
+                orientation,
+                velocity,
+            } = floating;
+            position
+                .amax()
+                .max(orientation.coords.amax())
+                .max(velocity.v.amax())
+                .max(velocity.w.amax())
+        });
+        self.q.abs().max(self.qd.abs()).max(floating)
+    }
+}
+
+const FLOATING_COMPONENTS: [&str; 13] = [
+    "x", "y", "z", "qw", "qx", "qy", "qz", "vx", "vy", "vz", "wx", "wy", "wz",
+];
+
+// the orientation is a rotation vector, perturbed about the body axes in linearizations
+const FLOATING_TANGENT: [&str; 12] = [
+    "x", "y", "z", "rx", "ry", "rz", "vx", "vy", "vz", "wx", "wy", "wz",
+];
+
+impl StateVector for JointState {
+    fn to_vec(&self) -> Vec<f64> {
+        match self.floating.as_deref() {
+            Some(floating) => {
+                let FloatingState {
+                    position: p,
+                    orientation: o,
+                    velocity: v,
+                } = floating;
+                vec![
+                    p.x, p.y, p.z, o.w, o.i, o.j, o.k, v.v.x, v.v.y, v.v.z, v.w.x, v.w.y, v.w.z,
+                ]
+            }
+            None => vec![self.q, self.qd],
+        }
+    }
+
+    fn from_slice(components: &[f64]) -> Self {
+        match components {
+            [x, y, z, qw, qx, qy, qz, vx, vy, vz, wx, wy, wz] => {
+                Self::from_floating(FloatingState {
+                    position: Vector::new(*x, *y, *z),
+                    orientation: Quaternion::new(*qw, *qx, *qy, *qz),
+                    velocity: Motion::new([*vx, *vy, *vz], [*wx, *wy, *wz]),
+                })
+            }
+            _ => Self::new(components[0], components[1]),
+        }
+    }
+
+    fn component_names(&self) -> Vec<String> {
+        match self.floating {
+            Some(_) => FLOATING_COMPONENTS.map(String::from).to_vec(),
+            None => vec!["q".to_string(), "qd".to_string()],
+        }
+    }
+
+    fn normalize(&mut self) {
+        if let Some(floating) = &mut self.floating {
+            floating.orientation = floating.orientation.normalize();
+        }
+    }
+
+    fn axpy(&mut self, a: f64, x: &Self) {
+        self.q += a * x.q;
+        self.qd += a * x.qd;
+        match (self.floating.as_deref_mut(), x.floating.as_deref()) {
+            (Some(floating), Some(x)) => *floating = *floating + *x * a,
+            (None, Some(x)) => self.floating = Some(Box::new(*x * a)),
+            _ => {}
+        }
+    }
+
+    fn tangent(&self) -> Vec<f64> {
+        match self.floating.as_deref() {
+            Some(floating) => {
+                let p = floating.position;
+                let r = UnitQuaternion::from_quaternion(floating.orientation).scaled_axis();
+                let Motion { v, w } = floating.velocity;
+                vec![p.x, p.y, p.z, r.x, r.y, r.z, v.x, v.y, v.z, w.x, w.y, w.z]
+            }
+            None => self.to_vec(),
+        }
+    }
+
+    fn tangent_names(&self) -> Vec<String> {
+        match self.floating {
+            Some(_) => FLOATING_TANGENT.map(String::from).to_vec(),
+            None => self.component_names(),
+        }
+    }
+
+    // a floating joint is rotated by the rotation vector in body coordinates
+    fn retract(&self, delta: &[f64]) -> Vec<f64> {
+        let Some(floating) = self.floating.as_deref() else {
+            return vec![self.q + delta[0], self.qd + delta[1]];
+        };
+        let orientation = UnitQuaternion::from_quaternion(floating.orientation)
+            * UnitQuaternion::from_scaled_axis(Vector::new(delta[3], delta[4], delta[5]));
+        JointState::from_floating(FloatingState {
+            position: floating.position + Vector::new(delta[0], delta[1], delta[2]),
+            orientation: orientation.into_inner(),
+            velocity: floating.velocity
+                + Motion::new(
+                    [delta[6], delta[7], delta[8]],
+                    [delta[9], delta[10], delta[11]],
+                ),
+        })
+        .to_vec()
+    }
+
+    fn tangent_derivative(&self, components: &[f64], derivative: &[f64]) -> Vec<f64> {
+        let (Some(reference), Some(state), Some(dstate)) = (
+            self.floating.as_deref(),
+            JointState::from_slice(components).floating,
+            JointState::from_slice(derivative).floating,
+        ) else {
+            return derivative.to_vec();
+        };
+
+        // angular velocity in body coordinates, as in Joint::set_dstate
+        let w = (state.orientation.conjugate() * dstate.orientation * 2.).imag();
+        // rate of the rotation vector from the reference orientation
+        let rotation = UnitQuaternion::from_quaternion(reference.orientation).inverse()
+            * UnitQuaternion::from_quaternion(state.orientation);
+        let r = rotation.scaled_axis();
+        let rd = so3_right_jacobian_inverse(r) * w;
+
+        let p = dstate.position;
+        let Motion { v, w } = dstate.velocity;
+        vec![
+            p.x, p.y, p.z, rd.x, rd.y, rd.z, v.x, v.y, v.z, w.x, w.y, w.z,
+        ]
+    }
+}
+
+// Maps the body angular velocity to the rate of the rotation vector r, for a rotation
+// q0 * exp(r) about a fixed q0.
+fn so3_right_jacobian_inverse(r: Vector) -> Matrix {
+    let angle = r.norm();
+    let r_cross = r.cross_matrix();
+    let factor = if angle < 1e-4 {
+        1. / 12.
+    } else {
+        1. / angle.powi(2) - (1. + angle.cos()) / (2. * angle * angle.sin())
+    };
+    Matrix::identity() + 0.5 * r_cross + factor * r_cross * r_cross
+}
+
 impl Stateful for Joint {
     type State = JointState;
     fn get_state(&self) -> Self::State {
-        Self::State {
-            q: self.q,
-            qd: self.qd,
+        match self.joint_type {
+            JointType::Floating => JointState::from_floating(self.floating.state()),
+            _ => JointState::new(self.q, self.qd),
         }
     }
 
     fn set_state(&mut self, state: &Self::State) {
         self.q = state.q;
         self.qd = state.qd;
+        if let Some(floating) = &state.floating {
+            self.floating.position = floating.position;
+            self.floating.orientation = UnitQuaternion::from_quaternion(floating.orientation);
+            self.floating.velocity = floating.velocity;
+        }
     }
 
     fn get_dstate(&self) -> Self::State {
-        Self::State {
-            q: self.qd,
-            qd: self.qdd,
+        match self.joint_type {
+            JointType::Floating => JointState::from_floating(self.floating.dstate()),
+            _ => JointState::new(self.qd, self.qdd),
         }
     }
 
     fn set_dstate(&mut self, dstate: Self::State) {
         self.qd = dstate.q;
         self.qdd = dstate.qd;
+        if let Some(floating) = &dstate.floating {
+            // invert the kinematics of dstate()
+            let orientation = self.floating.orientation;
+            let angular = orientation.quaternion().conjugate() * floating.orientation * 2.;
+            self.floating.velocity = Motion {
+                v: orientation.inverse() * floating.position,
+                w: angular.imag(),
+            };
+            self.floating.acceleration = floating.velocity;
+        }
     }
 
     fn reset(&mut self) {
         self.qdd = 0.;
         self.f_ext = Force::zero();
         self.tau = 0.;
+        self.floating.acceleration = Motion::zero();
+        self.floating.tau = Force::zero();
     }
 
     fn get_name(&self) -> String {
@@ -209,30 +526,51 @@ impl Stateful for Joint {
     }
 }
 
+// The floating state is boxed, so the states of the 1-DoF joints stay three words long and
+// cheap to copy in the solver arithmetic.
 #[derive(Clone)]
 pub struct JointState {
     pub q: f64,
     pub qd: f64,
+    pub floating: Option<Box<FloatingState>>, // only for floating joints
 }
 
 impl JointState {
     pub fn new(q: f64, qd: f64) -> Self {
-        Self { q, qd }
+        Self {
+            q,
+            qd,
+            floating: None,
+        }
     }
     pub fn zero() -> Self {
         Self::new(0., 0.)
     }
+    pub fn from_floating(floating: FloatingState) -> Self {
+        Self {
+            floating: Some(Box::new(floating)),
+            ..Self::zero()
+        }
+    }
     pub fn from_joint(joint: &Joint) -> Self {
-        Self::new(joint.q, joint.qd)
+        joint.get_state()
     }
 }
 
 impl Add for JointState {
     type Output = JointState;
     fn add(self, other: JointState) -> JointState {
+        let floating = match (self.floating, other.floating) {
+            (Some(mut a), Some(b)) => {
+                *a = *a + *b;
+                Some(a)
+            }
+            (a, b) => a.or(b),
+        };
         JointState {
             q: self.q + other.q,
             qd: self.qd + other.qd,
+            floating,
         }
     }
 }
@@ -243,6 +581,41 @@ impl Mul<f64> for JointState {
         JointState {
             q: self.q * other,
             qd: self.qd * other,
+            floating: self.floating.map(|mut floating| {
+                *floating = *floating * other;
+                floating
+            }),
+        }
+    }
+}
+
+// Position, orientation and velocity of a floating joint, or their derivatives. The
+// orientation isn't normalized here, so the solvers can add and scale it like a vector.
+#[derive(Clone, Copy, Debug)]
+pub struct FloatingState {
+    pub position: Vector,
+    pub orientation: Quaternion<f64>,
+    pub velocity: Motion,
+}
+
+impl Add for FloatingState {
+    type Output = FloatingState;
+    fn add(self, other: FloatingState) -> FloatingState {
+        FloatingState {
+            position: self.position + other.position,
+            orientation: self.orientation + other.orientation,
+            velocity: self.velocity + other.velocity,
+        }
+    }
+}
+
+impl Mul<f64> for FloatingState {
+    type Output = FloatingState;
+    fn mul(self, other: f64) -> FloatingState {
+        FloatingState {
+            position: self.position * other,
+            orientation: self.orientation * other,
+            velocity: other * self.velocity,
         }
     }
 }
@@ -0,0 +1,125 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    joint::Joint,
+    structure::JointTree,
+    sva::{Matrix, Motion, Vector, Xform},
+};
+
+// Pose, velocity and acceleration of a body in world (Base) coordinates, from the joint state of
+// the last evaluation of the dynamics. The acceleration of the Base (e.g. gravity, which is
+// modeled as an upward acceleration of the Base) is removed, so the body accelerations are
+// relative to the world.
+#[derive(Clone, Copy, Debug)]
+pub struct BodyKinematics {
+    pub x: Xform,             // from world to body coordinates, as Joint::x
+    pub velocity: Motion,     // spatial velocity in world coordinates
+    pub acceleration: Motion, // spatial acceleration in world coordinates
+}
+
+// Position, velocity and acceleration of a body-fixed point, all in world coordinates
+#[derive(Clone, Copy, Debug)]
+pub struct PointKinematics {
+    pub position: Vector,
+    pub velocity: Vector,
+    pub acceleration: Vector,
+}
+
+impl BodyKinematics {
+    // base is the Base at the root of the joint's tree, None for a Base with no acceleration
+    pub fn new(joint: &Joint, base: Option<&Joint>) -> Self {
+        let x_inverse = joint.x.inverse();
+        let base_acceleration = base.map_or(Motion::zero(), |base| base.x.inverse() * base.a);
+        Self {
+            x: joint.x,
+            velocity: x_inverse * joint.v,
+            acceleration: x_inverse * joint.a - base_acceleration,
+        }
+    }
+
+    // body origin in world coordinates
+    pub fn position(&self) -> Vector {
+        self.x.position
+    }
+
+    // rotation from body to world coordinates
+    pub fn rotation(&self) -> Matrix {
+        self.x.rotation.transpose()
+    }
+
+    pub fn angular_velocity(&self) -> Vector {
+        self.velocity.w
+    }
+
+    pub fn angular_acceleration(&self) -> Vector {
+        self.acceleration.w
+    }
+
+    // a direction fixed in the body, in world coordinates
+    pub fn direction(&self, direction: Vector) -> Vector {
+        self.rotation() * direction
+    }
+
+    // a point given in body coordinates
+    pub fn point(&self, point: Vector) -> PointKinematics {
+        let position = self.x.inverse().transform_point(point);
+        let velocity = self.velocity.velocity_point(position).vel;
+        // the spatial acceleration is of the body point at the world origin
+        let acceleration =
+            self.acceleration.velocity_point(position).vel + self.velocity.w.cross(&velocity);
+        PointKinematics {
+            position,
+            velocity,
+            acceleration,
+        }
+    }
+}
+
+// Read-only access to the world kinematics of the bodies, for sensors, cameras, HUDs and
+// controllers. Systems that write to the joints can use BodyKinematics::new directly.
+#[derive(SystemParam)]
+pub struct Kinematics<'w, 's> {
+    tree: Res<'w, JointTree>,
+    joints: Query<'w, 's, (Entity, &'static Joint)>,
+}
+
+impl Kinematics<'_, '_> {
+    pub fn body(&self, entity: Entity) -> Option<BodyKinematics> {
+        let (_, joint) = self.joints.get(entity).ok()?;
+        let base = self
+            .joints
+            .get(root_base(&self.tree, entity))
+            .ok()
+            .map(|(_, base)| base);
+        Some(BodyKinematics::new(joint, base))
+    }
+
+    // a point given in the body coordinates of the joint
+    pub fn point(&self, entity: Entity, point: Vector) -> Option<PointKinematics> {
+        Some(self.body(entity)?.point(point))
+    }
+
+    // first joint with this name
+    pub fn find(&self, name: &str) -> Option<Entity> {
+        self.joints
+            .iter()
+            .find(|(_, joint)| joint.name == name)
+            .map(|(entity, _)| entity)
+    }
+}
+
+// the same for a World, e.g. after bevy_integrator::step in a headless simulation
+pub fn body_kinematics(world: &mut World, entity: Entity) -> Option<BodyKinematics> {
+    let base = root_base(world.get_resource::<JointTree>()?, entity);
+    let joint = world.get::<Joint>(entity)?;
+    Some(BodyKinematics::new(joint, world.get::<Joint>(base)))
+}
+
+// the Base at the root of the tree of a joint
+fn root_base(tree: &JointTree, entity: Entity) -> Entity {
+    let mut root = entity;
+    while let Some(parent) = tree.parent(root) {
+        root = parent;
+    }
+    root
+}
@@ -1,8 +1,16 @@
 pub mod algorithms;
+pub mod collision;
+pub mod constraints;
 pub mod definitions;
+pub mod dynamics;
+pub mod forces;
+pub mod health;
 pub mod joint;
+pub mod kinematics;
+pub mod limits;
 pub mod mesh;
 pub mod plugin;
 pub mod rendering;
 pub mod structure;
 pub mod sva;
+pub mod urdf;
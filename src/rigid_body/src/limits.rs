@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+use bevy_integrator::{PhysicsSchedule, PhysicsSet, PhysicsState, PostStepSchedule, SimTime};
+
+use crate::{
+    health::health_monitor_system,
+    joint::{Joint, JointType},
+};
+
+// End stops on the joint coordinate q of 1-DoF joints, e.g. suspension bump stops and steering
+// locks.
+pub struct JointLimitPlugin;
+
+impl Plugin for JointLimitPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<JointLimitEvent>()
+            .add_systems(
+                PhysicsSchedule,
+                joint_limit_system.in_set(PhysicsSet::Evaluate),
+            )
+            .add_systems(
+                PostStepSchedule,
+                joint_limit_event_system.after(health_monitor_system),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimitSide {
+    Lower,
+    Upper,
+}
+
+// The stop is a spring-damper acting beyond the bounds. While the joint moves back out of the
+// stop the spring is scaled by restitution^2, so the stop returns that fraction of the energy.
+// Floating and base joints have no joint coordinate q, so a limit on them is ignored.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct JointLimit {
+    pub lower: f64,
+    pub upper: f64,
+    pub stiffness: f64,
+    pub damping: f64,
+    pub restitution: f64,       // 1 for an elastic stop, 0 for a plastic one
+    engaged: Option<LimitSide>, // side the joint was on after the last step
+}
+
+impl JointLimit {
+    pub fn new(lower: f64, upper: f64, stiffness: f64, damping: f64, restitution: f64) -> Self {
+        Self {
+            lower,
+            upper,
+            stiffness,
+            damping,
+            restitution: restitution.clamp(0., 1.),
+            engaged: None,
+        }
+    }
+
+    pub fn side(&self, q: f64) -> Option<LimitSide> {
+        if q < self.lower {
+            Some(LimitSide::Lower)
+        } else if q > self.upper {
+            Some(LimitSide::Upper)
+        } else {
+            None
+        }
+    }
+
+    // generalized force of the stop, zero within the bounds
+    pub fn force(&self, q: f64, qd: f64) -> f64 {
+        let penetration = match self.side(q) {
+            Some(LimitSide::Lower) => q - self.lower,
+            Some(LimitSide::Upper) => q - self.upper,
+            None => return 0.,
+        };
+        let leaving = penetration * qd < 0.;
+        let stiffness = if leaving {
+            self.restitution.powi(2) * self.stiffness
+        } else {
+            self.stiffness
+        };
+        let force = -stiffness * penetration - self.damping * qd;
+
+        // a stop can only push the joint back
+        if penetration > 0. {
+            force.min(0.)
+        } else {
+            force.max(0.)
+        }
+    }
+}
+
+// Sent when a joint runs into one of its limits
+#[derive(Event, Clone, Debug)]
+pub struct JointLimitEvent {
+    pub time: f64,
+    pub joint: Entity,
+    pub joint_name: String,
+    pub side: LimitSide,
+    pub qd: f64, // joint velocity at the end of the step that hit the limit
+}
+
+// the joints with a single coordinate q
+fn is_limited(joint: &Joint) -> bool {
+    !matches!(joint.joint_type, JointType::Floating | JointType::Base)
+}
+
+pub fn joint_limit_system(mut joints: Query<(&mut Joint, &JointLimit)>) {
+    for (mut joint, limit) in joints.iter_mut() {
+        if !is_limited(&joint) {
+            continue;
+        }
+        joint.tau += limit.force(joint.q, joint.qd);
+    }
+}
+
+// Check the integrated state after each step
+fn joint_limit_event_system(
+    time: Res<SimTime>,
+    physics_state: Option<Res<PhysicsState<Joint>>>,
+    mut joints: Query<(Entity, &Joint, &mut JointLimit)>,
+    mut events: EventWriter<JointLimitEvent>,
+) {
+    let Some(physics_state) = physics_state else {
+        return;
+    };
+    for (entity, joint, mut limit) in joints.iter_mut() {
+        if !is_limited(joint) {
+            continue;
+        }
+        let Some(state) = physics_state.states.get(&entity) else {
+            continue;
+        };
+        let side = limit.side(state.q);
+        if let Some(side) = side.filter(|side| limit.engaged != Some(*side)) {
+            events.send(JointLimitEvent {
+                time: time.time(),
+                joint: entity,
+                joint_name: joint.name.clone(),
+                side,
+                qd: state.qd,
+            });
+        }
+        limit.engaged = side;
+    }
+}
@@ -3,11 +3,11 @@
 use crate::{
     joint::{bevy_joint_positions, Joint},
     rendering::startup_rendering,
-    structure::{apply_external_forces, loop_1, loop_23},
+    structure::{external_forces_loop_23, loop_1, update_joint_tree, JointTree},
 };
 use bevy::{app::AppExit, prelude::*};
 use bevy_integrator::{
-    initialize_state, integrator_schedule, ExitEvent, PhysicsSchedule, PhysicsScheduleExt, SimTime, Solver
+    initialize_state, integrator_schedule, ExitEvent, GameState, PhysicsSchedule, PhysicsScheduleExt, PhysicsSet, SimTime, Solver
 };
 use bevy_obj::ObjPlugin;
 
@@ -32,10 +32,10 @@ pub struct RigidBodyPlugin {
 
 impl RigidBodyPlugin {
     pub fn setup_physics_simulation(&self, app: &mut App) {
-        let schedule = create_physics_schedule();
-        app.add_schedule(schedule)
-            .insert_resource(self.time.clone())
+        add_physics_schedule(app);
+        app.insert_resource(self.time.clone())
             .insert_resource(self.solver)
+            .init_resource::<JointTree>()
             .insert_resource(Time::<Fixed>::from_seconds(self.time.dt as f64))
             .add_systems(FixedUpdate, integrator_schedule::<Joint>.run_if(in_state(CarState::Finished)));
     }
@@ -77,12 +77,45 @@ impl Plugin for RigidBodyPlugin {
     }
 }
 
-fn create_physics_schedule() -> Schedule {
-    let mut physics_schedule = Schedule::new(PhysicsSchedule);
-    physics_schedule
-        .add_physics_systems::<Joint, _, _>((loop_1,), (apply_external_forces, loop_23).chain());
+// Physics without rendering, windows or input, e.g. for CI, servers and tests.
+// The simulation is advanced explicitly with bevy_integrator::step, which uses SimTime::dt
+// instead of the wall-clock driven fixed timestep loop. The integrator isn't added to
+// FixedUpdate, so app.update() doesn't advance the physics.
+#[derive(Clone)]
+pub struct HeadlessRigidBodyPlugin {
+    pub time: SimTime,
+    pub simulation_setup: Vec<fn(&mut App)>,
+    pub solver: Solver,
+}
+
+impl Plugin for HeadlessRigidBodyPlugin {
+    fn build(&self, app: &mut App) {
+        add_physics_schedule(app);
+        app.insert_resource(self.time.clone())
+            .insert_resource(self.solver)
+            .init_resource::<JointTree>();
+
+        // the physics sets only run in game
+        if !app.world.contains_resource::<State<GameState>>() {
+            app.insert_resource(State::new(GameState::InGame));
+        }
+
+        for setup in self.simulation_setup.iter() {
+            setup(app);
+        }
+    }
+}
 
-    physics_schedule
+// Adds the articulated-body passes to the physics schedule. This keeps the systems that other
+// plugins (limits, constraints, force elements, collisions, ...) already added to it, so those
+// plugins can be added before or after this one.
+fn add_physics_schedule(app: &mut App) {
+    app.edit_schedule(PhysicsSchedule, |physics_schedule| {
+        physics_schedule
+            .add_physics_systems::<Joint, _, _>((loop_1,), (external_forces_loop_23,))
+            // flatten the joint tree before the passes if it changed
+            .add_systems(update_joint_tree.in_set(PhysicsSet::Pre));
+    });
 }
 
 fn time_exit_system(time: Res<SimTime>, mut exit: EventWriter<ExitEvent>) {
@@ -8,7 +8,8 @@ pub fn startup_rendering(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut asset_server: Res<AssetServer>,
-    mut joint_mesh_query: Query<(Entity, &MeshDef), (With<Joint>, With<MeshDef>)>,
+    // on joints, or on their children, e.g. the links merged into a body by Urdf::spawn
+    mut joint_mesh_query: Query<(Entity, &MeshDef)>,
     mut joint_no_mesh_query: Query<Entity, (With<Joint>, Without<MeshDef>)>,
     mut car_state: ResMut<NextState<CarState>>,
 ) {
@@ -1,131 +1,384 @@
 use crate::joint::{Base, Joint};
-use bevy::prelude::*;
+use bevy::{
+    prelude::*,
+    tasks::{ComputeTaskPool, TaskPool},
+};
 
 use crate::algorithms::{apply_external_update, loop_1_update, loop_2_update, loop_3_update};
 
-pub fn loop_1(
-    base_query: Query<Entity, With<Base>>,
-    joint_children_query: Query<&Children, With<Joint>>,
-    mut joint_query: Query<&mut Joint>,
-) {
-    base_loop(
-        &base_query,
-        &joint_children_query,
-        &mut joint_query,
-        Some(loop_1_update),
-        None,
-    );
+// The joints below one child of a Base (e.g. one car), in depth-first order
+#[derive(Clone, Debug)]
+pub struct JointSubtree {
+    pub base: usize,                 // index into JointTree::bases
+    pub joints: Vec<Entity>,         // parents come before their children
+    pub parents: Vec<Option<usize>>, // index of the parent in joints, None for the root
+    start: usize,                    // index of the root in the joints of all subtrees
 }
 
-pub fn apply_external_forces(
-    base_query: Query<Entity, With<Base>>,
-    joint_children_query: Query<&Children, With<Joint>>,
-    mut joint_query: Query<&mut Joint>,
-) {
-    base_loop(
-        &base_query,
-        &joint_children_query,
-        &mut joint_query,
-        Some(apply_external_update),
-        None,
-    );
+#[derive(Clone, Copy, Debug)]
+enum Slot {
+    Base(usize),
+    Joint(usize, usize), // subtree, index in the subtree
 }
 
-pub fn loop_23(
-    base_query: Query<Entity, With<Base>>,
-    joint_children_query: Query<&Children, With<Joint>>,
-    mut joint_query: Query<&mut Joint>,
-) {
-    base_loop(
-        &base_query,
-        &joint_children_query,
-        &mut joint_query,
-        None,
-        Some(loop_2_update),
-    );
-
-    base_loop(
-        &base_query,
-        &joint_children_query,
-        &mut joint_query,
-        Some(loop_3_update),
-        None,
-    );
-}
-
-pub fn base_loop(
-    base_query: &Query<Entity, With<Base>>,
-    joint_children_query: &Query<&Children, With<Joint>>,
-    mut joint_query: &mut Query<&mut Joint>,
-    fn_out: Option<fn(&mut Joint, &Joint)>,
-    fn_in: Option<fn(&mut Joint, Option<&mut Joint>)>,
-) {
-    for base_entity in base_query.iter() {
-        if let Ok(children) = joint_children_query.get(base_entity) {
+// The subtrees are evaluated in chunks of at least this many joints, one task per chunk. A task
+// costs a few microseconds to spawn, so a car (11 joints) isn't worth one of its own, but a few
+// cars together are.
+const PARALLEL_MIN_JOINTS: usize = 24;
+
+// The joint tree flattened by update_joint_tree, rebuilt only when the hierarchy changes.
+// The Featherstone passes run as linear loops over it.
+#[derive(Resource, Clone, Debug)]
+pub struct JointTree {
+    bases: Vec<Entity>,
+    subtrees: Vec<JointSubtree>,
+    slots: Vec<Option<(Entity, Slot)>>, // indexed by Entity::index
+    len: usize,                         // joints in all subtrees
+    parallel_min_joints: usize,
+}
+
+impl Default for JointTree {
+    fn default() -> Self {
+        Self {
+            bases: Vec::new(),
+            subtrees: Vec::new(),
+            slots: Vec::new(),
+            len: 0,
+            parallel_min_joints: PARALLEL_MIN_JOINTS,
+        }
+    }
+}
+
+impl JointTree {
+    // the fewest joints in a chunk of subtrees evaluated on its own task, 0 for a chunk per thread
+    pub fn set_parallel_min_joints(&mut self, joints: usize) {
+        self.parallel_min_joints = joints;
+    }
+
+    pub fn bases(&self) -> &[Entity] {
+        &self.bases
+    }
+
+    pub fn subtrees(&self) -> &[JointSubtree] {
+        &self.subtrees
+    }
+
+    // number of joints, not counting the bases
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subtrees.is_empty()
+    }
+
+    // parent of a joint, which is a Base for the roots of the subtrees
+    pub fn parent(&self, entity: Entity) -> Option<Entity> {
+        match self.slot(entity)? {
+            Slot::Base(_) => None,
+            Slot::Joint(subtree, index) => {
+                let subtree = &self.subtrees[subtree];
+                Some(match subtree.parents[index] {
+                    Some(parent) => subtree.joints[parent],
+                    None => self.bases[subtree.base],
+                })
+            }
+        }
+    }
+
+    // all joints in depth-first order with their parents
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, Entity)> + '_ {
+        self.subtrees.iter().flat_map(move |subtree| {
+            subtree
+                .joints
+                .iter()
+                .zip(subtree.parents.iter())
+                .map(move |(joint, parent)| {
+                    let parent = match parent {
+                        Some(parent) => subtree.joints[*parent],
+                        None => self.bases[subtree.base],
+                    };
+                    (*joint, parent)
+                })
+        })
+    }
+
+    fn slot(&self, entity: Entity) -> Option<Slot> {
+        match self.slots.get(entity.index() as usize) {
+            Some(Some((slot_entity, slot))) if *slot_entity == entity => Some(*slot),
+            _ => None,
+        }
+    }
+
+    // position in TreeJoints: the joints of the subtrees in order, then the bases
+    fn table_index(&self, entity: Entity) -> Option<usize> {
+        match self.slot(entity)? {
+            Slot::Base(base) => Some(self.len + base),
+            Slot::Joint(subtree, index) => Some(self.subtrees[subtree].start + index),
+        }
+    }
+
+    fn set_slot(&mut self, entity: Entity, slot: Slot) {
+        let index = entity.index() as usize;
+        if self.slots.len() <= index {
+            self.slots.resize(index + 1, None);
+        }
+        self.slots[index] = Some((entity, slot));
+    }
+
+    fn build(
+        &mut self,
+        base_query: &Query<Entity, With<Base>>,
+        joint_children_query: &Query<&Children, With<Joint>>,
+    ) {
+        self.bases.clear();
+        self.subtrees.clear();
+        self.slots.clear();
+        self.len = 0;
+
+        for base_entity in base_query.iter() {
+            // a Base without a Joint has nothing to pass to its children
+            let Ok(children) = joint_children_query.get(base_entity) else {
+                continue;
+            };
+            let base = self.bases.len();
+            self.bases.push(base_entity);
+            self.set_slot(base_entity, Slot::Base(base));
+
             for child_entity in children.iter() {
-                recursive_loop(
-                    base_entity,
-                    &child_entity,
-                    &joint_children_query,
-                    &mut joint_query,
-                    fn_out,
-                    fn_in,
-                );
+                let mut subtree = JointSubtree {
+                    base,
+                    joints: Vec::new(),
+                    parents: Vec::new(),
+                    start: self.len,
+                };
+                add_joint(&mut subtree, *child_entity, None, joint_children_query);
+                let subtree_index = self.subtrees.len();
+                for (index, entity) in subtree.joints.iter().enumerate() {
+                    self.set_slot(*entity, Slot::Joint(subtree_index, index));
+                }
+                self.len += subtree.joints.len();
+                self.subtrees.push(subtree);
             }
         }
     }
 }
 
-pub fn recursive_loop(
-    parent_entity: Entity,
-    joint_entity: &Entity,
+fn add_joint(
+    subtree: &mut JointSubtree,
+    entity: Entity,
+    parent: Option<usize>,
     joint_children_query: &Query<&Children, With<Joint>>,
-    joint_query: &mut Query<&mut Joint>,
-    fn_out: Option<fn(&mut Joint, &Joint)>,
-    fn_in: Option<fn(&mut Joint, Option<&mut Joint>)>,
 ) {
-    match fn_out {
-        Some(f) => {
-            // get parent and joint
-            if let Ok([parent, mut joint]) =
-                joint_query.get_many_mut([parent_entity, *joint_entity])
-            {
-                // call fn_out - outward pass, ordered from parent to child
-                f(&mut joint, &parent);
-            }
+    let index = subtree.joints.len();
+    subtree.joints.push(entity);
+    subtree.parents.push(parent);
+
+    if let Ok(children) = joint_children_query.get(entity) {
+        for child_entity in children.iter() {
+            add_joint(subtree, *child_entity, Some(index), joint_children_query);
         }
-        None => (),
     }
+}
 
-    match joint_children_query.get(*joint_entity) {
-        Ok(children) => {
-            // joint has children. loop through them.
-            for child_entity in children.iter() {
-                recursive_loop(
-                    *joint_entity,
-                    child_entity,
-                    joint_children_query,
-                    joint_query,
-                    fn_out,
-                    fn_in,
-                );
+// rebuild the JointTree when joints or bases are added or removed, or reparented
+#[allow(clippy::type_complexity)]
+pub fn update_joint_tree(
+    mut tree: ResMut<JointTree>,
+    base_query: Query<Entity, With<Base>>,
+    joint_children_query: Query<&Children, With<Joint>>,
+    changed_query: Query<(), Or<(Added<Joint>, Added<Base>, Changed<Children>)>>,
+    mut removed_joints: RemovedComponents<Joint>,
+    mut removed_bases: RemovedComponents<Base>,
+    mut removed_children: RemovedComponents<Children>,
+) {
+    let removed = removed_joints.read().count()
+        + removed_bases.read().count()
+        + removed_children.read().count();
+    if changed_query.is_empty() && removed == 0 {
+        return;
+    }
+    tree.build(&base_query, &joint_children_query);
+}
+
+pub fn loop_1(tree: Res<JointTree>, mut joint_query: Query<(Entity, &mut Joint)>) {
+    TreeJoints::gather(&tree, &mut joint_query).outward_pass(loop_1_update);
+}
+
+pub fn apply_external_forces(tree: Res<JointTree>, mut joint_query: Query<(Entity, &mut Joint)>) {
+    TreeJoints::gather(&tree, &mut joint_query).outward_pass(apply_external_update);
+}
+
+pub fn loop_23(tree: Res<JointTree>, mut joint_query: Query<(Entity, &mut Joint)>) {
+    let mut joints = TreeJoints::gather(&tree, &mut joint_query);
+    joints.inward_pass(loop_2_update);
+    joints.outward_pass(loop_3_update);
+}
+
+// apply_external_forces and loop_23 on one gather of the joints
+pub fn external_forces_loop_23(tree: Res<JointTree>, mut joint_query: Query<(Entity, &mut Joint)>) {
+    let mut joints = TreeJoints::gather(&tree, &mut joint_query);
+    joints.outward_pass(apply_external_update);
+    joints.inward_pass(loop_2_update);
+    joints.outward_pass(loop_3_update);
+}
+
+type Joints<'w> = [Option<Mut<'w, Joint>>];
+
+// The joints of the JointTree borrowed from the query, in the order of the tree: the joints of
+// each subtree, then the bases. Gathering them scans the query once, so passes that follow each
+// other should share one TreeJoints.
+pub struct TreeJoints<'t, 'w> {
+    tree: &'t JointTree,
+    joints: Vec<Option<Mut<'w, Joint>>>,
+}
+
+impl<'t, 'w> TreeJoints<'t, 'w> {
+    pub fn gather(tree: &'t JointTree, joint_query: &'w mut Query<(Entity, &mut Joint)>) -> Self {
+        let mut joints: Vec<Option<Mut<Joint>>> = Vec::with_capacity(tree.len + tree.bases.len());
+        joints.resize_with(tree.len + tree.bases.len(), || None);
+        for (entity, joint) in joint_query.iter_mut() {
+            if let Some(index) = tree.table_index(entity) {
+                joints[index] = Some(joint);
             }
         }
-        Err(_e) => {
-            // joint has no children. This is fine. Do nothing.
+        Self { tree, joints }
+    }
+
+    // the bases, and the joints of each subtree
+    fn split(&mut self) -> (&mut Joints<'w>, Vec<&mut Joints<'w>>) {
+        let (mut rest, bases) = self.joints.split_at_mut(self.tree.len);
+        let mut subtrees = Vec::with_capacity(self.tree.subtrees.len());
+        for subtree in self.tree.subtrees.iter() {
+            let (joints, tail) = std::mem::take(&mut rest).split_at_mut(subtree.joints.len());
+            subtrees.push(joints);
+            rest = tail;
         }
+        (bases, subtrees)
+    }
+
+    // outward pass, ordered from parent to child
+    pub fn outward_pass(&mut self, f: fn(&mut Joint, &Joint)) {
+        let tree = self.tree;
+        let (bases, mut subtrees) = self.split();
+        evaluate_subtrees(tree, bases, &mut subtrees, |subtree, base, joints| {
+            for index in 0..joints.len() {
+                let (head, tail) = joints.split_at_mut(index);
+                let parent = match subtree.parents[index] {
+                    Some(parent) => head[parent].as_deref(),
+                    None => base,
+                };
+                if let (Some(parent), Some(joint)) = (parent, tail[0].as_deref_mut()) {
+                    f(joint, parent);
+                }
+            }
+        });
     }
 
-    // get parent and joint
-    match fn_in {
-        Some(f) => {
-            if let Ok([mut parent, mut joint]) =
-                joint_query.get_many_mut([parent_entity, *joint_entity])
+    // inward pass, ordered from child to parent
+    pub fn inward_pass(&mut self, f: fn(&mut Joint, Option<&mut Joint>)) {
+        let tree = self.tree;
+        let (bases, mut subtrees) = self.split();
+        evaluate_subtrees(tree, bases, &mut subtrees, |subtree, _base, joints| {
+            for index in (0..joints.len()).rev() {
+                // the roots write to the shared Base, below
+                let Some(parent) = subtree.parents[index] else {
+                    continue;
+                };
+                let (head, tail) = joints.split_at_mut(index);
+                if let (Some(parent), Some(joint)) =
+                    (head[parent].as_deref_mut(), tail[0].as_deref_mut())
+                {
+                    f(joint, Some(parent));
+                }
+            }
+        });
+
+        for (subtree, joints) in tree.subtrees.iter().zip(subtrees.iter_mut()) {
+            if let (Some(base), Some(root)) =
+                (bases[subtree.base].as_deref_mut(), joints[0].as_deref_mut())
             {
-                // call fn_in - inward pass, ordered from child to parent
-                f(&mut joint, Some(&mut parent));
+                f(root, Some(base));
             }
         }
-        None => (),
     }
 }
+
+// Each subtree only shares its Base with the others, so they are evaluated in parallel on the
+// compute task pool, in chunks of consecutive subtrees with about the same number of joints, one
+// chunk per thread. Each subtree is evaluated in a fixed order, so the results don't depend on
+// the number of threads or chunks.
+fn evaluate_subtrees<'w>(
+    tree: &JointTree,
+    bases: &Joints<'w>,
+    subtrees: &mut [&mut Joints<'w>],
+    evaluate: impl Fn(&JointSubtree, Option<&Joint>, &mut Joints<'w>) + Sync,
+) {
+    let evaluate = &evaluate;
+    let evaluate_chunk = |tree_subtrees: &[JointSubtree], subtrees: &mut [&mut Joints<'w>]| {
+        for (subtree, joints) in tree_subtrees.iter().zip(subtrees.iter_mut()) {
+            evaluate(subtree, bases[subtree.base].as_deref(), joints);
+        }
+    };
+
+    let task_pool = ComputeTaskPool::get_or_init(TaskPool::default);
+    let chunks = chunk_count(tree, task_pool.thread_num());
+    if chunks < 2 {
+        evaluate_chunk(&tree.subtrees, subtrees);
+        return;
+    }
+
+    let evaluate_chunk = &evaluate_chunk;
+    task_pool.scope(|scope| {
+        let mut tree_subtrees = tree.subtrees.as_slice();
+        let mut subtrees = subtrees;
+        let mut joints = 0;
+        for chunk in 1..=chunks {
+            // the chunk ends at the subtree that reaches its share of the joints
+            let end_joints = tree.len * chunk / chunks;
+            let mut end = 0;
+            while end < tree_subtrees.len() && (joints < end_joints || end == 0) {
+                joints += tree_subtrees[end].joints.len();
+                end += 1;
+            }
+            if chunk == chunks {
+                end = tree_subtrees.len();
+            }
+            if end == 0 {
+                break;
+            }
+            let (chunk_tree, tree_tail) = tree_subtrees.split_at(end);
+            let (chunk_subtrees, tail) = std::mem::take(&mut subtrees).split_at_mut(end);
+            tree_subtrees = tree_tail;
+            subtrees = tail;
+            scope.spawn(async move { evaluate_chunk(chunk_tree, chunk_subtrees) });
+        }
+    });
+}
+
+// one chunk per thread, each with at least parallel_min_joints joints and one subtree
+fn chunk_count(tree: &JointTree, threads: usize) -> usize {
+    let by_size = match tree.parallel_min_joints {
+        0 => usize::MAX,
+        min_joints => tree.len / min_joints,
+    };
+    threads.min(by_size).min(tree.subtrees.len())
+}
+
+// outward pass, ordered from parent to child
+pub fn outward_pass(
+    tree: &JointTree,
+    joint_query: &mut Query<(Entity, &mut Joint)>,
+    f: fn(&mut Joint, &Joint),
+) {
+    TreeJoints::gather(tree, joint_query).outward_pass(f);
+}
+
+// inward pass, ordered from child to parent
+pub fn inward_pass(
+    tree: &JointTree,
+    joint_query: &mut Query<(Entity, &mut Joint)>,
+    f: fn(&mut Joint, Option<&mut Joint>),
+) {
+    TreeJoints::gather(tree, joint_query).inward_pass(f);
+}
@@ -1,7 +1,9 @@
 use core::ops::{Add, Mul, Sub};
 use std::ops::{AddAssign, SubAssign};
 
-use nalgebra::{Matrix3, Matrix6, Matrix6x1, Quaternion, SMatrix, UnitQuaternion, Vector3};
+use nalgebra::{
+    Matrix3, Matrix6, Matrix6x1, Quaternion, Rotation3, SMatrix, Unit, UnitQuaternion, Vector3,
+};
 
 pub type Vector = Vector3<f64>;
 pub type Matrix = Matrix3<f64>;
@@ -34,6 +36,13 @@ pub fn ry(angle: f64) -> Matrix {
     )
 }
 
+// coordinate rotation about a unit axis, rx, ry and rz for the coordinate axes
+pub fn r_axis(axis: Vector, angle: f64) -> Matrix {
+    Rotation3::from_axis_angle(&Unit::new_unchecked(axis), angle)
+        .into_inner()
+        .transpose()
+}
+
 pub fn rz(angle: f64) -> Matrix {
     Matrix::new(
         angle.cos(),
@@ -99,6 +108,19 @@ impl Xform {
             ..Default::default()
         }
     }
+    pub fn rot(axis: Vector, angle: f64) -> Self {
+        Self {
+            rotation: r_axis(axis, angle),
+            ..Default::default()
+        }
+    }
+    // screw motion, a rotation about the axis and a translation of pitch * angle along it
+    pub fn helical(axis: Vector, angle: f64, pitch: f64) -> Self {
+        Self {
+            position: pitch * angle * axis,
+            rotation: r_axis(axis, angle),
+        }
+    }
     pub fn posx(x: f64) -> Self {
         Self {
             position: Vector::new(x, 0.0, 0.0),
@@ -117,6 +139,12 @@ impl Xform {
             ..Default::default()
         }
     }
+    pub fn pos_axis(axis: Vector, distance: f64) -> Self {
+        Self {
+            position: distance * axis,
+            ..Default::default()
+        }
+    }
     pub fn pos(x: f64, y: f64, z: f64) -> Self {
         Self {
             position: Vector::new(x, y, z),
@@ -140,7 +168,6 @@ impl Xform {
     pub fn transform_point(self, point: Vector) -> Vector {
         self.rotation * (point - self.position)
     }
-
 }
 
 impl Mul<Xform> for Xform {
@@ -288,6 +315,13 @@ impl Motion {
             vel: self.w.cross(&point) + self.v,
         }
     }
+
+    pub fn from_mat(mat: &Matrix6x1<f64>) -> Self {
+        Self {
+            w: Vector::new(mat[(0, 0)], mat[(1, 0)], mat[(2, 0)]),
+            v: Vector::new(mat[(3, 0)], mat[(4, 0)], mat[(5, 0)]),
+        }
+    }
 }
 
 impl Add<Motion> for Motion {
@@ -300,6 +334,16 @@ impl Add<Motion> for Motion {
     }
 }
 
+impl Sub<Motion> for Motion {
+    type Output = Motion;
+    fn sub(self, rhs: Motion) -> Motion {
+        Motion {
+            v: self.v - rhs.v,
+            w: self.w - rhs.w,
+        }
+    }
+}
+
 impl Default for Motion {
     fn default() -> Self {
         Self::zero()
@@ -357,6 +401,10 @@ impl Force {
             f: Vector::new(mat[(3, 0)], mat[(4, 0)], mat[(5, 0)]),
         }
     }
+
+    pub fn to_mat(&self) -> Matrix6x1<f64> {
+        Matrix6x1::new(self.m.x, self.m.y, self.m.z, self.f.x, self.f.y, self.f.z)
+    }
 }
 
 impl Default for Force {
@@ -454,14 +502,52 @@ impl Inertia {
             moi: Matrix::zeros(),
         }
     }
+
+    pub fn mass(&self) -> f64 {
+        self.m
+    }
+
+    // the inertia given in the coordinates of frame x, in the coordinates x is relative to
+    pub fn from_frame(self, x: Xform) -> Inertia {
+        let rotation = x.rotation.transpose();
+        Inertia {
+            m: self.m,
+            c: x.position + rotation * self.c,
+            moi: rotation * self.moi * x.rotation,
+        }
+    }
+}
+
+// two bodies joined into one, the moment of inertia is about the new center of mass
+impl Add<Inertia> for Inertia {
+    type Output = Inertia;
+
+    fn add(self, rhs: Inertia) -> Inertia {
+        let m = self.m + rhs.m;
+        if m <= 0. {
+            return Inertia::zero();
+        }
+        let c = (self.m * self.c + rhs.m * rhs.c) / m;
+        let parallel_axis = |inertia: &Inertia| {
+            let d = (inertia.c - c).cross_matrix();
+            inertia.moi - inertia.m * d * d
+        };
+        Inertia {
+            m,
+            c,
+            moi: parallel_axis(&self) + parallel_axis(&rhs),
+        }
+    }
 }
 
 impl Mul<Motion> for Inertia {
     type Output = Force;
     fn mul(self, rhs: Motion) -> Force {
+        // c is the center of mass and moi is about it, as in From<Inertia> for InertiaAB
+        let v_c = rhs.v - self.c.cross(&rhs.w); // velocity of the center of mass
         Force {
-            f: self.m * rhs.v - self.c.cross(&rhs.w),
-            m: self.moi * rhs.w + self.c.cross(&rhs.v),
+            f: self.m * v_c,
+            m: self.moi * rhs.w + self.m * self.c.cross(&v_c),
         }
     }
 }
@@ -499,6 +585,22 @@ impl InertiaAB {
         }
         InertiaAB { m, c, moi }
     }
+
+    pub fn to_mat(&self) -> Matrix6<f64> {
+        let mut mat = Matrix6::zeros();
+        mat.fixed_view_mut::<3, 3>(0, 0).copy_from(&self.moi);
+        mat.fixed_view_mut::<3, 3>(0, 3).copy_from(&self.c);
+        mat.fixed_view_mut::<3, 3>(3, 0)
+            .copy_from(&self.c.transpose());
+        mat.fixed_view_mut::<3, 3>(3, 3).copy_from(&self.m);
+        mat
+    }
+
+    // the acceleration a with self * a = force, None if the inertia is singular
+    pub fn solve(&self, force: Force) -> Option<Motion> {
+        let solution = self.to_mat().lu().solve(&force.to_mat())?;
+        Some(Motion::from_mat(&solution))
+    }
 }
 
 impl From<Inertia> for InertiaAB {
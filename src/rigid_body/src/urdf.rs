@@ -0,0 +1,483 @@
+use std::{
+    collections::HashMap,
+    f64::consts::FRAC_PI_2,
+    fs,
+    io::{Error, ErrorKind, Result},
+};
+
+use bevy::prelude::*;
+use nalgebra::Rotation3;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::{
+    definitions::{MeshDef, MeshTypeDef, TransformDef},
+    joint::{Joint, JointType},
+    sva::{Inertia, Matrix, Vector, Xform},
+};
+
+// A robot description in the Unified Robot Description Format, as links joined by joints. Links
+// have an inertia and a visual, and become the bodies of Joint entities. Revolute, continuous and
+// prismatic joints map to JointType::Revolute and JointType::Prismatic about their axis, and
+// floating joints to JointType::Floating. Fixed joints have no Joint of their own: their child
+// link is merged into the body of the parent link, and its visual is put on a child entity of
+// that body, as the body keeps the visual of its own link.
+//
+// Only the first visual of a link is used, and spheres, mesh scales and collision elements are
+// ignored. Mesh files are relative to the assets folder, with package:// and file:// removed.
+#[derive(Clone, Debug)]
+pub struct Urdf {
+    pub name: String,
+    pub links: Vec<UrdfLink>,
+    pub joints: Vec<UrdfJoint>,
+}
+
+#[derive(Clone, Debug)]
+pub struct UrdfLink {
+    pub name: String,
+    pub inertia: Inertia, // in link coordinates
+    pub visual: Option<MeshDef>,
+}
+
+#[derive(Clone, Debug)]
+pub struct UrdfJoint {
+    pub name: String,
+    pub joint_type: Option<JointType>, // None for a fixed joint
+    pub parent: String,
+    pub child: String,
+    pub origin: Xform, // from the parent link to the joint (and child link)
+    pub limit: Option<(f64, f64)>, // lower and upper, e.g. for a JointLimit
+}
+
+// a body of the joint tree, the links joined by fixed joints
+struct Body {
+    name: String,
+    joint_type: Option<JointType>, // None for the root fixed to the parent entity
+    parent: Option<usize>,
+    xt: Xform,
+    inertia: Inertia,
+    visuals: Vec<MeshDef>, // of the merged links, in body coordinates
+}
+
+impl Urdf {
+    pub fn from_file(path: &str) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(xml: &str) -> Result<Self> {
+        let robot = Element::parse(xml)?;
+        if robot.name != "robot" {
+            return Err(invalid("the root element is not a robot".to_string()));
+        }
+
+        // named materials can be used by the visuals of all links
+        let mut colors = HashMap::new();
+        for material in robot.children("material") {
+            if let Some(color) = material.child("color") {
+                colors.insert(
+                    material.attribute("name")?.to_string(),
+                    color_attribute(color)?,
+                );
+            }
+        }
+
+        let links = robot
+            .children("link")
+            .map(|link| UrdfLink::parse(link, &colors))
+            .collect::<Result<Vec<_>>>()?;
+        let joints = robot
+            .children("joint")
+            .map(UrdfJoint::parse)
+            .collect::<Result<Vec<_>>>()?;
+
+        let urdf = Self {
+            name: robot.attribute("name").unwrap_or_default().to_string(),
+            links,
+            joints,
+        };
+        urdf.root()?;
+        Ok(urdf)
+    }
+
+    // The link that is no joint's child. Also checks the links of the joints, and that each
+    // link has one parent.
+    pub fn root(&self) -> Result<&UrdfLink> {
+        let mut children: Vec<&str> = Vec::new();
+        for joint in self.joints.iter() {
+            for link in [&joint.parent, &joint.child] {
+                if self.link(link).is_none() {
+                    return Err(invalid(format!(
+                        "joint {} has no link {}",
+                        joint.name, link
+                    )));
+                }
+            }
+            if children.contains(&joint.child.as_str()) {
+                return Err(invalid(format!("link {} has two parents", joint.child)));
+            }
+            children.push(&joint.child);
+        }
+        let mut roots = self
+            .links
+            .iter()
+            .filter(|link| !children.contains(&link.name.as_str()));
+        match (roots.next(), roots.next()) {
+            (Some(root), None) => Ok(root),
+            _ => Err(invalid("the links are not a single tree".to_string())),
+        }
+    }
+
+    pub fn link(&self, name: &str) -> Option<&UrdfLink> {
+        self.links.iter().find(|link| link.name == name)
+    }
+
+    // Spawns the joints below the parent entity, e.g. a Base, with the root link at origin.
+    // A floating root gets a floating joint. Otherwise it is fixed to the parent, its visual is
+    // put on the parent and its inertia is not used. Returns the entity of the body of each link.
+    pub fn spawn(
+        &self,
+        commands: &mut Commands,
+        parent: Entity,
+        origin: Xform,
+        floating: bool,
+    ) -> Result<HashMap<String, Entity>> {
+        let root = self.root()?;
+        // the root link relative to the root body, which is the parent if it is fixed
+        let root_x = if floating { Xform::identity() } else { origin };
+        let mut bodies = vec![Body {
+            name: root.name.clone(),
+            joint_type: floating.then_some(JointType::Floating),
+            parent: None,
+            xt: origin,
+            inertia: root.inertia,
+            visuals: root
+                .visual
+                .iter()
+                .map(|visual| MeshDef {
+                    transform: pose(Xform::from(&visual.transform) * root_x),
+                    ..visual.clone()
+                })
+                .collect(),
+        }];
+
+        // body of each link, and the link frame relative to the body
+        let mut link_bodies: Vec<(&str, usize, Xform)> = vec![(&root.name, 0, root_x)];
+        let mut index = 0;
+        while index < link_bodies.len() {
+            let (parent_link, parent_body, parent_x) = link_bodies[index];
+            for joint in self
+                .joints
+                .iter()
+                .filter(|joint| joint.parent == parent_link)
+            {
+                let link = self.link(&joint.child).unwrap();
+                match joint.joint_type {
+                    Some(joint_type) => {
+                        bodies.push(Body {
+                            name: joint.name.clone(),
+                            joint_type: Some(joint_type),
+                            parent: Some(parent_body),
+                            xt: joint.origin * parent_x,
+                            inertia: link.inertia,
+                            visuals: link.visual.iter().cloned().collect(),
+                        });
+                        link_bodies.push((&link.name, bodies.len() - 1, Xform::identity()));
+                    }
+                    None => {
+                        // the child link is part of the parent body
+                        let x = joint.origin * parent_x;
+                        let body = &mut bodies[parent_body];
+                        body.inertia = body.inertia + link.inertia.from_frame(x);
+                        body.visuals
+                            .extend(link.visual.iter().map(|visual| MeshDef {
+                                transform: pose(Xform::from(&visual.transform) * x),
+                                ..visual.clone()
+                            }));
+                        link_bodies.push((&link.name, parent_body, x));
+                    }
+                }
+            }
+            index += 1;
+        }
+        if link_bodies.len() != self.links.len() {
+            return Err(invalid("some links are in a loop".to_string()));
+        }
+
+        let mut entities: Vec<Entity> = Vec::new();
+        for body in bodies {
+            let parent_entity = body.parent.map_or(parent, |index| entities[index]);
+            let Some(joint_type) = body.joint_type else {
+                insert_visuals(commands, parent_entity, body.visuals);
+                entities.push(parent_entity);
+                continue;
+            };
+            let joint = match joint_type {
+                JointType::Revolute(axis) => {
+                    Joint::revolute(body.name, body.inertia, body.xt, axis)
+                }
+                JointType::Prismatic(axis) => {
+                    Joint::prismatic(body.name, body.inertia, body.xt, axis)
+                }
+                _ => Joint::floating(body.name, body.inertia, body.xt),
+            };
+            let entity = commands.spawn(joint).set_parent(parent_entity).id();
+            insert_visuals(commands, entity, body.visuals);
+            entities.push(entity);
+        }
+
+        Ok(link_bodies
+            .into_iter()
+            .map(|(link, body, _)| (link.to_string(), entities[body]))
+            .collect())
+    }
+}
+
+// the first visual on the body, and the visuals of the links merged into it on child entities
+fn insert_visuals(commands: &mut Commands, entity: Entity, visuals: Vec<MeshDef>) {
+    let mut visuals = visuals.into_iter();
+    if let Some(visual) = visuals.next() {
+        commands.entity(entity).insert(visual);
+    }
+    for visual in visuals {
+        commands.spawn(visual).set_parent(entity);
+    }
+}
+
+impl UrdfLink {
+    fn parse(link: &Element, colors: &HashMap<String, Color>) -> Result<Self> {
+        let name = link.attribute("name")?.to_string();
+        let inertia = match link.child("inertial") {
+            Some(inertial) => {
+                let mass = inertial
+                    .child("mass")
+                    .map_or(Ok(0.), |mass| number(mass.attribute("value")?))?;
+                let moi = match inertial.child("inertia") {
+                    Some(inertia) => {
+                        let [ixx, ixy, ixz, iyy, iyz, izz] =
+                            ["ixx", "ixy", "ixz", "iyy", "iyz", "izz"]
+                                .map(|name| inertia.attribute(name).and_then(number));
+                        let (ixx, ixy, ixz, iyy, iyz, izz) = (ixx?, ixy?, ixz?, iyy?, iyz?, izz?);
+                        Matrix::new(ixx, ixy, ixz, ixy, iyy, iyz, ixz, iyz, izz)
+                    }
+                    None => Matrix::zeros(),
+                };
+                Inertia::new(mass, Vector::zeros(), moi).from_frame(origin(inertial)?)
+            }
+            None => Inertia::zero(),
+        };
+
+        let visual = match link.child("visual") {
+            Some(visual) => {
+                let color = match visual.child("material") {
+                    Some(material) => match material.child("color") {
+                        Some(color) => color_attribute(color)?,
+                        None => material
+                            .attribute("name")
+                            .ok()
+                            .and_then(|name| colors.get(name).copied())
+                            .unwrap_or(Color::GRAY),
+                    },
+                    None => Color::GRAY,
+                };
+                let x = origin(visual)?;
+                visual
+                    .child("geometry")
+                    .and_then(|geometry| geometry.children.first())
+                    .map(|shape| mesh_def(shape, x, color))
+                    .transpose()?
+                    .flatten()
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            name,
+            inertia,
+            visual,
+        })
+    }
+}
+
+impl UrdfJoint {
+    fn parse(joint: &Element) -> Result<Self> {
+        let name = joint.attribute("name")?.to_string();
+        let axis = match joint.child("axis") {
+            Some(axis) => vector(axis.attribute("xyz")?)?.normalize(),
+            None => Vector::x(),
+        };
+        let joint_type = match joint.attribute("type")? {
+            "revolute" | "continuous" => Some(JointType::Revolute(axis)),
+            "prismatic" => Some(JointType::Prismatic(axis)),
+            "floating" => Some(JointType::Floating),
+            "fixed" => None,
+            other => return Err(invalid(format!("joint {} has type {}", name, other))),
+        };
+        let limit = match (joint.attribute("type")?, joint.child("limit")) {
+            ("revolute" | "prismatic", Some(limit)) => Some((
+                number(limit.attribute("lower")?)?,
+                number(limit.attribute("upper")?)?,
+            )),
+            _ => None,
+        };
+        Ok(Self {
+            name,
+            joint_type,
+            parent: joint
+                .child("parent")
+                .map_or(Ok(""), |p| p.attribute("link"))?
+                .to_string(),
+            child: joint
+                .child("child")
+                .map_or(Ok(""), |c| c.attribute("link"))?
+                .to_string(),
+            origin: origin(joint)?,
+            limit,
+        })
+    }
+}
+
+// the visual of a geometry element, None for unsupported shapes
+fn mesh_def(shape: &Element, x: Xform, color: Color) -> Result<Option<MeshDef>> {
+    let (mesh_type, x) = match shape.name.as_str() {
+        "box" => {
+            let size = vector(shape.attribute("size")?)?;
+            let dimensions = [size.x as f32, size.y as f32, size.z as f32];
+            (MeshTypeDef::Box { dimensions }, x)
+        }
+        "cylinder" => {
+            let radius = number(shape.attribute("radius")?)? as f32;
+            let height = number(shape.attribute("length")?)? as f32;
+            // URDF cylinders are along z, the meshes along y
+            (
+                MeshTypeDef::Cylinder { height, radius },
+                Xform::rotx(FRAC_PI_2) * x,
+            )
+        }
+        "mesh" => {
+            let file_name = shape.attribute("filename")?;
+            let file_name = file_name
+                .strip_prefix("package://")
+                .or_else(|| file_name.strip_prefix("file://"))
+                .unwrap_or(file_name)
+                .to_string();
+            (MeshTypeDef::File { file_name }, x)
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(MeshDef {
+        mesh_type,
+        transform: pose(x),
+        color,
+    }))
+}
+
+// the origin element of a joint, inertial or visual
+fn origin(element: &Element) -> Result<Xform> {
+    let Some(origin) = element.child("origin") else {
+        return Ok(Xform::identity());
+    };
+    let position = origin
+        .attribute("xyz")
+        .map_or(Ok(Vector::zeros()), vector)?;
+    let rpy = origin
+        .attribute("rpy")
+        .map_or(Ok(Vector::zeros()), vector)?;
+    Ok((&TransformDef::Pose {
+        position: position.into(),
+        rpy: rpy.into(),
+    })
+        .into())
+}
+
+fn pose(x: Xform) -> TransformDef {
+    let (roll, pitch, yaw) =
+        Rotation3::from_matrix_unchecked(x.rotation.transpose()).euler_angles();
+    TransformDef::Pose {
+        position: x.position.into(),
+        rpy: [roll, pitch, yaw],
+    }
+}
+
+fn color_attribute(color: &Element) -> Result<Color> {
+    let rgba: Vec<f32> = numbers(color.attribute("rgba")?)?
+        .into_iter()
+        .map(|value| value as f32)
+        .collect();
+    match rgba[..] {
+        [r, g, b, a] => Ok(Color::rgba(r, g, b, a)),
+        _ => Err(invalid(format!("color {:?} is not rgba", rgba))),
+    }
+}
+
+fn number(text: &str) -> Result<f64> {
+    text.trim()
+        .parse()
+        .map_err(|_| invalid(format!("{} is not a number", text)))
+}
+
+fn numbers(text: &str) -> Result<Vec<f64>> {
+    text.split_whitespace().map(number).collect()
+}
+
+fn vector(text: &str) -> Result<Vector> {
+    match numbers(text)?[..] {
+        [x, y, z] => Ok(Vector::new(x, y, z)),
+        _ => Err(invalid(format!("{} is not a vector", text))),
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+// an XML element with its attributes and child elements
+#[derive(Debug)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn parse(xml: &str) -> Result<Self> {
+        let mut stack: Vec<Element> = Vec::new();
+        for event in EventReader::from_str(xml) {
+            match event.map_err(|error| invalid(error.to_string()))? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => stack.push(Element {
+                    name: name.local_name,
+                    attributes: attributes
+                        .into_iter()
+                        .map(|attribute| (attribute.name.local_name, attribute.value))
+                        .collect(),
+                    children: Vec::new(),
+                }),
+                XmlEvent::EndElement { .. } => {
+                    let element = stack.pop().unwrap();
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return Ok(element),
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(invalid("no root element".to_string()))
+    }
+
+    fn attribute(&self, name: &str) -> Result<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .ok_or_else(|| invalid(format!("{} has no attribute {}", self.name, name)))
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use bevy_integrator::{step, PhysicsSchedule, Solver};
+use rigid_body::{
+    collision::{Collider, CollisionPlugin, CollisionShape, Contact, Contacts},
+    joint::Joint,
+    sva::{Force, Inertia, Matrix, Vector, Xform},
+};
+
+mod common;
+use common::{base, headless_app_with_plugins};
+
+// The bodies by entity, with their names, positions and external forces
+type Bodies = HashMap<Entity, (String, Vector, Force)>;
+
+// Contacts found by GJK and EPA, from one evaluation of the physics at the initial state
+fn contacts(startup_system: fn(Commands)) -> (Vec<Contact>, Bodies) {
+    let mut app = headless_app_with_plugins(0.001, Solver::RK4, CollisionPlugin, startup_system);
+    step::<Joint>(&mut app.world, 0); // sets up the state of the joints
+    app.world.run_schedule(PhysicsSchedule);
+
+    let mut query = app.world.query::<(Entity, &Joint)>();
+    let bodies = query
+        .iter(&app.world)
+        .map(|(entity, joint)| {
+            let body = (joint.name.clone(), joint.floating.position, joint.f_ext);
+            (entity, body)
+        })
+        .collect();
+    (app.world.resource::<Contacts>().contacts.clone(), bodies)
+}
+
+fn floating_body(
+    commands: &mut Commands,
+    base_id: Entity,
+    name: &str,
+    position: [f64; 3],
+    shape: CollisionShape,
+) {
+    let inertia = Inertia::new(
+        1.,
+        Vector::zeros(),
+        Matrix::from_diagonal(&Vector::new(0.1, 0.1, 0.1)),
+    );
+    let mut joint = Joint::floating(name.to_string(), inertia, Xform::identity());
+    joint.floating.set_pose(position, [0., 0., 0.]);
+    commands
+        .spawn((joint, Collider::new(shape, Xform::identity())))
+        .set_parent(base_id);
+}
+
+fn overlapping_spheres(mut commands: Commands) {
+    let base_id = base(&mut commands);
+    floating_body(
+        &mut commands,
+        base_id,
+        "a",
+        [0., 0., 0.],
+        CollisionShape::Sphere { radius: 0.5 },
+    );
+    // 0.1 deep, along (1, 1, 0)
+    let distance = 0.9 / 2_f64.sqrt();
+    floating_body(
+        &mut commands,
+        base_id,
+        "b",
+        [distance, distance, 0.],
+        CollisionShape::Sphere { radius: 0.5 },
+    );
+}
+
+fn separate_spheres(mut commands: Commands) {
+    let base_id = base(&mut commands);
+    floating_body(
+        &mut commands,
+        base_id,
+        "a",
+        [0., 0., 0.],
+        CollisionShape::Sphere { radius: 0.5 },
+    );
+    floating_body(
+        &mut commands,
+        base_id,
+        "b",
+        [0.8, 0.8, 0.],
+        CollisionShape::Sphere { radius: 0.5 },
+    );
+}
+
+// a unit cube sunk 0.01 into a larger box, along z
+fn box_on_box(mut commands: Commands) {
+    let base_id = base(&mut commands);
+    floating_body(
+        &mut commands,
+        base_id,
+        "ground",
+        [0., 0., -0.5],
+        CollisionShape::Box {
+            half_extents: Vector::new(5., 5., 0.5),
+        },
+    );
+    floating_body(
+        &mut commands,
+        base_id,
+        "cube",
+        [0.2, -0.1, 0.49],
+        CollisionShape::Box {
+            half_extents: Vector::new(0.5, 0.5, 0.5),
+        },
+    );
+}
+
+#[test]
+fn sphere_penetration_depth_and_normal() {
+    let (contacts, bodies) = contacts(overlapping_spheres);
+    assert_eq!(contacts.len(), 1, "{:?}", contacts);
+    let contact = contacts[0];
+    // EPA approximates the spheres with a polytope, to about the support tolerance
+    assert!((contact.depth - 0.1).abs() < 1e-5, "{:?}", contact);
+
+    // the normal points from body_a to body_b, and the force pushes body_b away from body_a
+    let (_, position_a, f_ext_a) = &bodies[&contact.body_a];
+    let (_, position_b, f_ext_b) = &bodies[&contact.body_b];
+    let a_to_b = (position_b - position_a).normalize();
+    assert!(contact.normal.dot(&a_to_b) > 1. - 1e-4, "{:?}", contact);
+    assert!(contact.force.dot(&a_to_b) > 0., "{:?}", contact);
+    assert!(f_ext_a.f.dot(&a_to_b) < 0., "{:?}", f_ext_a);
+    assert!(f_ext_b.f.dot(&a_to_b) > 0., "{:?}", f_ext_b);
+}
+
+#[test]
+fn separate_spheres_have_no_contact() {
+    let (contacts, _) = contacts(separate_spheres);
+    assert!(contacts.is_empty());
+}
+
+#[test]
+fn resting_box_gets_a_contact_at_each_corner() {
+    let (contacts, bodies) = contacts(box_on_box);
+    assert_eq!(contacts.len(), 4, "{:?}", contacts);
+    for contact in contacts.iter() {
+        assert!((contact.depth - 0.01).abs() < 1e-6, "{:?}", contact);
+        assert!((contact.point.x - 0.2).abs() <= 0.5 + 1e-6, "{:?}", contact);
+        assert!((contact.point.y + 0.1).abs() <= 0.5 + 1e-6, "{:?}", contact);
+
+        // up from the ground to the cube, or down from the cube to the ground
+        let up = match bodies[&contact.body_a].0.as_str() {
+            "ground" => Vector::z(),
+            _ => -Vector::z(),
+        };
+        assert!(contact.normal.dot(&up) > 1. - 1e-6, "{:?}", contact);
+    }
+
+    // the cube is pushed up and the ground down
+    let f_ext = |name: &str| {
+        let (_, _, f_ext) = bodies.values().find(|body| body.0 == name).unwrap();
+        *f_ext
+    };
+    assert!(f_ext("cube").f.z > 0., "{:?}", f_ext("cube"));
+    assert!(f_ext("ground").f.z < 0., "{:?}", f_ext("ground"));
+}
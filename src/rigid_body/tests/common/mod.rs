@@ -0,0 +1,93 @@
+// shared by the integration tests, which each use some of it
+#![allow(dead_code)]
+
+use bevy::{app::Plugins, prelude::*};
+
+use bevy_integrator::{PhysicsState, SimTime, Solver};
+use rigid_body::{
+    joint::{Base, Joint, JointState},
+    plugin::HeadlessRigidBodyPlugin,
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+// an app without rendering, after its startup systems
+pub fn headless_app(dt: f64, solver: Solver, startup_system: fn(Commands)) -> App {
+    headless_app_with_plugins(dt, solver, (), startup_system)
+}
+
+// the same with more plugins, e.g. CollisionPlugin
+pub fn headless_app_with_plugins<M>(
+    dt: f64,
+    solver: Solver,
+    plugins: impl Plugins<M>,
+    startup_system: fn(Commands),
+) -> App {
+    let mut app = App::new();
+    app.add_plugins(HeadlessRigidBodyPlugin {
+        time: SimTime::new(dt, 0.0, None),
+        solver,
+        simulation_setup: vec![],
+    })
+    .add_plugins(plugins)
+    .add_systems(Startup, startup_system);
+    app.update();
+    app
+}
+
+pub fn base(commands: &mut Commands) -> Entity {
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    commands.spawn((base, Base)).id()
+}
+
+// the double pendulum of the examples, started away from rest
+pub fn double_pendulum_startup_system(mut commands: Commands) {
+    let base_id = base(&mut commands);
+    let inertia = Inertia::new(
+        1.,
+        Vector::new(0.0, 0.0, -0.5),
+        Matrix::from_diagonal(&Vector::new(0.08, 0.08, 0.0004)),
+    );
+
+    let mut ry0 = Joint::ry("body_ry0".to_string(), inertia, Xform::identity());
+    ry0.q = 0.5;
+    ry0.qd = 1.;
+    let ry0_id = commands.spawn(ry0).set_parent(base_id).id();
+
+    let mut ry1 = Joint::ry("body_ry1".to_string(), inertia, Xform::posz(-1.0));
+    ry1.q = -0.3;
+    ry1.qd = 2.;
+    commands.spawn(ry1).set_parent(ry0_id);
+}
+
+// a single pendulum, hanging down from the base at q = 0
+pub fn pendulum(commands: &mut Commands, q: f64) -> Entity {
+    let base_id = base(commands);
+    let inertia = Inertia::new(
+        1.,
+        Vector::new(0., 0., -0.5),
+        Matrix::from_diagonal(&Vector::new(0.08, 0.08, 0.0004)),
+    );
+    let mut pendulum = Joint::ry("pendulum".to_string(), inertia, Xform::identity());
+    pendulum.q = q;
+    commands.spawn(pendulum).set_parent(base_id).id()
+}
+
+pub fn joint<'a>(app: &'a mut App, name: &str) -> &'a Joint {
+    let mut query = app.world.query::<&Joint>();
+    query
+        .iter(&app.world)
+        .find(|joint| joint.name == name)
+        .unwrap_or_else(|| panic!("no joint {}", name))
+}
+
+// The accepted state of a joint after a step. The joint components hold the last solver stage.
+pub fn joint_state(app: &mut App, name: &str) -> JointState {
+    let mut query = app.world.query::<(Entity, &Joint)>();
+    let entity = query
+        .iter(&app.world)
+        .find(|(_, joint)| joint.name == name)
+        .map(|(entity, _)| entity)
+        .unwrap_or_else(|| panic!("no joint {}", name));
+    let physics_state = app.world.resource::<PhysicsState<Joint>>();
+    physics_state.states.get(&entity).unwrap().clone()
+}
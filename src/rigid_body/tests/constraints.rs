@@ -0,0 +1,100 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{step, Solver};
+use rigid_body::{
+    constraints::{LoopConstraint, LoopConstraintPlugin, Stabilization},
+    joint::Joint,
+    sva::{Inertia, Matrix, Vector, Xform},
+};
+
+mod common;
+use common::{base, headless_app_with_plugins, joint_state};
+
+// The parallelogram four-bar linkage of 04_four_bar: the crank and coupler are a chain from the
+// base, the rocker hangs from the base 2 m from the crank, and a ball constraint joins the end
+// of the coupler to the end of the rocker. The rocker starts 0.1 rad off the constraint.
+fn four_bar_startup_system(mut commands: Commands) {
+    let base_id = base(&mut commands);
+    let link = |center: Vector| {
+        Inertia::new(
+            1.,
+            center,
+            Matrix::from_diagonal(&Vector::new(1. / 12., 1. / 12., 1. / 12.)),
+        )
+    };
+
+    let mut crank = Joint::ry(
+        "crank".to_string(),
+        link(Vector::new(0., 0., -0.5)),
+        Xform::identity(),
+    );
+    crank.q = 0.5;
+    let crank_id = commands.spawn(crank).set_parent(base_id).id();
+
+    let mut coupler = Joint::ry(
+        "coupler".to_string(),
+        link(Vector::new(1., 0., 0.)),
+        Xform::posz(-1.),
+    );
+    coupler.q = -0.5;
+    let coupler_id = commands.spawn(coupler).set_parent(crank_id).id();
+
+    let mut rocker = Joint::ry(
+        "rocker".to_string(),
+        link(Vector::new(0., 0., -0.5)),
+        Xform::posx(2.),
+    );
+    rocker.q = 0.6;
+    let rocker_id = commands.spawn(rocker).set_parent(base_id).id();
+
+    commands.spawn(LoopConstraint::ball(
+        "coupler_rocker".to_string(),
+        (coupler_id, Xform::posx(2.)),
+        (rocker_id, Xform::posz(-1.)),
+    ));
+}
+
+// The distance from the parallelogram after the steps: the rocker follows the crank and the
+// coupler stays level, for any crank angle.
+fn drift(stabilization: Stabilization, steps: usize) -> f64 {
+    let mut app = headless_app_with_plugins(
+        0.002,
+        Solver::RK4,
+        LoopConstraintPlugin,
+        four_bar_startup_system,
+    );
+    for mut constraint in app
+        .world
+        .query::<&mut LoopConstraint>()
+        .iter_mut(&mut app.world)
+    {
+        constraint.stabilization = stabilization;
+    }
+
+    step::<Joint>(&mut app.world, steps);
+    let crank = joint_state(&mut app, "crank").q;
+    let coupler = joint_state(&mut app, "coupler").q;
+    let rocker = joint_state(&mut app, "rocker").q;
+    (rocker - crank).abs().max((coupler + crank).abs())
+}
+
+#[test]
+fn baumgarte_stabilization_removes_the_drift() {
+    // critically damped at 20 1/s, so after 1 s the drift is down by about e^-20 (1 + 20)
+    let drift_1 = drift(Stabilization::default(), 500);
+    assert!(drift_1 < 1e-6, "{:e}", drift_1);
+    let drift_none = drift(Stabilization::None, 500);
+    assert!(drift_none > 1e-2, "{:e}", drift_none);
+}
+
+#[test]
+fn projection_removes_the_drift_in_one_step() {
+    let stabilization = Stabilization::Projection {
+        iterations: 10,
+        tolerance: 1e-12,
+    };
+    for steps in [1, 500] {
+        let drift = drift(stabilization, steps);
+        assert!(drift < 1e-9, "{} steps: {:e}", steps, drift);
+    }
+}
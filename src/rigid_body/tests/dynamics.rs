@@ -0,0 +1,118 @@
+use bevy::prelude::*;
+use nalgebra::DVector;
+
+use bevy_integrator::{step, PhysicsSchedule, Solver};
+use rigid_body::{
+    dynamics::JointSpace,
+    joint::Joint,
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+mod common;
+use common::{base, double_pendulum_startup_system, headless_app};
+
+// The joint space dynamics (CRBA and RNEA) against the articulated-body passes of the physics
+
+// a few steps away from the initial state, then one evaluation of the physics at the current
+// state, so the joints hold its articulated-body accelerations
+fn evaluated_app(startup_system: fn(Commands)) -> App {
+    let mut app = headless_app(0.002, Solver::RK4, startup_system);
+    step::<Joint>(&mut app.world, 10);
+    app.world.run_schedule(PhysicsSchedule);
+    app
+}
+
+// a floating chassis with an offset center of mass, tumbling, with a spinning wheel on it
+fn floating_chassis_startup_system(mut commands: Commands) {
+    let base_id = base(&mut commands);
+    let chassis_inertia = Inertia::new(
+        1000.,
+        Vector::new(0.2, 0., -0.1),
+        Matrix::from_diagonal(&Vector::new(400., 2000., 2000.)),
+    );
+    let mut chassis = Joint::floating("chassis".to_string(), chassis_inertia, Xform::identity());
+    chassis.floating.set_pose([1., 2., 0.5], [0.1, -0.2, 1.57]);
+    chassis.floating.velocity = Motion::new([5., 0.5, -0.2], [0.3, -0.4, 0.2]);
+    let chassis_id = commands.spawn(chassis).set_parent(base_id).id();
+
+    let wheel_inertia = Inertia::new(
+        20.,
+        Vector::new(0., 0.05, 0.),
+        Matrix::from_diagonal(&Vector::new(0.5, 1., 0.5)),
+    );
+    let mut wheel = Joint::ry(
+        "wheel".to_string(),
+        wheel_inertia,
+        Xform::pos(1.5, 0.8, -0.3),
+    );
+    wheel.qd = 10.;
+    commands.spawn(wheel).set_parent(chassis_id);
+}
+
+fn assert_close(a: &DVector<f64>, b: &DVector<f64>, tolerance: f64) {
+    assert_eq!(a.len(), b.len());
+    let error = (a - b).amax();
+    let scale = a.amax().max(b.amax()).max(1.);
+    assert!(
+        error <= tolerance * scale,
+        "max difference {:e}\n{}\n{}",
+        error,
+        a.transpose(),
+        b.transpose()
+    );
+}
+
+fn assert_forward_dynamics_matches_articulated_body(startup_system: fn(Commands)) {
+    let mut app = evaluated_app(startup_system);
+    let joint_space = JointSpace::from_world(&mut app.world);
+    assert!(!joint_space.is_empty());
+
+    let qdd = joint_space
+        .forward_dynamics(&joint_space.joint_forces())
+        .expect("the mass matrix is positive definite");
+    assert_close(&qdd, &joint_space.accelerations(), 1e-8);
+
+    // and back, the inverse dynamics of the articulated-body accelerations are the joint forces
+    let tau = joint_space.inverse_dynamics(&joint_space.accelerations());
+    assert_close(&tau, &joint_space.joint_forces(), 1e-8);
+}
+
+fn assert_mass_matrix_symmetric_positive_definite(startup_system: fn(Commands)) {
+    let mut app = evaluated_app(startup_system);
+    let mass_matrix = JointSpace::from_world(&mut app.world).mass_matrix();
+    assert_eq!(mass_matrix.nrows(), mass_matrix.ncols());
+
+    let asymmetry = (&mass_matrix - mass_matrix.transpose()).amax();
+    assert!(
+        asymmetry <= 1e-12 * mass_matrix.amax(),
+        "asymmetry {:e}",
+        asymmetry
+    );
+
+    let eigenvalues = mass_matrix.symmetric_eigenvalues();
+    assert!(
+        eigenvalues.min() > 0.,
+        "eigenvalues {}",
+        eigenvalues.transpose()
+    );
+}
+
+#[test]
+fn double_pendulum_forward_dynamics() {
+    assert_forward_dynamics_matches_articulated_body(double_pendulum_startup_system);
+}
+
+#[test]
+fn floating_chassis_forward_dynamics() {
+    assert_forward_dynamics_matches_articulated_body(floating_chassis_startup_system);
+}
+
+#[test]
+fn double_pendulum_mass_matrix() {
+    assert_mass_matrix_symmetric_positive_definite(double_pendulum_startup_system);
+}
+
+#[test]
+fn floating_chassis_mass_matrix() {
+    assert_mass_matrix_symmetric_positive_definite(floating_chassis_startup_system);
+}
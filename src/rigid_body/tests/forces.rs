@@ -0,0 +1,142 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{step, PhysicsSchedule, Solver};
+use rigid_body::{
+    forces::{Bushing, ForceElementPlugin, PointSpringDamper, RotationalSpring},
+    joint::Joint,
+    sva::{Force, Inertia, Matrix, Motion, Vector, Xform},
+};
+
+mod common;
+use common::{base, headless_app_with_plugins, joint};
+
+// two free bodies, a and b, at the origin
+fn two_bodies_startup_system(mut commands: Commands) {
+    let base_id = base(&mut commands);
+    let inertia = Inertia::new(1., Vector::zeros(), Matrix::identity());
+    for name in ["a", "b"] {
+        let body = Joint::floating(name.to_string(), inertia, Xform::identity());
+        commands.spawn(body).set_parent(base_id);
+    }
+}
+
+// The external forces on a and b (in base coordinates) from an element between them, with b
+// moved to a position and roll, pitch, yaw, at a velocity
+fn element_forces<B: Bundle>(
+    position: [f64; 3],
+    euler_angles: [f64; 3],
+    velocity: Motion,
+    element: impl FnOnce(Entity, Entity) -> B,
+) -> (Force, Force) {
+    let mut app = headless_app_with_plugins(
+        0.001,
+        Solver::RK4,
+        ForceElementPlugin,
+        two_bodies_startup_system,
+    );
+    let mut query = app.world.query::<(Entity, &mut Joint)>();
+    let mut a = None;
+    let mut b = None;
+    for (entity, mut joint) in query.iter_mut(&mut app.world) {
+        match joint.name.as_str() {
+            "a" => a = Some(entity),
+            "b" => {
+                joint.floating.set_pose(position, euler_angles);
+                joint.floating.velocity = velocity;
+                b = Some(entity);
+            }
+            _ => {}
+        }
+    }
+    app.world.spawn(element(a.unwrap(), b.unwrap()));
+
+    step::<Joint>(&mut app.world, 0); // sets up the state of the joints
+    app.world.run_schedule(PhysicsSchedule);
+    (joint(&mut app, "a").f_ext, joint(&mut app, "b").f_ext)
+}
+
+fn assert_close(a: Vector, b: Vector) {
+    assert!(
+        (a - b).amax() < 1e-9,
+        "{} against {}",
+        a.transpose(),
+        b.transpose()
+    );
+}
+
+fn spring(a: Entity, b: Entity) -> PointSpringDamper {
+    let zero = Vector::zeros();
+    PointSpringDamper::new("spring".to_string(), (a, zero), (b, zero), 100., 10., 1.)
+}
+
+#[test]
+fn stretched_spring_pulls_the_bodies_together() {
+    let (a, b) = element_forces([2., 0., 0.], [0.; 3], Motion::zero(), spring);
+    assert_close(a.f, Vector::new(100., 0., 0.));
+    assert_close(b.f, Vector::new(-100., 0., 0.));
+}
+
+#[test]
+fn compressed_spring_pushes_the_bodies_apart() {
+    let (a, b) = element_forces([0.5, 0., 0.], [0.; 3], Motion::zero(), spring);
+    assert_close(a.f, Vector::new(-50., 0., 0.));
+    assert_close(b.f, Vector::new(50., 0., 0.));
+
+    // unless it is a rope
+    let rope = |a, b| {
+        let zero = Vector::zeros();
+        PointSpringDamper::rope("rope".to_string(), (a, zero), (b, zero), 100., 10., 1.)
+    };
+    let (a, b) = element_forces([0.5, 0., 0.], [0.; 3], Motion::zero(), rope);
+    assert_close(a.f, Vector::zeros());
+    assert_close(b.f, Vector::zeros());
+}
+
+#[test]
+fn damper_resists_the_bodies_moving_apart() {
+    // at the free length, b moving away from a at 1 m/s
+    let velocity = Motion::new([1., 0., 0.], [0., 0., 0.]);
+    let (a, b) = element_forces([1., 0., 0.], [0.; 3], velocity, spring);
+    assert_close(a.f, Vector::new(10., 0., 0.));
+    assert_close(b.f, Vector::new(-10., 0., 0.));
+}
+
+#[test]
+fn bushing_pulls_the_frames_back_together() {
+    let bushing = |a, b| {
+        Bushing::new(
+            "bushing".to_string(),
+            (a, Xform::identity()),
+            (b, Xform::identity()),
+            [10., 20., 30., 1000., 2000., 3000.],
+            [0.; 6],
+        )
+    };
+
+    // b moved along y: the force on a is towards b, and b is pulled back
+    let (a, b) = element_forces([0., 0.01, 0.], [0.; 3], Motion::zero(), bushing);
+    assert_close(a.f, Vector::new(0., 20., 0.));
+    assert_close(b.f, Vector::new(0., -20., 0.));
+
+    // b turned about z: the torque on b turns it back, and a gets the opposite
+    let (a, b) = element_forces([0.; 3], [0., 0., 0.01], Motion::zero(), bushing);
+    assert!(b.m.z < 0. && (b.m.z + 0.3).abs() < 1e-4, "{}", b.m.z);
+    assert_close(a.m, -b.m);
+    assert_close(a.f, Vector::zeros());
+}
+
+#[test]
+fn rotational_spring_turns_the_bodies_back() {
+    let torsion = |a, b| {
+        RotationalSpring::new(
+            "torsion".to_string(),
+            (a, Xform::identity()),
+            (b, Xform::identity()),
+            10.,
+            0.,
+        )
+    };
+    let (a, b) = element_forces([0.; 3], [0., 0., 0.1], Motion::zero(), torsion);
+    assert!(b.m.z < 0. && (b.m.z + 1.).abs() < 1e-3, "{}", b.m.z);
+    assert_close(a.m, -b.m);
+}
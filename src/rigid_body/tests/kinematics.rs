@@ -0,0 +1,90 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{step, PhysicsSchedule, Solver};
+use rigid_body::{
+    joint::Joint,
+    kinematics::{body_kinematics, PointKinematics},
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+mod common;
+use common::{base, double_pendulum_startup_system, headless_app};
+
+const H: f64 = 1e-4;
+
+// a free body tumbling and falling, with gravity as the only force
+fn free_body_startup_system(mut commands: Commands) {
+    let base_id = base(&mut commands);
+    let inertia = Inertia::new(
+        2.,
+        Vector::new(0.1, 0., -0.2),
+        Matrix::from_diagonal(&Vector::new(1., 2., 3.)),
+    );
+    let mut body = Joint::floating("body".to_string(), inertia, Xform::identity());
+    body.floating.set_pose([1., 2., 3.], [0.3, -0.2, 0.5]);
+    body.floating.velocity = Motion::new([0.5, -1., 2.], [1., 0.2, 1.5]);
+    commands.spawn(body).set_parent(base_id);
+}
+
+// the kinematics of a body-fixed point at the accepted state after the next step
+fn step_point(app: &mut App, entity: Entity, point: Vector) -> PointKinematics {
+    step::<Joint>(&mut app.world, 1);
+    app.world.run_schedule(PhysicsSchedule);
+    body_kinematics(&mut app.world, entity)
+        .unwrap()
+        .point(point)
+}
+
+fn assert_close(name: &str, a: Vector, b: Vector, tolerance: f64) {
+    let error = (a - b).amax();
+    assert!(
+        error <= tolerance * b.amax().max(1.),
+        "{}: {} against {}",
+        name,
+        a.transpose(),
+        b.transpose()
+    );
+}
+
+// the velocity and acceleration of the point against central differences of its position
+fn assert_point_matches_finite_differences(
+    startup_system: fn(Commands),
+    name: &str,
+    point: Vector,
+) {
+    let mut app = headless_app(H, Solver::RK4, startup_system);
+    let mut query = app.world.query::<(Entity, &Joint)>();
+    let (entity, _) = query
+        .iter(&app.world)
+        .find(|(_, joint)| joint.name == name)
+        .unwrap();
+
+    step::<Joint>(&mut app.world, 100);
+    let before = step_point(&mut app, entity, point);
+    let now = step_point(&mut app, entity, point);
+    let after = step_point(&mut app, entity, point);
+
+    let velocity = (after.position - before.position) / (2. * H);
+    let acceleration = (after.position - 2. * now.position + before.position) / (H * H);
+    assert_close("velocity", now.velocity, velocity, 1e-7);
+    assert_close("acceleration", now.acceleration, acceleration, 1e-5);
+}
+
+#[test]
+fn pendulum_tip_matches_finite_differences() {
+    assert_point_matches_finite_differences(
+        double_pendulum_startup_system,
+        "body_ry1",
+        Vector::new(0.1, 0.2, -1.),
+    );
+}
+
+#[test]
+fn free_body_point_matches_finite_differences() {
+    // falling, so the acceleration includes gravity, and not the upward acceleration of the Base
+    assert_point_matches_finite_differences(
+        free_body_startup_system,
+        "body",
+        Vector::new(0.5, -0.3, 0.8),
+    );
+}
@@ -0,0 +1,49 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{step, Solver};
+use rigid_body::{
+    joint::Joint,
+    limits::{JointLimit, JointLimitEvent, JointLimitPlugin, LimitSide},
+};
+
+mod common;
+use common::{headless_app_with_plugins, joint_state, pendulum};
+
+// a pendulum released from 0.5 rad, swinging into an elastic stop at -0.3 rad
+fn limited_pendulum_startup_system(mut commands: Commands) {
+    let pendulum_id = pendulum(&mut commands, 0.5);
+    commands
+        .entity(pendulum_id)
+        .insert(JointLimit::new(-0.3, 1., 1e4, 0., 1.));
+}
+
+#[test]
+fn pendulum_hits_its_lower_limit() {
+    let mut app = headless_app_with_plugins(
+        0.001,
+        Solver::RK4,
+        JointLimitPlugin,
+        limited_pendulum_startup_system,
+    );
+
+    let mut min_q = f64::INFINITY;
+    for _ in 0..1000 {
+        step::<Joint>(&mut app.world, 1);
+        min_q = min_q.min(joint_state(&mut app, "pendulum").q);
+    }
+    assert!(min_q < -0.3 && min_q > -0.33, "{}", min_q);
+
+    // one event for the swing into the stop, though the joint is in it for several steps
+    let events = app.world.resource::<Events<JointLimitEvent>>();
+    let events: Vec<JointLimitEvent> = events.get_reader().read(events).cloned().collect();
+    assert_eq!(events.len(), 1, "{:?}", events);
+    assert_eq!(events[0].joint_name, "pendulum");
+    assert_eq!(events[0].side, LimitSide::Lower);
+    assert!(events[0].qd < 0., "{:?}", events[0]);
+    // a quarter period to the bottom, and about arcsin(0.3 / 0.5) / omega_0 more to the stop
+    assert!(
+        events[0].time > 0.55 && events[0].time < 0.6,
+        "{:?}",
+        events[0]
+    );
+}
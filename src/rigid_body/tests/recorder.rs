@@ -0,0 +1,48 @@
+use bevy_integrator::{
+    recorder::{read_binary, Recorder},
+    step, Solver,
+};
+use rigid_body::joint::Joint;
+
+mod common;
+use common::{double_pendulum_startup_system, headless_app};
+
+#[test]
+fn recording_is_named_like_the_linearization() {
+    let path = std::env::temp_dir().join("rigid_body_recorder_test.bin");
+    let path = path.to_str().unwrap().to_string();
+    let mut app = headless_app(0.01, Solver::RK4, double_pendulum_startup_system);
+    app.insert_resource(Recorder::<Joint>::new(2, None, Some(path.clone())));
+    step::<Joint>(&mut app.world, 10);
+    app.world.resource_mut::<Recorder<Joint>>().flush();
+
+    let (columns, rows) = read_binary(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+    // the base joint has no name
+    let expected = [
+        "time",
+        ".q",
+        ".qd",
+        ".q_dot",
+        ".qd_dot",
+        "body_ry0.q",
+        "body_ry0.qd",
+        "body_ry0.q_dot",
+        "body_ry0.qd_dot",
+        "body_ry1.q",
+        "body_ry1.qd",
+        "body_ry1.q_dot",
+        "body_ry1.qd_dot",
+    ];
+    assert_eq!(columns, expected);
+
+    // every second step, in one file, with the derivative of q being qd
+    assert_eq!(rows.len(), 5);
+    for (row, next) in rows.iter().zip(rows.iter().skip(1)) {
+        assert!((next[0] - row[0] - 0.02).abs() < 1e-12);
+    }
+    for row in rows.iter() {
+        assert_eq!(row[6], row[7]);
+        assert_eq!(row[10], row[11]);
+    }
+}
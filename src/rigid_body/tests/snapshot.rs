@@ -0,0 +1,36 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{
+    snapshot::{restore_snapshot, take_snapshot},
+    step, SimTime, Solver,
+};
+use rigid_body::joint::Joint;
+
+mod common;
+use common::{double_pendulum_startup_system, headless_app, joint_state};
+
+fn pendulum_state(app: &mut App) -> [f64; 4] {
+    let ry0 = joint_state(app, "body_ry0");
+    let ry1 = joint_state(app, "body_ry1");
+    [ry0.q, ry0.qd, ry1.q, ry1.qd]
+}
+
+#[test]
+fn restored_snapshot_continues_the_same_way() {
+    let mut app = headless_app(0.01, Solver::RK4, double_pendulum_startup_system);
+    step::<Joint>(&mut app.world, 10);
+    let state = pendulum_state(&mut app);
+    let snapshot = take_snapshot::<Joint>(&mut app.world).unwrap();
+
+    step::<Joint>(&mut app.world, 20);
+    let later = pendulum_state(&mut app);
+    assert_ne!(later, state);
+
+    restore_snapshot(&mut app.world, &snapshot);
+    assert_eq!(app.world.resource::<SimTime>().index, 10);
+    assert_eq!(pendulum_state(&mut app), state);
+
+    // the same steps from the same state
+    step::<Joint>(&mut app.world, 20);
+    assert_eq!(pendulum_state(&mut app), later);
+}
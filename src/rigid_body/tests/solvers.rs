@@ -0,0 +1,94 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{step, AdaptiveStep, Solver};
+use rigid_body::{
+    joint::{Floating, Joint},
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+mod common;
+use common::{base, double_pendulum_startup_system, headless_app, joint};
+
+// the double pendulum after a second, with RK4 at a small time step as the reference
+fn reference() -> [f64; 4] {
+    let mut app = headless_app(0.0002, Solver::RK4, double_pendulum_startup_system);
+    step::<Joint>(&mut app.world, 5000);
+    pendulum_state(&mut app)
+}
+
+fn pendulum_state(app: &mut App) -> [f64; 4] {
+    let (q0, qd0) = {
+        let joint = joint(app, "body_ry0");
+        (joint.q, joint.qd)
+    };
+    let joint = joint(app, "body_ry1");
+    [q0, qd0, joint.q, joint.qd]
+}
+
+fn assert_close(a: [f64; 4], b: [f64; 4], tolerance: f64) {
+    for (a, b) in a.iter().zip(b.iter()) {
+        assert!((a - b).abs() <= tolerance, "{:?} and {:?}", a, b);
+    }
+}
+
+#[test]
+fn dormand_prince_substeps_within_tolerance() {
+    // a fixed step much longer than the pendulum allows, split into adaptive substeps
+    let mut app = headless_app(
+        0.05,
+        Solver::DormandPrince45,
+        double_pendulum_startup_system,
+    );
+    app.insert_resource(AdaptiveStep::new(1e-8, 1e-8, 1e-6, 0.05));
+    step::<Joint>(&mut app.world, 20);
+
+    let adaptive_step = app.world.resource::<AdaptiveStep>();
+    assert!(adaptive_step.accepted > 20, "{:?}", adaptive_step);
+    assert_close(pendulum_state(&mut app), reference(), 1e-5);
+}
+
+#[test]
+fn fixed_step_solvers_converge() {
+    let reference = reference();
+    for (solver, tolerance) in [(Solver::RK4, 1e-5), (Solver::Rosenbrock2, 1e-2)] {
+        let mut app = headless_app(0.001, solver, double_pendulum_startup_system);
+        step::<Joint>(&mut app.world, 1000);
+        assert_close(pendulum_state(&mut app), reference, tolerance);
+    }
+}
+
+// a free body tumbling about all three axes, without a symmetry
+fn tumbling_body_startup_system(mut commands: Commands) {
+    let base_id = base(&mut commands);
+    let inertia = Inertia::new(
+        1.,
+        Vector::zeros(),
+        Matrix::from_diagonal(&Vector::new(1., 2., 3.)),
+    );
+    let mut body = Joint::floating("body".to_string(), inertia, Xform::identity());
+    body.floating.set_pose([0., 0., 0.], [0.3, -0.2, 0.5]);
+    body.floating.velocity = Motion::new([0.5, 0., 0.], [1., 0.2, 1.5]);
+    commands.spawn(body).set_parent(base_id);
+}
+
+fn tumbling_body(dt: f64, solver: Solver, steps: usize) -> Floating {
+    let mut app = headless_app(dt, solver, tumbling_body_startup_system);
+    step::<Joint>(&mut app.world, steps);
+    joint(&mut app, "body").floating
+}
+
+#[test]
+fn implicit_solvers_follow_a_tumbling_body() {
+    // the Jacobian and the Newton updates are in the tangent coordinates of the orientation
+    let reference = tumbling_body(0.0001, Solver::RK4, 10000);
+    for (name, solver, tolerance) in [
+        ("Rosenbrock2", Solver::Rosenbrock2, 1e-5),
+        ("BackwardEuler", Solver::BackwardEuler, 1e-2),
+    ] {
+        let body = tumbling_body(0.001, solver, 1000);
+        let angle = body.orientation.angle_to(&reference.orientation);
+        let spin = (body.velocity.w - reference.velocity.w).amax();
+        assert!(angle <= tolerance, "{}: {:e} rad", name, angle);
+        assert!(spin <= tolerance, "{}: {:e} rad/s", name, spin);
+    }
+}
@@ -0,0 +1,42 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{SolverState, StateMap};
+use rigid_body::joint::{Joint, JointState};
+
+fn state_map(states: &[(Entity, f64, f64)]) -> StateMap<Joint> {
+    let mut map = StateMap::new();
+    for (entity, q, qd) in states.iter() {
+        map.insert(*entity, JointState::new(*q, *qd));
+    }
+    map
+}
+
+fn q_qd(map: &StateMap<Joint>, entity: Entity) -> (f64, f64) {
+    let state = map.get(&entity).unwrap();
+    (state.q, state.qd)
+}
+
+#[test]
+fn axpy_with_the_same_layout() {
+    let [a, b] = [Entity::from_raw(1), Entity::from_raw(2)];
+    let mut y = state_map(&[(a, 1., 2.), (b, 3., 4.)]);
+    let x = y.clone();
+    y.axpy(0.5, &x);
+    assert_eq!(q_qd(&y, a), (1.5, 3.));
+    assert_eq!(q_qd(&y, b), (4.5, 6.));
+}
+
+#[test]
+fn axpy_with_a_different_layout() {
+    // x has the entities in another order, one entity less and one more
+    let [a, b, c, d] = [1, 2, 3, 4].map(Entity::from_raw);
+    let mut y = state_map(&[(a, 1., 2.), (b, 3., 4.), (c, 5., 6.)]);
+    let x = state_map(&[(d, 100., 100.), (c, 10., 20.), (a, 30., 40.)]);
+    y.axpy(2., &x);
+
+    assert_eq!(q_qd(&y, a), (61., 82.));
+    assert_eq!(q_qd(&y, b), (3., 4.)); // missing from x, unchanged
+    assert_eq!(q_qd(&y, c), (25., 46.));
+    assert!(y.get(&d).is_none());
+    assert_eq!(y.entities(), &[a, b, c]);
+}
@@ -0,0 +1,198 @@
+use bevy::prelude::*;
+
+use bevy_integrator::Solver;
+use rigid_body::{
+    definitions::{MeshDef, MeshTypeDef},
+    joint::{Joint, JointType},
+    sva::{Inertia, InertiaAB, Matrix, Vector, Xform},
+    urdf::Urdf,
+};
+
+mod common;
+use common::{base, headless_app, joint};
+
+// An arm with a fixed camera and a fixed tip on the upper arm, and a fixed mount below the root
+const ARM: &str = r#"
+<robot name="arm">
+  <material name="red">
+    <color rgba="1 0 0 1"/>
+  </material>
+  <link name="mount">
+    <visual>
+      <geometry><box size="0.2 0.2 0.05"/></geometry>
+    </visual>
+  </link>
+  <link name="plate">
+    <visual>
+      <geometry><box size="0.4 0.4 0.01"/></geometry>
+    </visual>
+  </link>
+  <link name="upper_arm">
+    <inertial>
+      <origin xyz="0 0 -0.5"/>
+      <mass value="1"/>
+      <inertia ixx="0.0835" ixy="0" ixz="0" iyy="0.0835" iyz="0" izz="0.0005"/>
+    </inertial>
+    <visual>
+      <origin xyz="0 0 -0.5"/>
+      <geometry><cylinder radius="0.025" length="1"/></geometry>
+      <material name="red"/>
+    </visual>
+  </link>
+  <link name="camera">
+    <inertial>
+      <mass value="0.2"/>
+      <inertia ixx="0.001" ixy="0" ixz="0" iyy="0.001" iyz="0" izz="0.001"/>
+    </inertial>
+    <visual>
+      <geometry><box size="0.05 0.05 0.05"/></geometry>
+    </visual>
+  </link>
+  <link name="tip">
+    <inertial>
+      <mass value="0.5"/>
+      <inertia ixx="0.001" ixy="0" ixz="0" iyy="0.001" iyz="0" izz="0.001"/>
+    </inertial>
+    <visual>
+      <geometry><cylinder radius="0.05" length="0.1"/></geometry>
+    </visual>
+  </link>
+  <joint name="plate_mount" type="fixed">
+    <parent link="mount"/>
+    <child link="plate"/>
+    <origin xyz="0 0 -0.05"/>
+  </joint>
+  <joint name="shoulder" type="continuous">
+    <parent link="mount"/>
+    <child link="upper_arm"/>
+    <axis xyz="0 1 0"/>
+  </joint>
+  <joint name="camera_mount" type="fixed">
+    <parent link="upper_arm"/>
+    <child link="camera"/>
+    <origin xyz="0.1 0 -0.2"/>
+  </joint>
+  <joint name="tip_mass" type="fixed">
+    <parent link="upper_arm"/>
+    <child link="tip"/>
+    <origin xyz="0 0 -1"/>
+  </joint>
+</robot>
+"#;
+
+fn arm_startup_system(mut commands: Commands) {
+    let base_id = base(&mut commands);
+    Urdf::parse(ARM)
+        .unwrap()
+        .spawn(&mut commands, base_id, Xform::identity(), false)
+        .unwrap();
+}
+
+#[test]
+fn parse_links_and_joints() {
+    let urdf = Urdf::parse(ARM).unwrap();
+    assert_eq!(urdf.name, "arm");
+    assert_eq!(urdf.root().unwrap().name, "mount");
+    assert_eq!(urdf.links.len(), 5);
+
+    let shoulder = urdf.joints.iter().find(|j| j.name == "shoulder").unwrap();
+    assert!(matches!(shoulder.joint_type, Some(JointType::Revolute(_))));
+    let fixed = urdf.joints.iter().filter(|j| j.joint_type.is_none());
+    assert_eq!(fixed.count(), 3);
+
+    let red = urdf.link("upper_arm").unwrap().visual.as_ref().unwrap();
+    assert_eq!(red.color, Color::rgba(1., 0., 0., 1.));
+}
+
+#[test]
+fn invalid_trees_are_errors() {
+    // a joint to a link that doesn't exist
+    assert!(
+        Urdf::parse(&ARM.replace(r#"<child link="tip"/>"#, r#"<child link="hand"/>"#)).is_err()
+    );
+    // the camera with two parents
+    assert!(
+        Urdf::parse(&ARM.replace(r#"<child link="tip"/>"#, r#"<child link="camera"/>"#)).is_err()
+    );
+    assert!(Urdf::parse("<link name=\"mount\"/>").is_err());
+}
+
+#[test]
+fn fixed_links_are_merged_into_their_parent_body() {
+    let mut app = headless_app(0.002, Solver::RK4, arm_startup_system);
+
+    // one joint besides the base, carrying the arm, the camera and the tip
+    let mut query = app.world.query::<&Joint>();
+    assert_eq!(query.iter(&app.world).count(), 2);
+    let shoulder = joint(&mut app, "shoulder");
+    assert!((shoulder.i.mass() - 1.7).abs() < 1e-12);
+    assert_eq!(shoulder.s.w, Vector::y());
+
+    // the arm, the camera and the tip with their centers of mass in the shoulder body
+    let parts = [
+        (
+            1.,
+            Vector::new(0., 0., -0.5),
+            Vector::new(0.0835, 0.0835, 0.0005),
+        ),
+        (
+            0.2,
+            Vector::new(0.1, 0., -0.2),
+            Vector::new(0.001, 0.001, 0.001),
+        ),
+        (
+            0.5,
+            Vector::new(0., 0., -1.),
+            Vector::new(0.001, 0.001, 0.001),
+        ),
+    ];
+    let center = Vector::new(0.02, 0., -1.04) / 1.7;
+    let mut moi = Matrix::zeros();
+    for (mass, position, diagonal) in parts {
+        // parallel axis theorem, about the merged center of mass
+        let d = position - center;
+        moi += Matrix::from_diagonal(&diagonal)
+            + mass * (d.norm_squared() * Matrix::identity() - d * d.transpose());
+    }
+    let expected = InertiaAB::from(Inertia::new(1.7, center, moi)).to_mat();
+    let merged = InertiaAB::from(shoulder.i).to_mat();
+    assert!((merged - expected).amax() < 1e-12, "{}", merged);
+}
+
+#[test]
+fn merged_links_keep_their_visuals() {
+    let mut app = headless_app(0.002, Solver::RK4, arm_startup_system);
+
+    // the arm on the shoulder joint, the camera and the tip on its children
+    let shoulder = app
+        .world
+        .query::<(Entity, &Joint)>()
+        .iter(&app.world)
+        .find(|(_, joint)| joint.name == "shoulder")
+        .unwrap()
+        .0;
+    let mut visuals = app.world.query::<(Entity, &MeshDef, Option<&Parent>)>();
+    let on_shoulder: Vec<&MeshTypeDef> = visuals
+        .iter(&app.world)
+        .filter(|(entity, _, parent)| {
+            *entity == shoulder || parent.is_some_and(|parent| parent.get() == shoulder)
+        })
+        .map(|(_, visual, _)| &visual.mesh_type)
+        .collect();
+    assert_eq!(on_shoulder.len(), 3, "{:?}", on_shoulder);
+
+    // the camera box at its fixed joint, in the shoulder body
+    let camera = visuals
+        .iter(&app.world)
+        .find(|(_, visual, _)| {
+            matches!(visual.mesh_type, MeshTypeDef::Box { dimensions } if dimensions[0] == 0.05)
+        })
+        .unwrap()
+        .1;
+    let x = Xform::from(&camera.transform);
+    assert!((x.position - Vector::new(0.1, 0., -0.2)).amax() < 1e-12);
+    assert!((x.rotation - Matrix::identity()).amax() < 1e-12);
+
+    // and the mount and plate on the base
+    assert_eq!(visuals.iter(&app.world).count(), 5);
+}
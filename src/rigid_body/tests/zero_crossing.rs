@@ -0,0 +1,94 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{
+    implicit::ImplicitStep,
+    step,
+    zero_crossing::{
+        CrossingDirection, CrossingMethod, ZeroCrossingEvent, ZeroCrossingPlugin, ZeroCrossings,
+    },
+    Solver,
+};
+use rigid_body::joint::Joint;
+
+mod common;
+use common::{headless_app_with_plugins, pendulum};
+
+const Q_0: f64 = 0.5;
+
+// a pendulum released from rest at Q_0
+fn pendulum_startup_system(mut commands: Commands) {
+    pendulum(&mut commands, Q_0);
+}
+
+// the time the pendulum first swings through the bottom, a quarter of the period for the
+// amplitude Q_0: K(sin(Q_0 / 2)) / omega_0, with the complete elliptic integral K
+fn quarter_period() -> f64 {
+    let (m, l, g): (f64, f64, f64) = (1., 0.5, 9.81);
+    let inertia = 0.08 + m * l * l; // about the pivot
+    let omega_0 = (m * g * l / inertia).sqrt();
+
+    let k = (Q_0 / 2.).sin();
+    let (mut a, mut b) = (1., (1. - k * k).sqrt());
+    for _ in 0..10 {
+        (a, b) = (0.5 * (a + b), (a * b).sqrt());
+    }
+    std::f64::consts::PI / (2. * a) / omega_0
+}
+
+// the crossing times of the pendulum through the bottom, and the implicit solver settings
+fn crossings(solver: Solver, method: CrossingMethod) -> (Vec<f64>, ImplicitStep) {
+    let mut app = headless_app_with_plugins(
+        0.01,
+        solver,
+        ZeroCrossingPlugin::<Joint>::new(method, false),
+        pendulum_startup_system,
+    );
+    app.insert_resource(ImplicitStep::default());
+
+    let mut query = app.world.query::<(Entity, &Joint)>();
+    let (entity, _) = query
+        .iter(&app.world)
+        .find(|(_, joint)| joint.name == "pendulum")
+        .unwrap();
+    app.world.resource_mut::<ZeroCrossings<Joint>>().register(
+        "bottom",
+        CrossingDirection::Falling,
+        move |states| states.get(&entity).map_or(0., |state| state.q),
+    );
+
+    step::<Joint>(&mut app.world, 50);
+    let events = app.world.resource::<Events<ZeroCrossingEvent>>();
+    let times = events
+        .get_reader()
+        .read(events)
+        .map(|event| event.time)
+        .collect();
+    (times, app.world.resource::<ImplicitStep>().clone())
+}
+
+#[test]
+fn pendulum_crosses_the_bottom_at_a_quarter_period() {
+    let bisection = CrossingMethod::Bisection {
+        tolerance: 1e-7,
+        max_iterations: 40,
+    };
+    for (method, tolerance) in [(CrossingMethod::Interpolation, 1e-3), (bisection, 1e-6)] {
+        let (times, _) = crossings(Solver::RK4, method);
+        assert_eq!(times.len(), 1);
+        let error = times[0] - quarter_period();
+        assert!(error.abs() <= tolerance, "{:?}: {:e} s", method, error);
+    }
+}
+
+#[test]
+fn bisection_keeps_the_implicit_jacobian() {
+    // the bisection repeats the step, which must not age or rebuild the Jacobian of the solver
+    let bisection = CrossingMethod::Bisection {
+        tolerance: 1e-7,
+        max_iterations: 40,
+    };
+    let (_, interpolated) = crossings(Solver::Rosenbrock2, CrossingMethod::Interpolation);
+    let (times, bisected) = crossings(Solver::Rosenbrock2, bisection);
+    assert_eq!(times.len(), 1);
+    assert_eq!(bisected.jacobian_updates, interpolated.jacobian_updates);
+}